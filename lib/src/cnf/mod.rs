@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 #[cfg(feature = "parallel")]
 // Specifies how many concurrent jobs can be buffered in the worker channel.
 pub const MAX_CONCURRENT_TASKS: usize = 64;
@@ -5,6 +7,33 @@ pub const MAX_CONCURRENT_TASKS: usize = 64;
 // Specifies how deep various forms of computation will go before the query fails.
 pub const MAX_COMPUTATION_DEPTH: u8 = 30;
 
+// Specifies how deeply braces and template literals can be nested in an embedded script.
+pub const MAX_SCRIPT_DEPTH: usize = 256;
+
+// Specifies the maximum length in bytes of an embedded script.
+pub const MAX_SCRIPT_LENGTH: usize = 1_048_576;
+
+// Specifies how long an embedded script can run before it is interrupted.
+pub const SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Specifies how many bytes of memory an embedded script can allocate.
+pub const SCRIPT_MEMORY_LIMIT: usize = 2_000_000;
+
+// Specifies how many bytes of stack an embedded script can use.
+pub const SCRIPT_STACK_SIZE: usize = 262_144;
+
+// Specifies how many values the result of an embedded script can hold.
+pub const SCRIPT_RESULT_LIMIT: usize = 1_000_000;
+
+// Specifies how many bytes of console output are kept for each statement.
+pub const SCRIPT_CONSOLE_LIMIT: usize = 65_536;
+
+// Specifies how many compiled embedded scripts are cached by a datastore.
+pub const SCRIPT_CACHE_SIZE: usize = 1_000;
+
+// Specifies how many embedded script runtimes are created ahead of time.
+pub const SCRIPT_POOL_SIZE: usize = 16;
+
 // The characters which are supported in server record IDs.
 pub const ID_CHARS: [char; 36] = [
 	'0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i',
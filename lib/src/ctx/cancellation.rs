@@ -21,4 +21,8 @@ impl Cancellation {
 		self.deadline.map(|d| d <= Instant::now()).unwrap_or(false)
 			|| self.cancellations.iter().any(|c| c.load(Ordering::Relaxed))
 	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancellations.iter().any(|c| c.load(Ordering::Relaxed))
+	}
 }
@@ -1,21 +1,31 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-#[derive(Default)]
+/// Cancels the contexts which it is tied to, such as those of a query
+/// which is executed with [`Datastore::execute_cancellable`], stopping any
+/// embedded function which is running in them. It can be cloned, and
+/// cancelled from another thread.
+///
+/// [`Datastore::execute_cancellable`]: crate::Datastore::execute_cancellable
+#[derive(Clone, Debug, Default)]
 pub struct Canceller {
 	/// A reference to the canceled value of a context.
-	cancelled: Arc<AtomicBool>,
+	pub(crate) cancelled: Arc<AtomicBool>,
 }
 
 impl Canceller {
-	// Create a new Canceller
+	/// Create a new Canceller
 	pub fn new(cancelled: Arc<AtomicBool>) -> Canceller {
 		Canceller {
 			cancelled,
 		}
 	}
-	// Cancel the context.
+	/// Cancel the context.
 	pub fn cancel(&self) {
 		self.cancelled.store(true, Ordering::Relaxed);
 	}
+	/// Check whether the context has been cancelled.
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+	}
 }
@@ -76,6 +76,12 @@ impl<'a> Context<'a> {
 		)
 	}
 
+	// Tie the context to a canceller which already exists, so that the
+	// context and its children are cancelled once it is called.
+	pub fn add_canceller(&mut self, canceller: &Canceller) {
+		self.cancelled = canceller.cancelled.clone();
+	}
+
 	// Add a deadline to the context. If the current deadline is sooner than
 	// the provided deadline, this method does nothing.
 	pub fn add_deadline(&mut self, deadline: Instant) {
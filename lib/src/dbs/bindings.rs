@@ -0,0 +1,80 @@
+use crate::err::Error;
+use crate::sql::object::Object;
+use crate::sql::script::is_identifier;
+use crate::sql::value::Value;
+use std::collections::btree_map::Iter;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Specifies the values which are bound as globals in embedded functions.
+///
+/// Each value is computed before the function runs, so a [`Value::Param`]
+/// binds the current value of that parameter.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Bindings(BTreeMap<String, Value>, BTreeSet<String>);
+
+impl Bindings {
+	/// Create an empty set of bindings
+	pub fn new() -> Bindings {
+		Bindings::default()
+	}
+	/// Bind a value to a name, which must be a valid JavaScript identifier
+	pub fn bind<K, V>(mut self, name: K, value: V) -> Result<Bindings, Error>
+	where
+		K: Into<String>,
+		V: Into<Value>,
+	{
+		let name = name.into();
+		if !is_identifier(&name) {
+			return Err(Error::InvalidBinding {
+				name,
+			});
+		}
+		self.1.remove(&name);
+		self.0.insert(name, value.into());
+		Ok(self)
+	}
+	/// Bind a single object to a name, with a field for each of the values,
+	/// so that a function reads them as `ctx.user`, rather than as separate
+	/// globals. If it is frozen, the object and everything within it is
+	/// deeply frozen, whether or not the other inputs are.
+	///
+	/// ```rust
+	/// # use surrealdb::Bindings;
+	/// # use std::collections::BTreeMap;
+	/// let values = BTreeMap::from([(String::from("user"), "tobie".into())]);
+	/// let bindings = Bindings::new().bind_context("ctx", values, true).unwrap();
+	/// assert!(bindings.is_frozen("ctx"));
+	/// ```
+	pub fn bind_context<K>(
+		self,
+		name: K,
+		values: BTreeMap<String, Value>,
+		frozen: bool,
+	) -> Result<Bindings, Error>
+	where
+		K: Into<String>,
+	{
+		let name = name.into();
+		let mut out = self.bind(name.clone(), Object::from(values))?;
+		if frozen {
+			out.1.insert(name);
+		}
+		Ok(out)
+	}
+	/// Check whether the value bound to a name is always frozen
+	pub fn is_frozen(&self, name: &str) -> bool {
+		self.1.contains(name)
+	}
+	/// Get the number of bindings
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+	/// Check if there are no bindings
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+	/// Iterate over the bound names and values
+	pub fn iter(&self) -> Iter<'_, String, Value> {
+		self.0.iter()
+	}
+}
@@ -0,0 +1,206 @@
+use crate::sql::script::Script;
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// A least-recently-used cache of compiled embedded function bytecode,
+// keyed by the script body. A cache is limited either by how many
+// scripts it holds, or by how many bytes of bytecode it holds. A cache
+// with a capacity of zero stores nothing, so every function is compiled
+// when it is run.
+#[derive(Debug, Default)]
+pub struct ScriptCache {
+	capacity: usize,
+	budget: Option<usize>,
+	inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+	// The last time any entry was used
+	tick: u64,
+	// The number of lookups which found an entry
+	hits: u64,
+	// The number of lookups which did not find an entry
+	misses: u64,
+	// The total length of the bytecode in the cache
+	bytes: usize,
+	// The bytecode, and when it was last used, for each script
+	items: HashMap<Script, (u64, Arc<[u8]>)>,
+	// The scripts in the cache, by when they were last used
+	order: BTreeMap<u64, Script>,
+}
+
+impl PartialEq for ScriptCache {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+impl Eq for ScriptCache {}
+
+impl ScriptCache {
+	pub fn new(capacity: usize) -> ScriptCache {
+		ScriptCache {
+			capacity,
+			budget: None,
+			inner: Mutex::default(),
+		}
+	}
+	// Create a cache which holds at most the given number of bytes of
+	// bytecode, however many scripts that is. A script whose bytecode is
+	// longer than the budget is not stored.
+	pub fn with_budget(budget: usize) -> ScriptCache {
+		ScriptCache {
+			capacity: usize::MAX,
+			budget: Some(budget),
+			inner: Mutex::default(),
+		}
+	}
+	// Fetch the bytecode for a script, marking it as recently used
+	pub fn get<Q>(&self, key: &Q) -> Option<Arc<[u8]>>
+	where
+		Script: Borrow<Q>,
+		Q: Hash + Eq + ?Sized,
+	{
+		let mut v = self.inner.lock().unwrap();
+		v.tick += 1;
+		let tick = v.tick;
+		match v.items.get_mut(key) {
+			Some((used, code)) => {
+				let last = std::mem::replace(used, tick);
+				let code = code.clone();
+				if let Some(key) = v.order.remove(&last) {
+					v.order.insert(tick, key);
+				}
+				v.hits += 1;
+				Some(code)
+			}
+			None => {
+				v.misses += 1;
+				None
+			}
+		}
+	}
+	// Store the bytecode for a script, evicting the least recently used
+	pub fn set(&self, key: Script, code: Arc<[u8]>) {
+		if self.capacity == 0 || matches!(self.budget, Some(b) if code.len() > b) {
+			return;
+		}
+		let mut v = self.inner.lock().unwrap();
+		v.tick += 1;
+		let tick = v.tick;
+		v.bytes += code.len();
+		if let Some((last, code)) = v.items.insert(key.clone(), (tick, code)) {
+			v.bytes -= code.len();
+			v.order.remove(&last);
+		}
+		v.order.insert(tick, key);
+		while v.items.len() > self.capacity || matches!(self.budget, Some(b) if v.bytes > b) {
+			match v.order.keys().next().copied() {
+				Some(last) => {
+					if let Some(key) = v.order.remove(&last) {
+						if let Some((_, code)) = v.items.remove(&key) {
+							v.bytes -= code.len();
+						}
+					}
+				}
+				None => break,
+			}
+		}
+	}
+	// The number of scripts in the cache
+	pub fn len(&self) -> usize {
+		self.inner.lock().unwrap().items.len()
+	}
+	// The total length of the bytecode in the cache
+	pub fn bytes(&self) -> usize {
+		self.inner.lock().unwrap().bytes
+	}
+	// The number of lookups which found an entry
+	pub fn hits(&self) -> u64 {
+		self.inner.lock().unwrap().hits
+	}
+	// The number of lookups which did not find an entry
+	pub fn misses(&self) -> u64 {
+		self.inner.lock().unwrap().misses
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn code(v: u8) -> Arc<[u8]> {
+		Arc::from(vec![v])
+	}
+
+	#[test]
+	fn cache_hit() {
+		let cache = ScriptCache::new(2);
+		let key = Script::from("return 1;");
+		assert_eq!(cache.get(&key), None);
+		cache.set(key.clone(), code(1));
+		assert_eq!(cache.get(&key), Some(code(1)));
+		assert_eq!(cache.hits(), 1);
+		assert_eq!(cache.misses(), 1);
+	}
+
+	#[test]
+	fn cache_distinct() {
+		let cache = ScriptCache::new(2);
+		cache.set(Script::from("return 1;"), code(1));
+		cache.set(Script::from("return 2;"), code(2));
+		assert_eq!(cache.get(&Script::from("return 1;")), Some(code(1)));
+		assert_eq!(cache.get(&Script::from("return 2;")), Some(code(2)));
+		assert_eq!(cache.get(&Script::from("return 3;")), None);
+		assert_eq!(cache.get("return 1;"), Some(code(1)));
+		assert_eq!(cache.get("return 3;"), None);
+	}
+
+	#[test]
+	fn cache_evicts_least_recently_used() {
+		let cache = ScriptCache::new(2);
+		cache.set(Script::from("return 1;"), code(1));
+		cache.set(Script::from("return 2;"), code(2));
+		cache.get(&Script::from("return 1;"));
+		cache.set(Script::from("return 3;"), code(3));
+		assert_eq!(cache.len(), 2);
+		assert_eq!(cache.get(&Script::from("return 2;")), None);
+		assert_eq!(cache.get(&Script::from("return 1;")), Some(code(1)));
+		assert_eq!(cache.get(&Script::from("return 3;")), Some(code(3)));
+	}
+
+	#[test]
+	fn cache_evicts_over_budget() {
+		let cache = ScriptCache::with_budget(10);
+		cache.set(Script::from("return 1;"), Arc::from(vec![1; 4]));
+		cache.set(Script::from("return 2;"), Arc::from(vec![2; 4]));
+		assert_eq!(cache.bytes(), 8);
+		cache.get(&Script::from("return 1;"));
+		cache.set(Script::from("return 3;"), Arc::from(vec![3; 4]));
+		assert_eq!(cache.len(), 2);
+		assert_eq!(cache.bytes(), 8);
+		assert_eq!(cache.get(&Script::from("return 2;")), None);
+		assert!(cache.get(&Script::from("return 1;")).is_some());
+		assert!(cache.get(&Script::from("return 3;")).is_some());
+		cache.set(Script::from("return 1;"), Arc::from(vec![1; 7]));
+		assert_eq!(cache.len(), 1);
+		assert_eq!(cache.bytes(), 7);
+		cache.set(Script::from("return 4;"), Arc::from(vec![4; 11]));
+		assert_eq!(cache.bytes(), 7);
+		assert_eq!(cache.get(&Script::from("return 4;")), None);
+	}
+
+	#[test]
+	fn cache_disabled() {
+		let cache = ScriptCache::new(0);
+		cache.set(Script::from("return 1;"), code(1));
+		assert_eq!(cache.len(), 0);
+		assert_eq!(cache.get(&Script::from("return 1;")), None);
+	}
+}
@@ -0,0 +1,82 @@
+use crate::cnf;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Mutex;
+
+/// The level of a message logged by an embedded function.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+	Log,
+	Warn,
+	Error,
+}
+
+impl fmt::Display for LogLevel {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			LogLevel::Log => write!(f, "log"),
+			LogLevel::Warn => write!(f, "warn"),
+			LogLevel::Error => write!(f, "error"),
+		}
+	}
+}
+
+/// A message logged by an embedded function using `console`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct LogEntry {
+	pub level: LogLevel,
+	pub message: String,
+}
+
+// Collects the messages logged by embedded functions while a statement
+// is processed. Once the size limit is reached, the last message is
+// truncated and any further messages are dropped.
+#[derive(Debug, Default)]
+pub struct Console {
+	inner: Mutex<Logs>,
+}
+
+#[derive(Debug, Default)]
+struct Logs {
+	size: usize,
+	entries: Vec<LogEntry>,
+}
+
+impl PartialEq for Console {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+impl Eq for Console {}
+
+impl Console {
+	// Record a logged message, if there is space left
+	pub fn push(&self, level: LogLevel, mut message: String) {
+		let mut logs = self.inner.lock().unwrap();
+		if logs.size >= cnf::SCRIPT_CONSOLE_LIMIT {
+			return;
+		}
+		// Each entry takes up at least one byte
+		let left = cnf::SCRIPT_CONSOLE_LIMIT - logs.size - 1;
+		if message.len() > left {
+			let mut end = left;
+			while !message.is_char_boundary(end) {
+				end -= 1;
+			}
+			message.truncate(end);
+		}
+		logs.size += message.len() + 1;
+		logs.entries.push(LogEntry {
+			level,
+			message,
+		});
+	}
+	// Take the messages which have been logged so far
+	pub fn take(&self) -> Vec<LogEntry> {
+		let mut logs = self.inner.lock().unwrap();
+		logs.size = 0;
+		std::mem::take(&mut logs.entries)
+	}
+}
@@ -0,0 +1,59 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/// The lines of an embedded function which ran, as recorded when the
+/// coverage of embedded functions is recorded. Lines are numbered from 1.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ScriptCoverage {
+	/// The byte offset of the function body within the query, if the
+	/// function was parsed from a query
+	pub offset: Option<usize>,
+	/// The lines of the function body on which a statement begins, and
+	/// whose running could be recorded
+	pub lines: BTreeSet<u32>,
+	/// The lines of the function body which ran
+	pub executed: BTreeSet<u32>,
+}
+
+impl ScriptCoverage {
+	/// Returns the lines of the query which ran, given the query which
+	/// the function was parsed from, counting line breaks as JavaScript
+	/// does. Nothing is returned if the function was not parsed from a
+	/// query.
+	pub fn query_lines(&self, sql: &str) -> BTreeSet<u32> {
+		let offset = match self.offset {
+			Some(v) if sql.is_char_boundary(v) => v,
+			_ => return BTreeSet::new(),
+		};
+		let before = &sql[..offset];
+		let first = before.matches('\n').count() + before.replace("\r\n", "").matches('\r').count();
+		self.executed.iter().map(|v| v + first as u32).collect()
+	}
+}
+
+// Collects the coverage of each embedded function which runs while a
+// statement is processed, in the order in which they ran
+#[derive(Debug, Default)]
+pub struct Coverage {
+	inner: Mutex<Vec<ScriptCoverage>>,
+}
+
+impl PartialEq for Coverage {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+impl Eq for Coverage {}
+
+impl Coverage {
+	// Record the coverage of an embedded function which ran
+	pub fn push(&self, v: ScriptCoverage) {
+		self.inner.lock().unwrap().push(v);
+	}
+	// Take the coverage which has been recorded so far
+	pub fn take(&self) -> Vec<ScriptCoverage> {
+		std::mem::take(&mut self.inner.lock().unwrap())
+	}
+}
@@ -97,6 +97,10 @@ impl<'a> Executor<'a> {
 			sql: v.sql,
 			time: v.time,
 			result: Err(Error::QueryCancelled),
+			logs: v.logs,
+			records: v.records,
+			coverage: v.coverage,
+			timing: v.timing,
 		}
 	}
 
@@ -109,6 +113,10 @@ impl<'a> Executor<'a> {
 					Ok(_) => Err(Error::QueryNotExecuted),
 					Err(e) => Err(e),
 				},
+				logs: v.logs,
+				records: v.records,
+				coverage: v.coverage,
+				timing: v.timing,
 			},
 			_ => v,
 		}
@@ -315,6 +323,10 @@ impl<'a> Executor<'a> {
 					},
 					time: dur,
 					result: Ok(v),
+					logs: opt.console.take(),
+					records: opt.recorder.take(),
+					coverage: opt.coverage.take(),
+					timing: opt.timing.take(),
 				},
 				Err(e) => {
 					// Produce the response
@@ -325,6 +337,10 @@ impl<'a> Executor<'a> {
 						},
 						time: dur,
 						result: Err(e),
+						logs: opt.console.take(),
+						records: opt.recorder.take(),
+						coverage: opt.coverage.take(),
+						timing: opt.timing.take(),
 					};
 					// Mark the error
 					self.err = true;
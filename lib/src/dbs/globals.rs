@@ -0,0 +1,60 @@
+use crate::err::Error;
+use crate::sql::script::is_identifier;
+use std::collections::BTreeSet;
+
+/// Specifies which JavaScript globals are available to embedded functions.
+///
+/// The `BigInt`, `Date`, `Decimal`, `Duration`, `Error`, `Object` and
+/// `String` globals are needed for converting values, so they are always
+/// kept. Removing a global does not remove access to it through other
+/// values, such as the `constructor` of a function.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScriptGlobals {
+	/// Remove the listed globals, keeping all others
+	Deny(BTreeSet<String>),
+	/// Keep the listed globals, removing all others
+	Allow(BTreeSet<String>),
+}
+
+impl Default for ScriptGlobals {
+	fn default() -> Self {
+		ScriptGlobals::Deny(BTreeSet::from([String::from("eval"), String::from("Function")]))
+	}
+}
+
+impl ScriptGlobals {
+	/// Create a denylist of globals which are removed
+	pub fn deny<I, S>(names: I) -> Result<ScriptGlobals, Error>
+	where
+		I: IntoIterator<Item = S>,
+		S: Into<String>,
+	{
+		Ok(ScriptGlobals::Deny(validate(names)?))
+	}
+	/// Create an allowlist of globals which are kept
+	pub fn allow<I, S>(names: I) -> Result<ScriptGlobals, Error>
+	where
+		I: IntoIterator<Item = S>,
+		S: Into<String>,
+	{
+		Ok(ScriptGlobals::Allow(validate(names)?))
+	}
+}
+
+// Checks that each global name is a valid identifier
+fn validate<I, S>(names: I) -> Result<BTreeSet<String>, Error>
+where
+	I: IntoIterator<Item = S>,
+	S: Into<String>,
+{
+	names
+		.into_iter()
+		.map(Into::into)
+		.map(|name| match is_identifier(&name) {
+			true => Ok(name),
+			false => Err(Error::InvalidGlobal {
+				name,
+			}),
+		})
+		.collect()
+}
@@ -1,21 +1,41 @@
 mod auth;
+mod bindings;
+mod cache;
+mod console;
+mod coverage;
 mod executor;
+mod globals;
 mod iterate;
 mod iterator;
+mod modules;
 mod options;
+mod pool;
+mod recorder;
 mod response;
+mod scripting;
 mod session;
 mod statement;
+mod timing;
 mod transaction;
 mod variables;
 
 pub use self::auth::*;
+pub use self::bindings::*;
+pub use self::cache::*;
+pub use self::console::*;
+pub use self::coverage::*;
 pub use self::executor::*;
+pub use self::globals::*;
 pub use self::iterator::*;
+pub use self::modules::*;
 pub use self::options::*;
+pub use self::pool::*;
+pub use self::recorder::*;
 pub use self::response::*;
+pub use self::scripting::*;
 pub use self::session::*;
 pub use self::statement::*;
+pub use self::timing::*;
 pub use self::transaction::*;
 pub use self::variables::*;
 
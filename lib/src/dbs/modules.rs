@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Supplies the modules which embedded functions can import.
+///
+/// Importing modules is disabled unless a source is set on the
+/// [`Datastore`](crate::Datastore) using `with_modules`.
+pub trait ModuleSource: Send + Sync {
+	/// Returns the JavaScript source of the named module, if it exists
+	fn source(&self, name: &str) -> Option<String>;
+}
+
+/// A [`ModuleSource`] which holds each module's source in memory.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryModules(BTreeMap<String, String>);
+
+impl MemoryModules {
+	/// Create an empty set of modules
+	pub fn new() -> MemoryModules {
+		MemoryModules::default()
+	}
+	/// Register a module with the given name and source
+	pub fn with_module<N, S>(mut self, name: N, source: S) -> MemoryModules
+	where
+		N: Into<String>,
+		S: Into<String>,
+	{
+		self.0.insert(name.into(), source.into());
+		self
+	}
+}
+
+impl ModuleSource for MemoryModules {
+	fn source(&self, name: &str) -> Option<String> {
+		self.0.get(name).cloned()
+	}
+}
+
+// The module source used when running embedded functions. Module
+// sources can not be compared, so this is compared by identity.
+#[derive(Clone)]
+pub struct Modules(pub Arc<dyn ModuleSource>);
+
+impl fmt::Debug for Modules {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("Modules")
+	}
+}
+
+impl PartialEq for Modules {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl Eq for Modules {}
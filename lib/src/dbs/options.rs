@@ -1,7 +1,18 @@
 use crate::cnf;
 use crate::dbs::Auth;
+use crate::dbs::Bindings;
+use crate::dbs::Console;
+use crate::dbs::Coverage;
 use crate::dbs::Level;
+use crate::dbs::Modules;
+use crate::dbs::Recorder;
+use crate::dbs::ScriptCache;
+use crate::dbs::ScriptGlobals;
+use crate::dbs::ScriptPool;
+use crate::dbs::Scripting;
+use crate::dbs::Timing;
 use crate::err::Error;
+use crate::sql::ScriptPermissions;
 use std::sync::Arc;
 
 // An Options is passed around when processing a set of query
@@ -41,6 +52,28 @@ pub struct Options {
 	pub indexes: bool,
 	// Should we process function futures?
 	pub futures: bool,
+	// The limits for running embedded functions
+	pub scripting: Scripting,
+	// The globals bound in embedded functions
+	pub bindings: Arc<Bindings>,
+	// The messages logged by embedded functions
+	pub console: Arc<Console>,
+	// The records which embedded functions read or wrote
+	pub recorder: Arc<Recorder>,
+	// The coverage of the embedded functions which ran
+	pub coverage: Arc<Coverage>,
+	// The timing of the embedded functions which ran
+	pub timing: Arc<Timing>,
+	// The modules which embedded functions can import
+	pub modules: Option<Modules>,
+	// The cache of compiled embedded functions
+	pub cache: Arc<ScriptCache>,
+	// The globals available to embedded functions
+	pub globals: Arc<ScriptGlobals>,
+	// The permissions of embedded functions which have none of their own
+	pub permissions: Arc<ScriptPermissions>,
+	// The pool of runtimes for running embedded functions
+	pub pool: Arc<ScriptPool>,
 }
 
 impl Default for Options {
@@ -66,6 +99,17 @@ impl Options {
 			tables: true,
 			indexes: true,
 			futures: false,
+			scripting: Scripting::default(),
+			bindings: Arc::new(Bindings::default()),
+			console: Arc::new(Console::default()),
+			recorder: Arc::new(Recorder::default()),
+			coverage: Arc::new(Coverage::default()),
+			timing: Arc::new(Timing::default()),
+			modules: None,
+			cache: Arc::new(ScriptCache::default()),
+			globals: Arc::new(ScriptGlobals::default()),
+			permissions: Arc::new(ScriptPermissions::default()),
+			pool: Arc::new(ScriptPool::default()),
 			auth: Arc::new(auth),
 		}
 	}
@@ -88,11 +132,8 @@ impl Options {
 		let dive = self.dive.saturating_add(cost);
 		if dive <= cnf::MAX_COMPUTATION_DEPTH {
 			Ok(Options {
-				auth: self.auth.clone(),
-				ns: self.ns.clone(),
-				db: self.db.clone(),
 				dive,
-				..*self
+				..self.clone()
 			})
 		} else {
 			Err(Error::ComputationDepthExceeded)
@@ -102,112 +143,82 @@ impl Options {
 	/// Create a new Options object for a subquery
 	pub fn debug(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			debug: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn force(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			force: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn perms(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			perms: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn fields(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			fields: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn events(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			events: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn tables(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			tables: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn indexes(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			indexes: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn import(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			fields: !v,
 			events: !v,
 			tables: !v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn strict(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			strict: v,
-			..*self
+			..self.clone()
 		}
 	}
 
 	/// Create a new Options object for a subquery
 	pub fn futures(&self, v: bool) -> Options {
 		Options {
-			auth: self.auth.clone(),
-			ns: self.ns.clone(),
-			db: self.db.clone(),
 			futures: v,
-			..*self
+			..self.clone()
 		}
 	}
 
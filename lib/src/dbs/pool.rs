@@ -0,0 +1,105 @@
+use std::fmt;
+#[cfg(feature = "scripting")]
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "scripting")]
+use std::sync::atomic::Ordering;
+#[cfg(feature = "scripting")]
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// A bounded pool of clean JavaScript runtimes, which are prepared ahead
+// of time for running embedded functions. Each runtime is prepared with
+// a context in which the built-in globals, such as the Record, Uuid and
+// Duration classes, have already been installed. A runtime is taken from
+// the pool for each call, and the pool is then filled again in the
+// background, so neither preparing a runtime nor filling the pool delays
+// a call. The pool is first filled when the first runtime is taken from
+// it. A pool with a capacity of zero keeps nothing, so every function
+// prepares its own runtime.
+//
+// This is the isolation boundary between embedded functions, including
+// those run for different namespaces and databases. A runtime which has
+// run any code is never returned to the pool, so the globals which were
+// set in it, the modules which were imported into it, and the values
+// which were bound into it are all dropped with it. As every runtime in
+// the pool is clean, the pool is shared and not keyed by namespace. The
+// only thing which outlives a call is the bytecode in the script cache,
+// which depends on the script alone, and holds no state.
+#[derive(Default)]
+pub struct ScriptPool {
+	capacity: usize,
+	#[cfg(feature = "scripting")]
+	filling: AtomicBool,
+	#[cfg(feature = "scripting")]
+	inner: Mutex<Vec<js::Context>>,
+	#[cfg(not(feature = "scripting"))]
+	inner: Mutex<Vec<()>>,
+}
+
+impl fmt::Debug for ScriptPool {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("ScriptPool")
+			.field("capacity", &self.capacity)
+			.field("idle", &self.len())
+			.finish()
+	}
+}
+
+impl PartialEq for ScriptPool {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+impl Eq for ScriptPool {}
+
+impl ScriptPool {
+	pub fn new(capacity: usize) -> ScriptPool {
+		ScriptPool {
+			capacity,
+			#[cfg(feature = "scripting")]
+			filling: AtomicBool::new(false),
+			inner: Mutex::default(),
+		}
+	}
+	// The number of runtimes which are ready in the pool
+	pub fn len(&self) -> usize {
+		self.inner.lock().unwrap().len()
+	}
+	// Check if there are no runtimes ready in the pool
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	// Take a prepared runtime from the pool, and fill the pool again
+	#[cfg(feature = "scripting")]
+	pub(crate) fn take(self: &Arc<Self>) -> Option<js::Context> {
+		let v = self.inner.lock().unwrap().pop();
+		self.fill();
+		v
+	}
+	// Prepare runtimes in the background until the pool is full. Only one
+	// thread fills the pool at a time, so the pool is never overfilled.
+	#[cfg(feature = "scripting")]
+	fn fill(self: &Arc<Self>) {
+		if self.len() >= self.capacity || self.filling.swap(true, Ordering::AcqRel) {
+			return;
+		}
+		let pool = self.clone();
+		let res = std::thread::Builder::new().name("script-pool".into()).spawn(move || loop {
+			while pool.len() < pool.capacity {
+				match crate::fnc::script::prepare() {
+					Ok(v) => pool.inner.lock().unwrap().push(v),
+					Err(_) => return pool.filling.store(false, Ordering::Release),
+				}
+			}
+			pool.filling.store(false, Ordering::Release);
+			// Keep filling if a runtime was taken before the flag was cleared
+			if pool.len() >= pool.capacity || pool.filling.swap(true, Ordering::AcqRel) {
+				return;
+			}
+		});
+		if res.is_err() {
+			self.filling.store(false, Ordering::Release);
+		}
+	}
+}
@@ -0,0 +1,47 @@
+use crate::sql::thing::Thing;
+use crate::sql::value::Value;
+use std::sync::Mutex;
+
+// Collects the ids of the records which embedded functions read or wrote
+// with `surrealdb.query()` while a statement is processed, in the order
+// in which they were first returned.
+#[derive(Debug, Default)]
+pub struct Recorder {
+	inner: Mutex<Vec<Thing>>,
+}
+
+impl PartialEq for Recorder {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+impl Eq for Recorder {}
+
+impl Recorder {
+	// Record the id of each record in the result of a query
+	pub fn push(&self, val: &Value) {
+		let mut ids = self.inner.lock().unwrap();
+		record(&mut ids, val);
+	}
+	// Take the ids which have been recorded so far
+	pub fn take(&self) -> Vec<Thing> {
+		std::mem::take(&mut self.inner.lock().unwrap())
+	}
+}
+
+// Finds the records in a result, which are the objects with a record id,
+// whether on their own or within arrays
+fn record(ids: &mut Vec<Thing>, val: &Value) {
+	match val {
+		Value::Array(v) => v.iter().for_each(|v| record(ids, v)),
+		Value::Object(v) => {
+			if let Some(Value::Thing(id)) = v.get("id") {
+				if !ids.contains(id) {
+					ids.push(id.clone());
+				}
+			}
+		}
+		_ => (),
+	}
+}
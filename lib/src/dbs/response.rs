@@ -1,4 +1,9 @@
+use crate::dbs::LogEntry;
+use crate::dbs::ScriptCoverage;
+use crate::dbs::ScriptTiming;
 use crate::err::Error;
+use crate::sql;
+use crate::sql::thing::Thing;
 use crate::sql::value::Value;
 use crate::sql::Object;
 use serde::ser::SerializeStruct;
@@ -11,6 +16,17 @@ pub struct Response {
 	pub sql: Option<String>,
 	pub time: Duration,
 	pub result: Result<Value, Error>,
+	/// The messages logged by embedded functions
+	pub logs: Vec<LogEntry>,
+	/// The records which embedded functions read or wrote with queries,
+	/// if the records they access are recorded
+	pub records: Vec<Thing>,
+	/// The lines of each embedded function which ran, if the coverage of
+	/// embedded functions is recorded
+	pub coverage: Vec<ScriptCoverage>,
+	/// How long each phase of each embedded function which ran took, if
+	/// the timing of embedded functions is recorded
+	pub timing: Vec<ScriptTiming>,
 }
 
 impl Response {
@@ -34,34 +50,71 @@ impl From<Response> for Value {
 		// Get the response status
 		let status = v.output().map_or_else(|_| "ERR", |_| "OK");
 		// Convert the response
+		let mut out = map! {
+			String::from("time") => time.into(),
+			String::from("status") => status.into(),
+		};
+		if let Some(sql) = v.sql {
+			out.insert(String::from("sql"), sql.into());
+		}
 		match v.result {
-			Ok(val) => match v.sql {
-				Some(sql) => Value::Object(Object(map! {
-					String::from("sql") => sql.into(),
-					String::from("time") => time.into(),
-					String::from("status") => status.into(),
-					String::from("result") => val,
-				})),
-				None => Value::Object(Object(map! {
-					String::from("time") => time.into(),
-					String::from("status") => status.into(),
-					String::from("result") => val,
-				})),
-			},
-			Err(err) => match v.sql {
-				Some(sql) => Value::Object(Object(map! {
-					String::from("sql") => sql.into(),
-					String::from("time") => time.into(),
-					String::from("status") => status.into(),
-					String::from("detail") => err.to_string().into(),
-				})),
-				None => Value::Object(Object(map! {
-					String::from("time") => time.into(),
-					String::from("status") => status.into(),
-					String::from("detail") => err.to_string().into(),
-				})),
-			},
+			Ok(val) => out.insert(String::from("result"), val),
+			Err(err) => out.insert(String::from("detail"), err.to_string().into()),
+		};
+		if !v.logs.is_empty() {
+			let logs = v.logs.into_iter().map(Value::from).collect::<Vec<_>>();
+			out.insert(String::from("logs"), logs.into());
+		}
+		if !v.records.is_empty() {
+			let records = v.records.into_iter().map(Value::from).collect::<Vec<_>>();
+			out.insert(String::from("records"), records.into());
+		}
+		if !v.coverage.is_empty() {
+			let coverage = v.coverage.into_iter().map(Value::from).collect::<Vec<_>>();
+			out.insert(String::from("coverage"), coverage.into());
 		}
+		if !v.timing.is_empty() {
+			let timing = v.timing.into_iter().map(Value::from).collect::<Vec<_>>();
+			out.insert(String::from("timing"), timing.into());
+		}
+		Value::Object(Object(out))
+	}
+}
+
+impl From<LogEntry> for Value {
+	fn from(v: LogEntry) -> Value {
+		Value::Object(Object(map! {
+			String::from("level") => v.level.to_string().into(),
+			String::from("message") => v.message.into(),
+		}))
+	}
+}
+
+impl From<ScriptCoverage> for Value {
+	fn from(v: ScriptCoverage) -> Value {
+		let lines = |v: std::collections::BTreeSet<u32>| {
+			v.into_iter().map(|v| Value::from(v as i64)).collect::<Vec<_>>().into()
+		};
+		Value::Object(Object(map! {
+			String::from("offset") => v.offset.map_or(Value::None, |v| Value::from(v as i64)),
+			String::from("lines") => lines(v.lines),
+			String::from("executed") => lines(v.executed),
+		}))
+	}
+}
+
+impl From<ScriptTiming> for Value {
+	fn from(v: ScriptTiming) -> Value {
+		Value::Object(Object(map! {
+			String::from("offset") => v.offset.map_or(Value::None, |v| Value::from(v as i64)),
+			String::from("cached") => v.cached.into(),
+			String::from("parse") => sql::Duration::from(v.parse).into(),
+			String::from("compile") => sql::Duration::from(v.compile).into(),
+			String::from("bindings") => sql::Duration::from(v.bindings).into(),
+			String::from("execute") => sql::Duration::from(v.execute).into(),
+			String::from("marshal") => sql::Duration::from(v.marshal).into(),
+			String::from("total") => sql::Duration::from(v.total).into(),
+		}))
 	}
 }
 
@@ -70,41 +123,39 @@ impl Serialize for Response {
 	where
 		S: serde::Serializer,
 	{
+		let len = 3
+			+ self.sql.is_some() as usize
+			+ !self.logs.is_empty() as usize
+			+ !self.records.is_empty() as usize
+			+ !self.coverage.is_empty() as usize
+			+ !self.timing.is_empty() as usize;
+		let mut val = serializer.serialize_struct("Response", len)?;
+		if let Some(s) = &self.sql {
+			val.serialize_field("sql", s.as_str())?;
+		}
+		val.serialize_field("time", self.speed().as_str())?;
 		match &self.result {
-			Ok(v) => match &self.sql {
-				Some(s) => {
-					let mut val = serializer.serialize_struct("Response", 4)?;
-					val.serialize_field("sql", s.as_str())?;
-					val.serialize_field("time", self.speed().as_str())?;
-					val.serialize_field("status", "OK")?;
-					val.serialize_field("result", v)?;
-					val.end()
-				}
-				None => {
-					let mut val = serializer.serialize_struct("Response", 3)?;
-					val.serialize_field("time", self.speed().as_str())?;
-					val.serialize_field("status", "OK")?;
-					val.serialize_field("result", v)?;
-					val.end()
-				}
-			},
-			Err(e) => match &self.sql {
-				Some(s) => {
-					let mut val = serializer.serialize_struct("Response", 4)?;
-					val.serialize_field("sql", s.as_str())?;
-					val.serialize_field("time", self.speed().as_str())?;
-					val.serialize_field("status", "ERR")?;
-					val.serialize_field("detail", e)?;
-					val.end()
-				}
-				None => {
-					let mut val = serializer.serialize_struct("Response", 3)?;
-					val.serialize_field("time", self.speed().as_str())?;
-					val.serialize_field("status", "ERR")?;
-					val.serialize_field("detail", e)?;
-					val.end()
-				}
-			},
+			Ok(v) => {
+				val.serialize_field("status", "OK")?;
+				val.serialize_field("result", v)?;
+			}
+			Err(e) => {
+				val.serialize_field("status", "ERR")?;
+				val.serialize_field("detail", e)?;
+			}
+		}
+		if !self.logs.is_empty() {
+			val.serialize_field("logs", &self.logs)?;
+		}
+		if !self.records.is_empty() {
+			val.serialize_field("records", &self.records)?;
+		}
+		if !self.coverage.is_empty() {
+			val.serialize_field("coverage", &self.coverage)?;
+		}
+		if !self.timing.is_empty() {
+			val.serialize_field("timing", &self.timing)?;
 		}
+		val.end()
 	}
 }
@@ -0,0 +1,181 @@
+use crate::cnf;
+use std::time::Duration;
+
+/// Specifies the limits which apply when running embedded functions.
+///
+/// Each call of an embedded function runs in a JavaScript runtime which
+/// has not run any other code, and which is dropped once the call has
+/// finished. No globals, imported modules, or bound values are shared
+/// between calls, whether they are made in the same namespace or not.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Scripting {
+	/// How long an embedded function can run before it is interrupted
+	pub timeout: Duration,
+	/// How many instructions an embedded function can run before it is
+	/// interrupted, if limited. Unlike the timeout, this does not depend
+	/// on how quickly the function is run, so a function which stays
+	/// within the limit on one machine stays within it on every machine.
+	/// Only function calls and jumps, such as those which each iteration
+	/// of a loop makes, are counted, and they are checked in batches of
+	/// 10000, so the limit is checked to the nearest 10000 instructions.
+	pub instruction_limit: Option<u64>,
+	/// How many bytes of memory an embedded function can allocate
+	pub memory_limit: usize,
+	/// How many bytes of stack an embedded function can use, which limits
+	/// how deeply its function calls can be nested
+	pub stack_size: usize,
+	/// How many values the result of an embedded function can hold, which
+	/// are counted as the result is converted, counting each element of
+	/// an array and each field of an object
+	pub result_limit: usize,
+	/// Whether embedded functions use a fixed time and random seed
+	pub deterministic: Option<DeterministicSeed>,
+	/// Whether the values passed into embedded functions, as `this`, as
+	/// arguments, and as bound globals, are deeply frozen, so that the
+	/// functions can not change them
+	pub freeze_inputs: bool,
+	/// Whether embedded functions can run queries, with the function
+	/// `surrealdb.query()`, within the transaction of the statement which
+	/// called them, and with the same permissions
+	pub queries: bool,
+	/// Whether the global object is sealed before an embedded function is
+	/// run, so that the function can not add any globals. A function is
+	/// always run in strict mode, so assigning to an undeclared variable
+	/// throws whether or not this is set, but while the global object is
+	/// sealed, adding a property to `globalThis` throws too
+	pub seal_globals: bool,
+	/// Whether the embedded functions within a `DEFINE FIELD`, `DEFINE
+	/// EVENT` or `DEFINE SCOPE` statement are compiled, without being run,
+	/// when the statement is run, so that a syntax error is reported when
+	/// the definition is made, rather than when the function is first run
+	pub validate_on_define: bool,
+	/// Whether the ids of the records which embedded functions read or
+	/// write, with `surrealdb.query()`, are recorded in the response of
+	/// the statement which called them. These are the ids of the records
+	/// which each query returned, so nothing is recorded when embedded
+	/// functions can not run queries.
+	pub record_access: bool,
+	/// Whether the lines of embedded functions which run are recorded in
+	/// the response of the statement which called them. This is meant for
+	/// testing functions, as each function is changed to record its lines
+	/// before it is compiled, so it is slower to run, and it is off by
+	/// default. Functions which have already been compiled are not changed.
+	pub coverage: bool,
+	/// Whether the time which each phase of running an embedded function
+	/// takes is recorded in the response of the statement which called it.
+	/// This is off by default, and costs nothing when it is off.
+	pub timing: bool,
+	/// Whether an embedded function which ends without running a `return`
+	/// statement returns the value of its last statement, if that is an
+	/// expression, rather than `NONE`. This is found by inserting a
+	/// `return` before the last statement of the body before it is
+	/// compiled, so it is off by default. Functions which have already
+	/// been compiled, and generator bodies, are not changed.
+	pub completion: bool,
+}
+
+/// Specifies the values used in place of the current time and random
+/// numbers when embedded functions are run deterministically.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeterministicSeed {
+	/// The seed used for generating values from `Math.random()`
+	pub seed: u32,
+	/// The timestamp, in milliseconds since the Unix epoch, returned by
+	/// `Date.now()` and used for `new Date()`
+	pub timestamp: i64,
+}
+
+impl Default for Scripting {
+	fn default() -> Self {
+		Scripting {
+			timeout: cnf::SCRIPT_TIMEOUT,
+			instruction_limit: None,
+			memory_limit: cnf::SCRIPT_MEMORY_LIMIT,
+			stack_size: cnf::SCRIPT_STACK_SIZE,
+			result_limit: cnf::SCRIPT_RESULT_LIMIT,
+			deterministic: None,
+			freeze_inputs: false,
+			queries: false,
+			seal_globals: false,
+			validate_on_define: false,
+			record_access: false,
+			coverage: false,
+			timing: false,
+			completion: false,
+		}
+	}
+}
+
+impl Scripting {
+	/// Set how long an embedded function can run before it is interrupted
+	pub fn with_timeout(mut self, timeout: Duration) -> Scripting {
+		self.timeout = timeout;
+		self
+	}
+	/// Set how many instructions an embedded function can run
+	pub fn with_instruction_limit(mut self, instruction_limit: u64) -> Scripting {
+		self.instruction_limit = Some(instruction_limit);
+		self
+	}
+	/// Set how many bytes of memory an embedded function can allocate
+	pub fn with_memory_limit(mut self, memory_limit: usize) -> Scripting {
+		self.memory_limit = memory_limit;
+		self
+	}
+	/// Set how many bytes of stack an embedded function can use
+	pub fn with_stack_size(mut self, stack_size: usize) -> Scripting {
+		self.stack_size = stack_size;
+		self
+	}
+	/// Set how many values the result of an embedded function can hold
+	pub fn with_result_limit(mut self, result_limit: usize) -> Scripting {
+		self.result_limit = result_limit;
+		self
+	}
+	/// Run embedded functions with a fixed time and random seed
+	pub fn with_deterministic(mut self, seed: DeterministicSeed) -> Scripting {
+		self.deterministic = Some(seed);
+		self
+	}
+	/// Set whether the values passed into embedded functions are frozen
+	pub fn with_freeze_inputs(mut self, freeze_inputs: bool) -> Scripting {
+		self.freeze_inputs = freeze_inputs;
+		self
+	}
+	/// Set whether embedded functions can run queries
+	pub fn with_queries(mut self, queries: bool) -> Scripting {
+		self.queries = queries;
+		self
+	}
+	/// Set whether the global object is sealed
+	pub fn with_seal_globals(mut self, seal_globals: bool) -> Scripting {
+		self.seal_globals = seal_globals;
+		self
+	}
+	/// Set whether embedded functions are compiled when they are defined
+	pub fn with_validate_on_define(mut self, validate_on_define: bool) -> Scripting {
+		self.validate_on_define = validate_on_define;
+		self
+	}
+	/// Set whether the records which embedded functions access are recorded
+	pub fn with_record_access(mut self, record_access: bool) -> Scripting {
+		self.record_access = record_access;
+		self
+	}
+	/// Set whether the lines of embedded functions which run are recorded
+	pub fn with_coverage(mut self, coverage: bool) -> Scripting {
+		self.coverage = coverage;
+		self
+	}
+	/// Set whether the time which each phase of embedded functions takes is
+	/// recorded
+	pub fn with_timing(mut self, timing: bool) -> Scripting {
+		self.timing = timing;
+		self
+	}
+	/// Set whether embedded functions return the value of their last statement
+	pub fn with_completion(mut self, completion: bool) -> Scripting {
+		self.completion = completion;
+		self
+	}
+}
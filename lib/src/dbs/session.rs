@@ -1,5 +1,7 @@
 use crate::ctx::Context;
 use crate::dbs::Auth;
+use crate::dbs::Bindings;
+use crate::dbs::Scripting;
 use crate::sql::value::Value;
 use std::sync::Arc;
 
@@ -26,6 +28,10 @@ pub struct Session {
 	pub tk: Option<Value>,
 	/// The current scope authentication data
 	pub sd: Option<Value>,
+	/// The limits for embedded functions, overriding the datastore defaults
+	pub scripting: Option<Scripting>,
+	/// The globals bound in embedded functions
+	pub bindings: Arc<Bindings>,
 }
 
 impl Session {
@@ -72,6 +78,16 @@ impl Session {
 			..Session::default()
 		}
 	}
+	/// Set the limits for embedded functions run in this session
+	pub fn with_scripting(mut self, scripting: Scripting) -> Session {
+		self.scripting = Some(scripting);
+		self
+	}
+	/// Set the globals bound in embedded functions run in this session
+	pub fn with_bindings(mut self, bindings: Bindings) -> Session {
+		self.bindings = Arc::new(bindings);
+		self
+	}
 	/// Set the selected namespace for the session
 	pub fn with_ns(mut self, ns: &str) -> Session {
 		self.ns = Some(ns.to_owned());
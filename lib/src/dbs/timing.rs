@@ -0,0 +1,130 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(feature = "scripting")]
+use std::time::Instant;
+
+/// How long each phase of running an embedded function took, as recorded
+/// when the timing of embedded functions is recorded. Each moment of the
+/// run is counted in exactly one phase, so the phases add up to the total.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ScriptTiming {
+	/// The byte offset of the function body within the query, if the
+	/// function was parsed from a query
+	pub offset: Option<usize>,
+	/// Whether compiled bytecode was used, rather than compiling the body
+	pub cached: bool,
+	/// Preparing the function body to be compiled
+	pub parse: Duration,
+	/// Compiling the function, or loading its bytecode from the cache,
+	/// and evaluating the module which holds it
+	pub compile: Duration,
+	/// Setting up the runtime and its globals, and converting the values
+	/// and arguments which are passed in
+	pub bindings: Duration,
+	/// Running the function, including any queries which it makes
+	pub execute: Duration,
+	/// Converting the value which the function returned
+	pub marshal: Duration,
+	/// The whole run, not counting the runtime which is prepared afterward
+	/// for the next function
+	pub total: Duration,
+}
+
+// Collects the timing of each embedded function which runs while a
+// statement is processed, in the order in which they ran
+#[derive(Debug, Default)]
+pub struct Timing {
+	inner: Mutex<Vec<ScriptTiming>>,
+}
+
+impl PartialEq for Timing {
+	fn eq(&self, other: &Self) -> bool {
+		std::ptr::eq(self, other)
+	}
+}
+
+impl Eq for Timing {}
+
+impl Timing {
+	// Record the timing of an embedded function which ran
+	pub fn push(&self, v: ScriptTiming) {
+		self.inner.lock().unwrap().push(v);
+	}
+	// Take the timing which has been recorded so far
+	pub fn take(&self) -> Vec<ScriptTiming> {
+		std::mem::take(&mut self.inner.lock().unwrap())
+	}
+}
+
+// Measures the phases of running an embedded function, adding the time
+// since the end of the last phase to each phase as it ends. Nothing is
+// measured when the timing of embedded functions is not recorded.
+#[cfg(feature = "scripting")]
+pub(crate) struct Stopwatch {
+	start: Option<Instant>,
+	last: Option<Instant>,
+	timing: ScriptTiming,
+}
+
+#[cfg(feature = "scripting")]
+impl Stopwatch {
+	pub fn new(enabled: bool) -> Stopwatch {
+		let now = enabled.then(Instant::now);
+		Stopwatch {
+			start: now,
+			last: now,
+			timing: ScriptTiming::default(),
+		}
+	}
+	// End the current phase, adding its time to the given phase
+	pub fn lap(&mut self, phase: fn(&mut ScriptTiming) -> &mut Duration) {
+		if let Some(last) = self.last {
+			let now = Instant::now();
+			*phase(&mut self.timing) += now - last;
+			self.last = Some(now);
+		}
+	}
+	// Finish measuring, returning the timing if anything was measured
+	pub fn finish(mut self, offset: Option<usize>, cached: bool) -> Option<ScriptTiming> {
+		let (start, last) = (self.start?, self.last?);
+		self.timing.offset = offset;
+		self.timing.cached = cached;
+		self.timing.total = last - start;
+		Some(self.timing)
+	}
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn stopwatch_phases() {
+		let now = Instant::now();
+		let mut watch = Stopwatch::new(true);
+		std::thread::sleep(Duration::from_millis(2));
+		watch.lap(|t| &mut t.compile);
+		std::thread::sleep(Duration::from_millis(2));
+		watch.lap(|t| &mut t.execute);
+		watch.lap(|t| &mut t.compile);
+		let out = watch.finish(Some(4), true).unwrap();
+		let elapsed = now.elapsed();
+		assert!(out.compile >= Duration::from_millis(2));
+		assert!(out.execute >= Duration::from_millis(2));
+		// Only the phases which ended are recorded
+		assert_eq!(
+			(out.parse, out.bindings, out.marshal),
+			(Duration::ZERO, Duration::ZERO, Duration::ZERO)
+		);
+		// The total is no longer than the time which has passed
+		assert!(out.total >= Duration::from_millis(4) && out.total <= elapsed);
+		assert!(out.compile + out.execute <= out.total);
+		assert_eq!((out.offset, out.cached), (Some(4), true));
+		// Nothing is measured when timing is off
+		let mut watch = Stopwatch::new(false);
+		watch.lap(|t| &mut t.compile);
+		assert_eq!(watch.finish(None, false), None);
+	}
+}
@@ -1,6 +1,7 @@
 use crate::sql::idiom::Idiom;
 use msgpack::encode::Error as SerdeError;
 use serde::Serialize;
+use std::time::Duration;
 use storekey::decode::Error as DecodeError;
 use storekey::encode::Error as EncodeError;
 use thiserror::Error;
@@ -77,6 +78,23 @@ pub enum Error {
 		message: String,
 	},
 
+	/// An exception was thrown by an embedded function, and not caught.
+	/// The name is empty if the thrown value was not an `Error` object,
+	/// and the stack only includes the frames within the script.
+	#[error(
+		"Problem with embedded script function. An exception occurred{}: {}{message}{}",
+		exception_location(.line, .offset),
+		exception_name(.name),
+		exception_stack(.stack)
+	)]
+	ScriptException {
+		name: String,
+		message: String,
+		stack: String,
+		line: Option<u32>,
+		offset: Option<usize>,
+	},
+
 	/// The wrong quantity or magnitude of arguments was given for the specified function
 	#[error("Incorrect arguments for function {name}(). {message}")]
 	InvalidArguments {
@@ -88,6 +106,58 @@ pub enum Error {
 	#[error("The query was not executed because it exceeded the timeout")]
 	QueryTimedout,
 
+	/// The embedded function was interrupted because it exceeded the timeout
+	#[error(
+		"The embedded function was interrupted because it exceeded the timeout of {timeout:?}"
+	)]
+	ScriptTimeout {
+		timeout: Duration,
+	},
+
+	/// The embedded function was interrupted because the query was cancelled
+	#[error("The embedded function was interrupted because the query was cancelled")]
+	ScriptCancelled,
+
+	/// The embedded function was interrupted because it exceeded the instruction limit
+	#[error("The embedded function was interrupted because it exceeded the instruction limit of {limit}")]
+	ScriptInstructionLimit {
+		limit: u64,
+	},
+
+	/// The name bound in an embedded function was not a valid identifier
+	#[error("The binding name '{name}' is not a valid JavaScript identifier")]
+	InvalidBinding {
+		name: String,
+	},
+
+	/// The name of a global in an embedded function was not a valid identifier
+	#[error("The global name '{name}' is not a valid JavaScript identifier")]
+	InvalidGlobal {
+		name: String,
+	},
+
+	/// The embedded function was stopped because it exceeded the memory limit
+	#[error(
+		"The embedded function was stopped because it exceeded the memory limit of {limit} bytes"
+	)]
+	ScriptMemoryLimit {
+		limit: usize,
+	},
+
+	/// The embedded function returned a value which was too large
+	#[error("The embedded function returned a result with more than {limit} values")]
+	ScriptResultTooLarge {
+		limit: usize,
+	},
+
+	/// The embedded function was stopped because it exceeded the stack size
+	#[error(
+		"The embedded function was stopped because its calls exceeded the stack size of {limit} bytes"
+	)]
+	ScriptStackOverflow {
+		limit: usize,
+	},
+
 	/// The query did not execute, because the transaction was cancelled
 	#[error("The query was not executed due to a cancelled transaction")]
 	QueryCancelled,
@@ -260,6 +330,32 @@ pub enum Error {
 	Decode(#[from] DecodeError),
 }
 
+// Describes where in the script and the query an exception was thrown
+fn exception_location(line: &Option<u32>, offset: &Option<usize>) -> String {
+	let line = match line {
+		Some(v) => format!(" at script:{}", v),
+		None => String::default(),
+	};
+	match offset {
+		Some(v) => format!("{} (on the line at byte {} of the query)", line, v),
+		None => line,
+	}
+}
+
+fn exception_name(name: &str) -> String {
+	match name.is_empty() {
+		false => format!("{}: ", name),
+		true => String::default(),
+	}
+}
+
+fn exception_stack(stack: &str) -> String {
+	match stack.is_empty() {
+		false => format!("\n{}", stack),
+		true => String::default(),
+	}
+}
+
 impl From<Error> for String {
 	fn from(e: Error) -> String {
 		e.to_string()
@@ -1,3 +1,2 @@
-pub mod duration;
 pub mod record;
 pub mod uuid;
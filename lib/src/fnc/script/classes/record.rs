@@ -4,21 +4,29 @@
 #[allow(clippy::module_inception)]
 pub mod record {
 
+	use crate::sql::id::Id;
+	use crate::sql::value::Value;
+
 	#[derive(Clone)]
 	#[quickjs(class)]
 	pub struct Record {
 		#[quickjs(hide)]
 		pub(crate) tb: String,
 		#[quickjs(hide)]
-		pub(crate) id: String,
+		pub(crate) id: Id,
 	}
 
 	impl Record {
 		#[quickjs(constructor)]
-		pub fn new(tb: String, id: String) -> Self {
+		pub fn new(tb: String, id: Value) -> Self {
 			Self {
 				tb,
-				id,
+				id: match id {
+					Value::Number(v) => Id::from(v.as_int()),
+					Value::Array(v) => Id::from(v),
+					Value::Object(v) => Id::from(v),
+					v => Id::from(v.as_string()),
+				},
 			}
 		}
 		#[quickjs(get)]
@@ -26,12 +34,12 @@ pub mod record {
 			&self.tb
 		}
 		#[quickjs(get)]
-		pub fn id(&self) -> &str {
-			&self.id
+		pub fn id(&self) -> Value {
+			self.id.clone().into()
 		}
 		// Convert the object to a string
 		pub fn toString(&self) -> String {
-			format!("{}:{}", self.tb, self.id)
+			format!("{}:{}", self.tb, self.id.to_raw())
 		}
 	}
 }
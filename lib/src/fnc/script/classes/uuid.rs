@@ -4,6 +4,9 @@
 #[allow(clippy::module_inception)]
 pub mod uuid {
 
+	// A uuid, which is converted back to a uuid when it is returned,
+	// or to a string if its value is not a valid uuid. Strings which
+	// are returned are never converted, even if they contain a uuid.
 	#[derive(Clone)]
 	#[quickjs(class)]
 	pub struct Uuid {
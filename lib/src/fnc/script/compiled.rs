@@ -0,0 +1,152 @@
+use super::main;
+use crate::ctx::Canceller;
+use crate::ctx::Context;
+use crate::dbs::Bindings;
+use crate::dbs::Options;
+use crate::dbs::Scripting;
+use crate::err::Error;
+use crate::sql::script::Script;
+use crate::sql::value::Value;
+use std::sync::Arc;
+
+/// An embedded function which has been compiled once, so that it can be
+/// run many times without being compiled again.
+///
+/// The handle holds the script and its bytecode, rather than a JavaScript
+/// runtime, so it is `Send` and `Sync`, and is cheap to clone and share
+/// between threads. Each execution loads the bytecode into a runtime of
+/// its own, so executions can run at the same time, and nothing which
+/// one execution sets on the global object is seen by another.
+#[derive(Clone, Debug)]
+pub struct CompiledScript {
+	script: Script,
+	code: Arc<[u8]>,
+}
+
+impl CompiledScript {
+	/// Compiles a script, failing if it is not valid JavaScript
+	pub fn new(script: &Script) -> Result<CompiledScript, Error> {
+		Ok(CompiledScript {
+			script: script.clone(),
+			code: main::compile(script)?,
+		})
+	}
+	/// Get the script which was compiled
+	pub fn script(&self) -> &Script {
+		&self.script
+	}
+	/// Get the length of the bytecode, which is how many bytes the
+	/// script takes up in a script cache
+	pub fn bytecode_len(&self) -> usize {
+		self.code.len()
+	}
+	/// Runs the compiled script within the given limits, with the bindings
+	/// set as globals. Each bound value is used as it is, so a parameter is
+	/// not replaced with its value, as there is no query to compute it in.
+	pub async fn execute(
+		&self,
+		bindings: &Bindings,
+		scripting: &Scripting,
+	) -> Result<Value, Error> {
+		self.execute_cancellable(bindings, scripting, &Canceller::default()).await
+	}
+	/// Runs the compiled script as [`CompiledScript::execute`] does, until
+	/// the canceller is cancelled, at which point the script is interrupted
+	/// and fails with [`Error::ScriptCancelled`]. The script is interrupted
+	/// as it would be when it runs past its timeout, so it stops promptly.
+	pub async fn execute_cancellable(
+		&self,
+		bindings: &Bindings,
+		scripting: &Scripting,
+		canceller: &Canceller,
+	) -> Result<Value, Error> {
+		let mut ctx = Context::default();
+		ctx.add_canceller(canceller);
+		let mut opt = Options::default();
+		opt.scripting = *scripting;
+		opt.bindings = Arc::new(bindings.clone());
+		let bind = bindings.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+		main::run_compiled(&ctx, &opt, None, None, &self.script, &self.code, vec![], bind).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use std::time::Duration;
+
+	#[tokio::test]
+	async fn compiled_execute() {
+		let script = Script::from("return a * 2 + (typeof b === 'undefined' ? 0 : b);");
+		let compiled = CompiledScript::new(&script).unwrap();
+		assert_eq!(compiled.script(), &script);
+		assert!(compiled.bytecode_len() > 0);
+		let scripting = Scripting::default();
+		let one = Bindings::new().bind("a", 1).unwrap();
+		let res = compiled.execute(&one, &scripting).await;
+		assert_eq!(res.unwrap(), Value::from(2));
+		let two = Bindings::new().bind("a", 3).unwrap().bind("b", 4).unwrap();
+		let res = compiled.execute(&two, &scripting).await;
+		assert_eq!(res.unwrap(), Value::from(10));
+	}
+
+	#[tokio::test]
+	async fn compiled_isolated() {
+		let compiled =
+			CompiledScript::new(&Script::from("globalThis.a = (globalThis.a || 0) + 1; return a;"))
+				.unwrap();
+		let scripting = Scripting::default();
+		for _ in 0..2 {
+			let res = compiled.execute(&Bindings::new(), &scripting).await;
+			assert_eq!(res.unwrap(), Value::from(1));
+		}
+	}
+
+	#[tokio::test]
+	async fn compiled_limits() {
+		let compiled = CompiledScript::new(&Script::from("while(true) {}")).unwrap();
+		let scripting = Scripting::default().with_timeout(Duration::from_millis(100));
+		let res = compiled.execute(&Bindings::new(), &scripting).await;
+		assert!(matches!(res, Err(Error::ScriptTimeout { .. })));
+	}
+
+	#[tokio::test]
+	async fn compiled_frozen() {
+		let compiled = CompiledScript::new(&Script::from("a.push(3); return a;")).unwrap();
+		let bindings = Bindings::new().bind("a", vec![1, 2]).unwrap();
+		let res = compiled.execute(&bindings, &Scripting::default()).await;
+		assert_eq!(res.unwrap(), Value::from(vec![1, 2, 3]));
+		let scripting = Scripting::default().with_freeze_inputs(true);
+		let res = compiled.execute(&bindings, &scripting).await;
+		assert!(matches!(res, Err(Error::ScriptException { .. })));
+	}
+
+	#[tokio::test]
+	async fn compiled_cancelled() {
+		let compiled = CompiledScript::new(&Script::from("while(true) {}")).unwrap();
+		let scripting = Scripting::default().with_timeout(Duration::from_secs(30));
+		let canceller = Canceller::default();
+		let cancel = canceller.clone();
+		std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(100));
+			cancel.cancel();
+		});
+		let now = std::time::Instant::now();
+		let res = compiled.execute_cancellable(&Bindings::new(), &scripting, &canceller).await;
+		assert!(matches!(res, Err(Error::ScriptCancelled)));
+		assert!(now.elapsed() < Duration::from_secs(5));
+	}
+
+	#[test]
+	fn compiled_invalid() {
+		let res = CompiledScript::new(&Script::from("return {;"));
+		assert!(matches!(res, Err(Error::InvalidScript { .. })));
+	}
+
+	#[test]
+	fn compiled_send_sync() {
+		fn shared<T: Send + Sync>() {}
+		shared::<CompiledScript>();
+	}
+}
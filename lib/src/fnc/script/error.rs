@@ -1,36 +1,137 @@
 use crate::err::Error;
+use crate::sql::script::Script;
 
 impl From<js::Error> for Error {
 	fn from(e: js::Error) -> Error {
-		match e {
+		located(e, None)
+	}
+}
+
+// Converts an error thrown when running a script, including the offset
+// within the query of the line where it was thrown, if this is known
+pub fn exception(e: js::Error, src: &Script) -> Error {
+	let (e, offset) = locate(e, src);
+	located(e, offset)
+}
+
+// Converts a value thrown by the script itself, and not caught, keeping
+// the name of the `Error` which was thrown, if it was one
+pub fn thrown(e: js::Error, name: String, src: &Script) -> Error {
+	match locate(e, src) {
+		(
 			js::Error::Exception {
 				message,
 				stack,
 				file,
 				line,
-			} => Error::InvalidScript {
-				message: format!(
-					"An exception occurred{}: {}{}",
-					match file.is_empty() {
-						false => format!(" at {}:{}", file, line),
-						true => String::default(),
-					},
-					match message.is_empty() {
-						false => message,
-						true => String::default(),
-					},
-					match stack.is_empty() {
-						false => format!("\n{}", stack),
-						true => String::default(),
-					}
-				),
 			},
-			js::Error::Unknown => Error::InvalidScript {
-				message: "An unknown error occurred".to_string(),
+			offset,
+		) => Error::ScriptException {
+			name,
+			message,
+			stack: frames(&stack),
+			line: match file == "script" {
+				true => u32::try_from(line).ok(),
+				false => None,
+			},
+			offset,
+		},
+		(e, offset) => located(e, offset),
+	}
+}
+
+// Finds the line of the script where an error was thrown, and the offset
+// within the query of that line, if this is known
+fn locate(e: js::Error, src: &Script) -> (js::Error, Option<usize>) {
+	let e = match e {
+		// Errors thrown at runtime only record their line in the stack
+		js::Error::Exception {
+			message,
+			stack,
+			file,
+			line,
+		} if line < 0 => match stack_line(&stack) {
+			Some(v) => js::Error::Exception {
+				message,
+				stack,
+				file: String::from("script"),
+				line: v,
 			},
-			_ => Error::InvalidScript {
-				message: e.to_string(),
+			None => js::Error::Exception {
+				message,
+				stack,
+				file,
+				line,
 			},
-		}
+		},
+		e => e,
+	};
+	let offset = match &e {
+		js::Error::Exception {
+			file,
+			line,
+			..
+		} if file == "script" => u32::try_from(*line)
+			.ok()
+			.and_then(|l| src.line_offset(l))
+			.and_then(|l| Some(src.offset()? + l)),
+		_ => None,
+	};
+	(e, offset)
+}
+
+// Removes the stack frames which follow the script, as those are within
+// the function which the script is wrapped in
+fn frames(stack: &str) -> String {
+	match stack.find("at main (script") {
+		Some(p) => match stack[p..].find('\n') {
+			Some(n) => stack[..p + n + 1].to_owned(),
+			None => stack.to_owned(),
+		},
+		None => stack.to_owned(),
+	}
+}
+
+// Finds the line of the innermost stack frame within the script
+fn stack_line(stack: &str) -> Option<i32> {
+	let (_, rest) = stack.split_once("(script:")?;
+	let end = rest.find(|c: char| !c.is_ascii_digit())?;
+	rest[..end].parse().ok()
+}
+
+fn located(e: js::Error, offset: Option<usize>) -> Error {
+	match e {
+		js::Error::Exception {
+			message,
+			stack,
+			file,
+			line,
+		} => Error::InvalidScript {
+			message: format!(
+				"An exception occurred{}{}: {}{}",
+				match file.is_empty() {
+					false => format!(" at {}:{}", file, line),
+					true => String::default(),
+				},
+				match offset {
+					Some(v) => format!(" (on the line at byte {} of the query)", v),
+					None => String::default(),
+				},
+				match message.is_empty() {
+					false => message,
+					true => String::default(),
+				},
+				match stack.is_empty() {
+					false => format!("\n{}", stack),
+					true => String::default(),
+				}
+			),
+		},
+		js::Error::Unknown => Error::InvalidScript {
+			message: "An unknown error occurred".to_string(),
+		},
+		_ => Error::InvalidScript {
+			message: e.to_string(),
+		},
 	}
 }
@@ -1,4 +1,8 @@
+use futures::pin_mut;
+use futures::task::noop_waker;
 use std::future::Future;
+use std::task::Context;
+use std::task::Poll;
 
 #[derive(Default)]
 pub struct Executor<'a> {
@@ -6,8 +10,22 @@ pub struct Executor<'a> {
 }
 
 impl<'a> Executor<'a> {
-	pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
-		self.exe.run(future).await
+	// Runs the spawned tasks until the future completes. Every task which
+	// can settle a promise is run by this executor, so if there are no
+	// tasks left to run, the future can never complete, and None is
+	// returned instead of waiting forever.
+	pub fn drive<T>(&self, future: impl Future<Output = T>) -> Option<T> {
+		let waker = noop_waker();
+		let mut cx = Context::from_waker(&waker);
+		pin_mut!(future);
+		loop {
+			if let Poll::Ready(v) = future.as_mut().poll(&mut cx) {
+				return Some(v);
+			}
+			if !self.exe.try_tick() {
+				return None;
+			}
+		}
 	}
 }
 
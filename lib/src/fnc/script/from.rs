@@ -1,96 +1,249 @@
 use super::classes;
+use super::globals;
 use crate::sql::array::Array;
 use crate::sql::datetime::Datetime;
 use crate::sql::duration::Duration;
+use crate::sql::geometry::Geometry;
+use crate::sql::number::Number;
 use crate::sql::object::Object;
 use crate::sql::thing::Thing;
 use crate::sql::uuid::Uuid;
 use crate::sql::value::Value;
-use chrono::{TimeZone, Utc};
+use bigdecimal::BigDecimal;
+use chrono::{LocalResult, TimeZone, Utc};
 use js::Ctx;
 use js::Error;
 use js::FromAtom;
 use js::FromJs;
+use std::str::FromStr;
 
+// The number of values which can still be converted, while a value is
+// being converted, and whether the value held more than that
+#[derive(Default)]
+struct Budget {
+	remaining: Option<usize>,
+	exceeded: bool,
+}
+
+impl Budget {
+	// Counts a value which is being converted, failing once the limit of
+	// the value which is being converted is reached
+	fn count(&mut self) -> Result<(), Error> {
+		match self.remaining {
+			Some(0) => {
+				self.exceeded = true;
+				Err(Error::FromJs {
+					from: "value",
+					to: "Value",
+					message: Some(String::from("The result is too large")),
+				})
+			}
+			Some(n) => {
+				self.remaining = Some(n - 1);
+				Ok(())
+			}
+			None => Ok(()),
+		}
+	}
+}
+
+// Converts the result of a script, returning None, and stopping as soon
+// as the limit is reached, if the result holds more than `limit` values.
+// Every value is counted, including each element of an array, and each
+// field of an object, along with the array or object itself.
+pub fn limited<'js>(
+	ctx: Ctx<'js>,
+	val: js::Value<'js>,
+	limit: usize,
+) -> Result<Option<Value>, Error> {
+	let mut budget = Budget {
+		remaining: Some(limit),
+		exceeded: false,
+	};
+	let res = convert(ctx, val, &mut budget);
+	match budget.exceeded {
+		true => Ok(None),
+		false => res.map(Some),
+	}
+}
+
+// Returns a Uint8Array of the bytes of an ArrayBuffer or a Uint8Array.
+// Other typed arrays are converted like any other array-like object.
+fn bytes<'js>(ctx: Ctx<'js>, v: &js::Object<'js>) -> Result<Option<js::Object<'js>>, Error> {
+	let global = ctx.globals();
+	let view = match global.get::<_, Option<js::Function>>("Uint8Array")? {
+		Some(v) => v,
+		None => return Ok(None),
+	};
+	if v.is_instance_of(&view) {
+		return Ok(Some(v.clone()));
+	}
+	match global.get::<_, Option<js::Object>>("ArrayBuffer")? {
+		Some(buffer) if v.is_instance_of(&buffer) => view.construct((v.clone(),)).map(Some),
+		_ => Ok(None),
+	}
+}
+
+// undefined is converted to NONE, and null is converted to NULL. A
+// property of an object which is undefined is left out of the object,
+// so that it is absent, while a property which is null is kept.
 impl<'js> FromJs<'js> for Value {
 	fn from_js(ctx: Ctx<'js>, val: js::Value<'js>) -> Result<Self, Error> {
-		match val {
-			val if val.type_name() == "null" => Ok(Value::Null),
-			val if val.type_name() == "undefined" => Ok(Value::None),
-			val if val.is_bool() => Ok(val.as_bool().unwrap().into()),
-			val if val.is_string() => match val.into_string().unwrap().to_string() {
-				Ok(v) => Ok(Value::from(v)),
-				Err(e) => Err(e),
-			},
-			val if val.is_int() => Ok(val.as_int().unwrap().into()),
-			val if val.is_float() => Ok(val.as_float().unwrap().into()),
-			val if val.is_array() => {
-				let v = val.as_array().unwrap();
+		convert(ctx, val, &mut Budget::default())
+	}
+}
+
+// Converts a value, counting each value which is converted against the
+// budget, so that a conversion only ever counts against its own budget
+fn convert<'js>(ctx: Ctx<'js>, val: js::Value<'js>, budget: &mut Budget) -> Result<Value, Error> {
+	budget.count()?;
+	match val {
+		val if val.type_name() == "null" => Ok(Value::Null),
+		val if val.type_name() == "undefined" => Ok(Value::None),
+		val if val.is_bool() => Ok(val.as_bool().unwrap().into()),
+		val if val.is_string() => match val.into_string().unwrap().to_string() {
+			Ok(v) => Ok(Value::from(v)),
+			Err(e) => Err(e),
+		},
+		val if val.is_int() => Ok(val.as_int().unwrap().into()),
+		// A number which is not finite can not be held by a
+		// SurrealQL number, so it is an error rather than a value
+		val if val.is_float() => match val.as_float().unwrap() {
+			v if v.is_finite() => Ok(v.into()),
+			v => Err(Error::FromJs {
+				from: "number",
+				to: "Number",
+				message: Some(format!(
+					"{} can not be converted to a number",
+					match v.is_nan() {
+						true => "NaN",
+						false if v > 0.0 => "Infinity",
+						false => "-Infinity",
+					}
+				)),
+			}),
+		},
+		// A BigInt is converted to an integer, or to a
+		// decimal if it is too large to fit in an integer
+		val if val.type_of() == js::Type::Unknown => {
+			let big: js::Object = ctx.globals().get("BigInt")?;
+			let big: js::Object = big.get("prototype")?;
+			let f: js::Function = big.get("toString")?;
+			let err = || Error::FromJs {
+				from: "bigint",
+				to: "Number",
+				message: Some(String::from("The value can not be converted to a number")),
+			};
+			let v = f.call::<_, String>((js::This(val),)).map_err(|_| err())?;
+			match v.parse::<i64>() {
+				Ok(v) => Ok(v.into()),
+				Err(_) => match BigDecimal::from_str(&v) {
+					Ok(v) => Ok(Number::from(v).into()),
+					Err(_) => Err(err()),
+				},
+			}
+		}
+		val if val.is_array() => {
+			let v = val.as_array().unwrap();
+			let mut x = Array::with_capacity(v.len());
+			for i in v.iter() {
+				let v = i?;
+				let v = convert(ctx, v, budget)?;
+				x.push(v);
+			}
+			Ok(x.into())
+		}
+		val if val.is_object() => {
+			// Extract the value as an object
+			let v = val.into_object().unwrap();
+			// Check to see if this object is a duration
+			let duration: js::Object = ctx.globals().get("Duration")?;
+			if (v).is_instance_of(&duration) {
+				let secs: f64 = v.get("secs")?;
+				let nanos: f64 = v.get("nanos")?;
+				return Ok(Duration::from(globals::duration::duration(secs, nanos)).into());
+			}
+			// Check to see if this object is a record
+			if (v).instance_of::<classes::record::record::Record>() {
+				let v = v.into_instance::<classes::record::record::Record>().unwrap();
+				let v: &classes::record::record::Record = v.as_ref();
+				let v = (v.tb.clone(), v.id.clone());
+				return Ok(Thing::from(v).into());
+			}
+			// Check to see if this object is a uuid, which is
+			// converted to a string if it is an invalid uuid
+			if (v).instance_of::<classes::uuid::uuid::Uuid>() {
+				let v = v.into_instance::<classes::uuid::uuid::Uuid>().unwrap();
+				let v: &classes::uuid::uuid::Uuid = v.as_ref();
+				return match uuid::Uuid::try_parse(&v.value) {
+					Ok(v) => Ok(Uuid(v).into()),
+					Err(_) => Ok(Value::from(v.value.as_str())),
+				};
+			}
+			// Check to see if this object is a date, which is
+			// always a whole number of milliseconds, and which
+			// is converted to NONE if it is an invalid date
+			let date: js::Object = ctx.globals().get("Date")?;
+			if (v).is_instance_of(&date) {
+				let f: js::Function = v.get("getTime")?;
+				let m: f64 = f.call((js::This(v),))?;
+				return match Utc.timestamp_millis_opt(m as i64) {
+					LocalResult::Single(d) if m.is_finite() => Ok(Datetime::from(d).into()),
+					_ => Ok(Value::None),
+				};
+			}
+			// Check to see if this object is a decimal, which
+			// is converted to NONE if it is an invalid decimal
+			let decimal: js::Object = ctx.globals().get("Decimal")?;
+			if (v).is_instance_of(&decimal) {
+				let v: String = v.get("value")?;
+				return match BigDecimal::from_str(&v) {
+					Ok(v) => Ok(Number::from(v).into()),
+					Err(_) => Ok(Value::None),
+				};
+			}
+			// Check to see if this object holds binary data, which is
+			// converted to an array of bytes, as there is no binary
+			// value in SurrealQL which it could be converted to
+			if let Some(v) = bytes(ctx, &v)? {
+				let len: u32 = v.get("length")?;
+				let mut x = Array::with_capacity(len as usize);
+				for i in 0..len {
+					budget.count()?;
+					x.push(Value::from(v.get::<_, i32>(i)? as i64));
+				}
+				return Ok(x.into());
+			}
+			// Check to see if this object is an array
+			if let Some(v) = v.as_array() {
 				let mut x = Array::with_capacity(v.len());
 				for i in v.iter() {
 					let v = i?;
-					let v = Value::from_js(ctx, v)?;
+					let v = convert(ctx, v, budget)?;
 					x.push(v);
 				}
-				Ok(x.into())
+				return Ok(x.into());
 			}
-			val if val.is_object() => {
-				// Extract the value as an object
-				let v = val.into_object().unwrap();
-				// Check to see if this object is a duration
-				if (v).instance_of::<classes::duration::duration::Duration>() {
-					let v = v.into_instance::<classes::duration::duration::Duration>().unwrap();
-					let v: &classes::duration::duration::Duration = v.as_ref();
-					let v = v.value.clone();
-					return Ok(Duration::from(v).into());
-				}
-				// Check to see if this object is a record
-				if (v).instance_of::<classes::record::record::Record>() {
-					let v = v.into_instance::<classes::record::record::Record>().unwrap();
-					let v: &classes::record::record::Record = v.as_ref();
-					let v = (v.tb.clone(), v.id.clone());
-					return Ok(Thing::from(v).into());
-				}
-				// Check to see if this object is a uuid
-				if (v).instance_of::<classes::uuid::uuid::Uuid>() {
-					let v = v.into_instance::<classes::uuid::uuid::Uuid>().unwrap();
-					let v: &classes::uuid::uuid::Uuid = v.as_ref();
-					let v = v.value.clone();
-					return Ok(Uuid::from(v).into());
-				}
-				// Check to see if this object is a date
-				let date: js::Object = ctx.globals().get("Date")?;
-				if (v).is_instance_of(&date) {
-					let f: js::Function = v.get("getTime")?;
-					let m: i64 = f.call((js::This(v),))?;
-					let d = Utc.timestamp_millis(m);
-					return Ok(Datetime::from(d).into());
-				}
-				// Check to see if this object is an array
-				if let Some(v) = v.as_array() {
-					let mut x = Array::with_capacity(v.len());
-					for i in v.iter() {
-						let v = i?;
-						let v = Value::from_js(ctx, v)?;
-						x.push(v);
-					}
-					return Ok(x.into());
-				}
-				// Check to see if this object is a function
-				if v.as_function().is_some() {
-					return Ok(Value::None);
-				}
-				// This object is a normal object
-				let mut x = Object::default();
-				for i in v.props() {
-					let (k, v) = i?;
-					let k = String::from_atom(k)?;
-					let v = Value::from_js(ctx, v)?;
+			// Check to see if this object is a function
+			if v.as_function().is_some() {
+				return Ok(Value::None);
+			}
+			// This object is a normal object
+			let mut x = Object::default();
+			for i in v.props() {
+				let (k, v) = i?;
+				let k = String::from_atom(k)?;
+				let v = convert(ctx, v, budget)?;
+				if !matches!(v, Value::None) {
 					x.insert(k, v);
 				}
-				Ok(x.into())
 			}
-			_ => Ok(Value::None),
+			// Check to see if this object is a GeoJSON geometry
+			match Geometry::from_object(&x) {
+				Some(v) => Ok(v.into()),
+				None => Ok(x.into()),
+			}
 		}
+		_ => Ok(Value::None),
 	}
 }
@@ -0,0 +1,38 @@
+use crate::dbs::Console;
+use crate::dbs::LogLevel;
+use crate::sql::value::Value;
+use js::Ctx;
+use js::Function;
+use js::Object;
+use js::Rest;
+use js::Result;
+use std::sync::Arc;
+
+// Creates a console object which records any logged messages
+pub fn console<'js>(ctx: Ctx<'js>, console: &Arc<Console>) -> Result<Object<'js>> {
+	let obj = Object::new(ctx)?;
+	for (name, level) in [
+		("log", LogLevel::Log),
+		("info", LogLevel::Log),
+		("warn", LogLevel::Warn),
+		("error", LogLevel::Error),
+	] {
+		let console = console.clone();
+		let func = Function::new(ctx, move |args: Rest<Value>| {
+			console.push(level, message(args.into_inner()));
+		})?;
+		obj.set(name, func)?;
+	}
+	Ok(obj)
+}
+
+// Joins the logged values into a single message
+fn message(args: Vec<Value>) -> String {
+	args.iter()
+		.map(|v| match v {
+			Value::Strand(v) => v.as_str().to_owned(),
+			v => v.to_string(),
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
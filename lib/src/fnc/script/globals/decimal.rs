@@ -0,0 +1,27 @@
+use js::Ctx;
+use js::Result;
+
+// Defines the Decimal class, which holds the exact text of a decimal
+// number, so that it can be passed through without losing precision.
+// Any arithmetic on a Decimal uses its value as a floating point number.
+const SOURCE: &str = "
+	globalThis.Decimal = class Decimal {
+		constructor(value) {
+			this.value = String(value);
+			Object.freeze(this);
+		}
+		toString() {
+			return this.value;
+		}
+		toJSON() {
+			return this.value;
+		}
+		valueOf() {
+			return Number(this.value);
+		}
+	};
+";
+
+pub fn install(ctx: Ctx<'_>) -> Result<()> {
+	ctx.eval(SOURCE)
+}
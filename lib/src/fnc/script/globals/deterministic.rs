@@ -0,0 +1,31 @@
+use crate::dbs::DeterministicSeed;
+use js::Ctx;
+use js::Result;
+
+// Replaces Math.random with a seeded generator, and freezes the
+// time used by Date.now and by the Date constructor without arguments
+const SOURCE: &str = "
+	((seed, time) => {
+		Math.random = () => {
+			seed = (seed + 0x6d2b79f5) >>> 0;
+			let t = Math.imul(seed ^ (seed >>> 15), seed | 1);
+			t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+			return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+		};
+		const NativeDate = Date;
+		const FrozenDate = function Date(...args) {
+			if (!new.target) return new NativeDate(time).toString();
+			return args.length ? new NativeDate(...args) : new NativeDate(time);
+		};
+		FrozenDate.prototype = NativeDate.prototype;
+		FrozenDate.now = () => time;
+		FrozenDate.parse = NativeDate.parse;
+		FrozenDate.UTC = NativeDate.UTC;
+		globalThis.Date = FrozenDate;
+	})
+";
+
+pub fn install(ctx: Ctx<'_>, v: DeterministicSeed) -> Result<()> {
+	let install: js::Function = ctx.eval(SOURCE)?;
+	install.call((v.seed, v.timestamp as f64))
+}
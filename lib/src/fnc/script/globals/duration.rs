@@ -0,0 +1,53 @@
+use crate::sql::duration;
+use crate::sql::duration::Duration;
+use js::Ctx;
+use js::Function;
+use js::Result;
+use std::time;
+
+// Defines the Duration class, which holds the whole seconds and the
+// nanoseconds of a duration. A Duration can be created from the text
+// of a duration, which is parsed in the same way as in a query, and a
+// TypeError is thrown if the text is not a valid duration.
+const SOURCE: &str = "
+	((parse, format) => {
+		globalThis.Duration = class Duration {
+			constructor(secs, nanos) {
+				if (typeof secs === 'string') {
+					const v = parse(secs);
+					if (v === undefined) throw new TypeError(`Invalid duration: '${secs}'`);
+					[secs, nanos] = v;
+				}
+				this.secs = Number(secs) || 0;
+				this.nanos = Number(nanos) || 0;
+				Object.freeze(this);
+			}
+			get value() {
+				return format(this.secs, this.nanos);
+			}
+			toString() {
+				return format(this.secs, this.nanos);
+			}
+			toJSON() {
+				return format(this.secs, this.nanos);
+			}
+		};
+	})
+";
+
+pub fn install(ctx: Ctx<'_>) -> Result<()> {
+	let parse = Function::new(ctx, |v: String| match duration::duration(&v) {
+		Ok(("", v)) => Some(vec![v.as_secs() as f64, v.subsec_nanos() as f64]),
+		_ => None,
+	})?;
+	let format =
+		Function::new(ctx, |secs: f64, nanos: f64| Duration::from(duration(secs, nanos)).to_raw())?;
+	let install: Function = ctx.eval(SOURCE)?;
+	install.call((parse, format))
+}
+
+// Creates a duration from the seconds and nanoseconds of a Duration
+pub fn duration(secs: f64, nanos: f64) -> time::Duration {
+	let secs = time::Duration::from_secs(secs as u64);
+	secs.saturating_add(time::Duration::from_nanos(nanos as u64))
+}
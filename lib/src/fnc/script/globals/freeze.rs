@@ -0,0 +1,20 @@
+use js::Ctx;
+use js::Function;
+use js::Result;
+
+// Freezes a value, and every object and array within it, returning the
+// value. Values which are already frozen are skipped, so that an object
+// which is reached more than once is only frozen the first time.
+const SOURCE: &str = "
+	(function freeze(v) {
+		if (v !== null && typeof v === 'object' && !Object.isFrozen(v)) {
+			Object.freeze(v);
+			for (const k of Reflect.ownKeys(v)) freeze(v[k]);
+		}
+		return v;
+	})
+";
+
+pub fn freeze(ctx: Ctx<'_>) -> Result<Function<'_>> {
+	ctx.eval(SOURCE)
+}
@@ -1 +1,10 @@
+pub mod console;
+pub mod decimal;
+pub mod deterministic;
+pub mod duration;
 pub mod fetch;
+pub mod freeze;
+pub mod query;
+pub mod restrict;
+pub mod seal;
+pub mod surql;
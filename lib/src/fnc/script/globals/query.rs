@@ -0,0 +1,112 @@
+use crate::ctx::Context;
+use crate::dbs::Options;
+use crate::dbs::Transaction;
+use crate::err::Error;
+use crate::sql::statement::Statement;
+use crate::sql::value::Value;
+use async_recursion::async_recursion;
+use js::Ctx;
+use js::Func;
+use js::Function;
+use js::Object;
+use js::Opt;
+use js::Persistent;
+use js::Result;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// A query which a script has asked to run, with the functions which
+// settle the promise which the script was given for its result
+pub struct Request {
+	pub sql: String,
+	pub vars: Option<Value>,
+	pub resolve: Persistent<Function<'static>>,
+	pub reject: Persistent<Function<'static>>,
+}
+
+// The queries which a script has asked to run, which have not yet run
+pub type Pending = Arc<Mutex<Vec<Request>>>;
+
+// Registers the `surrealdb.query` function, which returns a promise for
+// the result of a query. The query is not run by the function itself,
+// but is run by the caller of the script, once the script is waiting.
+pub fn install<'js>(ctx: Ctx<'js>, pending: &Pending) -> Result<()> {
+	let surrealdb = Object::new(ctx)?;
+	let pending = pending.clone();
+	let query = Func::new("query", move |ctx: Ctx<'js>, sql: String, vars: Opt<Value>| {
+		let (promise, resolve, reject) = ctx.promise()?;
+		pending.lock().unwrap().push(Request {
+			sql,
+			vars: vars.0,
+			resolve: Persistent::save(ctx, resolve),
+			reject: Persistent::save(ctx, reject),
+		});
+		Ok::<_, js::Error>(promise)
+	});
+	surrealdb.set("query", query)?;
+	ctx.globals().set("surrealdb", surrealdb)
+}
+
+// Runs a query which a script asked to run, within the transaction of
+// the statement which called the script, and with the same permissions.
+// Only a single data statement can be run, with any variables which
+// were given available to it as parameters.
+#[cfg_attr(feature = "parallel", async_recursion)]
+#[cfg_attr(not(feature = "parallel"), async_recursion(?Send))]
+pub async fn run(
+	ctx: &Context<'_>,
+	opt: &Options,
+	txn: &Transaction,
+	sql: &str,
+	vars: Option<Value>,
+) -> std::result::Result<Value, Error> {
+	// Prevent deep recursion
+	let opt = &opt.dive(2)?;
+	// Parse the query
+	let mut query = crate::sql::parse(sql)?;
+	let stm = match query.0 .0.len() {
+		1 => query.0 .0.remove(0),
+		_ => {
+			return Err(Error::InvalidScript {
+				message: String::from("A script can only run a single statement at a time"),
+			})
+		}
+	};
+	if !matches!(
+		stm,
+		Statement::Select(_)
+			| Statement::Create(_)
+			| Statement::Update(_)
+			| Statement::Delete(_)
+			| Statement::Relate(_)
+			| Statement::Insert(_)
+			| Statement::Ifelse(_)
+			| Statement::Output(_)
+	) {
+		return Err(Error::InvalidScript {
+			message: format!("A script can not run the statement '{}'", stm),
+		});
+	}
+	// Add the variables as parameters
+	let mut ctx = Context::new(ctx);
+	match vars {
+		Some(Value::Object(v)) => {
+			for (k, v) in v.0 {
+				ctx.add_value(k, v);
+			}
+		}
+		Some(Value::None) | None => (),
+		Some(_) => {
+			return Err(Error::InvalidScript {
+				message: String::from("The variables for a query must be an object"),
+			})
+		}
+	}
+	// Process the statement
+	let res = stm.compute(&ctx, opt, txn, None).await?;
+	// Record the records which the statement returned, if specified
+	if opt.scripting.record_access {
+		opt.recorder.push(&res);
+	}
+	Ok(res)
+}
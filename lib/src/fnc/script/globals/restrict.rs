@@ -0,0 +1,104 @@
+use crate::dbs::ScriptGlobals;
+use crate::sql::ScriptPermissions;
+use js::Ctx;
+use js::Result;
+
+// The globals which are needed when converting values, and when wrapping
+// the value which a script throws
+const REQUIRED: [&str; 7] = ["BigInt", "Date", "Decimal", "Duration", "Error", "Object", "String"];
+
+// The globals which are kept whatever the permissions of a script, as
+// they are the built-in objects of the language, and the objects which
+// are installed in every runtime. Only the globals which can be used to
+// inspect or change the runtime itself, such as `Proxy`, `Atomics`,
+// `eval` and `Function`, need to be permitted.
+const BUILTINS: [&str; 51] = [
+	"AggregateError",
+	"Array",
+	"ArrayBuffer",
+	"BigInt64Array",
+	"BigUint64Array",
+	"Boolean",
+	"DataView",
+	"Decimal",
+	"Duration",
+	"EvalError",
+	"Float32Array",
+	"Float64Array",
+	"Infinity",
+	"Int16Array",
+	"Int32Array",
+	"Int8Array",
+	"InternalError",
+	"JSON",
+	"Map",
+	"Math",
+	"NaN",
+	"Number",
+	"Promise",
+	"RangeError",
+	"Record",
+	"ReferenceError",
+	"Reflect",
+	"RegExp",
+	"Set",
+	"Symbol",
+	"SyntaxError",
+	"TypeError",
+	"URIError",
+	"Uint16Array",
+	"Uint32Array",
+	"Uint8Array",
+	"Uint8ClampedArray",
+	"Uuid",
+	"WeakMap",
+	"WeakSet",
+	"console",
+	"decodeURI",
+	"decodeURIComponent",
+	"encodeURI",
+	"encodeURIComponent",
+	"globalThis",
+	"isFinite",
+	"isNaN",
+	"parseFloat",
+	"parseInt",
+	"surql",
+];
+
+// Removes each global which is denied, or which is not allowed,
+// skipping any which can not be removed, such as `undefined`. The
+// global object is the `this` of the code which defines the function,
+// as `globalThis` may already have been removed.
+const SOURCE: &str = "
+	((allow, names, required) => {
+		const global = this;
+		const describe = Object.getOwnPropertyDescriptor;
+		for (const name of Object.getOwnPropertyNames(global)) {
+			if (required.includes(name)) continue;
+			if (!describe(global, name).configurable) continue;
+			if (names.includes(name) !== allow) delete global[name];
+		}
+	})
+";
+
+pub fn restrict(ctx: Ctx<'_>, globals: &ScriptGlobals) -> Result<()> {
+	let (allow, names) = match globals {
+		ScriptGlobals::Allow(v) => (true, v),
+		ScriptGlobals::Deny(v) => (false, v),
+	};
+	let names: Vec<&str> = names.iter().map(String::as_str).collect();
+	let restrict: js::Function = ctx.eval(SOURCE)?;
+	restrict.call((allow, names, REQUIRED.to_vec()))
+}
+
+// Keeps only the globals which a script is permitted to use, along with
+// the built-in globals, and the host bindings which it is permitted to use
+pub fn permit(ctx: Ctx<'_>, perms: &ScriptPermissions) -> Result<()> {
+	let mut names: Vec<&str> = perms.globals.iter().map(String::as_str).collect();
+	names.extend(BUILTINS);
+	names.extend(perms.http.then_some("fetch"));
+	names.extend(perms.query.then_some("surrealdb"));
+	let restrict: js::Function = ctx.eval(SOURCE)?;
+	restrict.call((true, names, REQUIRED.to_vec()))
+}
@@ -0,0 +1,10 @@
+use js::Ctx;
+use js::Result;
+
+// Seals the global object, so that no globals can be added or removed,
+// while the values of the existing globals can still be changed.
+const SOURCE: &str = "Object.seal(globalThis)";
+
+pub fn seal(ctx: Ctx<'_>) -> Result<()> {
+	ctx.eval(SOURCE)
+}
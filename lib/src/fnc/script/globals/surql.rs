@@ -0,0 +1,70 @@
+use crate::sql::value::Value;
+use js::Ctx;
+use js::Function;
+use js::Result;
+
+// Defines how a Record and a Uuid are converted to JSON, and defines the
+// surql object, with the functions stringify and parse, which work in the
+// same way as JSON.stringify and JSON.parse, except that the values which
+// are converted to SurrealDB types keep their type through a round trip. A Record, a Date, a Duration, a Decimal, a Uuid, and a
+// BigInt are stringified as an object tagged with a `$surql` field, and
+// are parsed back into the same type. Any other object which has its own
+// `$surql` field is wrapped, so that it is parsed back unchanged.
+//
+// With JSON.stringify, a Record and a Uuid become their text, a Date
+// becomes its ISO 8601 text, and a Duration and a Decimal become their
+// text, so after JSON.parse all of these are returned as strings, while
+// a BigInt can not be stringified at all.
+const SOURCE: &str = "
+	((record) => {
+		Record.prototype.toJSON = function() { return this.toString(); };
+		Uuid.prototype.toJSON = function() { return this.toString(); };
+		const tagged = (v) => v !== null && typeof v === 'object' && Object.prototype.hasOwnProperty.call(v, '$surql');
+		const wrapped = new WeakSet();
+		function replacer(k, v) {
+			const x = this[k];
+			if (x instanceof Record) {
+				const [tb, id] = record(x);
+				return { $surql: 'record', tb, id };
+			}
+			if (x instanceof Uuid) return { $surql: 'uuid', value: x.toString() };
+			if (x instanceof Duration) return { $surql: 'duration', value: x.value };
+			if (x instanceof Decimal) return { $surql: 'decimal', value: x.value };
+			if (x instanceof Date) return isNaN(x.getTime()) ? undefined : { $surql: 'datetime', value: x.toISOString() };
+			if (typeof x === 'bigint') return { $surql: 'bigint', value: String(x) };
+			if (tagged(v) && !Array.isArray(v) && !wrapped.has(this)) {
+				const w = { $surql: 'object', value: v };
+				wrapped.add(w);
+				return w;
+			}
+			return v;
+		}
+		function reviver(k, v) {
+			if (!tagged(v) || (k === 'value' && this.$surql === 'object')) return v;
+			switch (v.$surql) {
+				case 'record': return new Record(v.tb, v.id);
+				case 'uuid': return new Uuid(v.value);
+				case 'duration': return new Duration(v.value);
+				case 'decimal': return new Decimal(v.value);
+				case 'datetime': return new Date(v.value);
+				case 'bigint': return BigInt(v.value);
+				case 'object': return v.value;
+				default: return v;
+			}
+		}
+		globalThis.surql = Object.freeze({
+			stringify: (value, space) => JSON.stringify(value, replacer, space),
+			parse: (text) => JSON.parse(text, reviver),
+		});
+	})
+";
+
+pub fn install(ctx: Ctx<'_>) -> Result<()> {
+	// Splits a Record into its table and its id
+	let record = Function::new(ctx, |v: Value| match v {
+		Value::Thing(v) => vec![Value::from(v.tb), Value::from(v.id)],
+		_ => vec![Value::None, Value::None],
+	})?;
+	let install: Function = ctx.eval(SOURCE)?;
+	install.call((record,))
+}
@@ -1,7 +1,6 @@
 use super::classes;
 use crate::sql::number::Number;
 use crate::sql::value::Value;
-use bigdecimal::ToPrimitive;
 use js::Array;
 use js::Class;
 use js::Ctx;
@@ -11,12 +10,18 @@ use js::Null;
 use js::Object;
 use js::Undefined;
 
+// The largest integer which a number can represent exactly
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
 impl<'js> IntoJs<'js> for Value {
 	fn into_js(self, ctx: Ctx<'js>) -> Result<js::Value<'js>, Error> {
 		(&self).into_js(ctx)
 	}
 }
 
+// NONE is converted to undefined, and NULL is converted to null. A
+// field of an object which is NONE is left out of the object, so that
+// it is absent, in the same way as a field which was never set.
 impl<'js> IntoJs<'js> for &Value {
 	fn into_js(self, ctx: Ctx<'js>) -> Result<js::Value<'js>, Error> {
 		match self {
@@ -25,28 +30,41 @@ impl<'js> IntoJs<'js> for &Value {
 			Value::True => Ok(js::Value::new_bool(ctx, true)),
 			Value::False => Ok(js::Value::new_bool(ctx, false)),
 			Value::Strand(v) => js::String::from_str(ctx, v)?.into_js(ctx),
-			Value::Number(Number::Int(v)) => Ok(js::Value::new_int(ctx, *v as i32)),
-			Value::Number(Number::Float(v)) => Ok(js::Value::new_float(ctx, *v as f64)),
-			Value::Number(Number::Decimal(v)) => match v.is_integer() {
-				true => Ok(js::Value::new_int(ctx, v.to_i32().unwrap_or_default())),
-				false => Ok(js::Value::new_float(ctx, v.to_f64().unwrap_or_default())),
+			// Integers which a number can not represent exactly, outside
+			// of the range of ±(2^53 - 1), are converted to a BigInt
+			Value::Number(Number::Int(v))
+				if !(-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(v) =>
+			{
+				let big: js::Function = ctx.globals().get("BigInt")?;
+				big.call((v.to_string(),))
+			}
+			Value::Number(Number::Int(v)) => match i32::try_from(*v) {
+				Ok(v) => Ok(js::Value::new_int(ctx, v)),
+				Err(_) => Ok(js::Value::new_float(ctx, *v as f64)),
 			},
+			Value::Number(Number::Float(v)) => Ok(js::Value::new_float(ctx, *v as f64)),
+			// A decimal is converted to a Decimal holding its exact text,
+			// as a number can not represent most decimals exactly
+			Value::Number(Number::Decimal(v)) => {
+				let decimal: js::Function = ctx.globals().get("Decimal")?;
+				decimal.construct((v.to_string(),))
+			}
+			// A date only has millisecond precision, so any part of the
+			// datetime smaller than a millisecond is truncated, always
+			// rounding down to the previous millisecond
 			Value::Datetime(v) => {
 				let date: js::Function = ctx.globals().get("Date")?;
 				date.construct((v.0.timestamp_millis(),))
 			}
-			Value::Duration(v) => Ok(Class::<classes::duration::duration::Duration>::instance(
-				ctx,
-				classes::duration::duration::Duration {
-					value: v.to_raw(),
-				},
-			)?
-			.into_value()),
+			Value::Duration(v) => {
+				let duration: js::Function = ctx.globals().get("Duration")?;
+				duration.construct((v.as_secs() as f64, v.subsec_nanos() as f64))
+			}
 			Value::Thing(v) => Ok(Class::<classes::record::record::Record>::instance(
 				ctx,
 				classes::record::record::Record {
 					tb: v.tb.to_owned(),
-					id: v.id.to_raw(),
+					id: v.id.to_owned(),
 				},
 			)?
 			.into_value()),
@@ -57,6 +75,7 @@ impl<'js> IntoJs<'js> for &Value {
 				},
 			)?
 			.into_value()),
+			Value::Geometry(v) => Value::from(v.as_object()).into_js(ctx),
 			Value::Array(v) => {
 				let x = Array::new(ctx)?;
 				for (i, v) in v.iter().enumerate() {
@@ -66,7 +85,7 @@ impl<'js> IntoJs<'js> for &Value {
 			}
 			Value::Object(v) => {
 				let x = Object::new(ctx)?;
-				for (k, v) in v.iter() {
+				for (k, v) in v.iter().filter(|(_, v)| !matches!(v, Value::None)) {
 					x.set(k, v)?;
 				}
 				x.into_js(ctx)
@@ -1,74 +1,579 @@
 use super::classes;
+use super::error::exception;
+use super::error::thrown;
 use super::executor::Executor;
+use super::from;
 use super::globals;
 use super::modules::loader;
 use super::modules::resolver;
+use super::modules::Custom;
+use super::modules::Denied;
 use crate::ctx::Context;
+use crate::dbs::Options;
+use crate::dbs::ScriptCoverage;
+use crate::dbs::Stopwatch;
+use crate::dbs::Transaction;
 use crate::err::Error;
+use crate::sql::script::Script;
+use crate::sql::script::ScriptLang;
 use crate::sql::value::Value;
+use js::Func;
 use js::Function;
+use js::IntoJs;
+use js::Module;
+use js::Persistent;
 use js::Promise;
 use js::Rest;
 use js::This;
+use std::collections::BTreeSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// How many instructions the interpreter runs between each check of the
+// interrupt handler. QuickJS only counts function calls and jumps, such
+// as those which each iteration of a loop makes, so this is not a count
+// of all the instructions which the interpreter runs, but it does not
+// depend on how quickly they are run.
+const INSTRUCTIONS_PER_CHECK: u64 = 10_000;
+
+// The global function which an instrumented script calls with the number
+// of each line which it runs, when the coverage of scripts is recorded
+const COVERAGE: &str = "__coverage";
 
 pub async fn run(
 	ctx: &Context<'_>,
+	opt: &Options,
+	txn: Option<&Transaction>,
+	doc: Option<&Value>,
+	src: &Script,
+	arg: Vec<Value>,
+	bind: Vec<(&str, Value)>,
+) -> Result<Value, Error> {
+	match src.lang() {
+		ScriptLang::JavaScript => execute(ctx, opt, txn, doc, src, None, arg, bind).await,
+	}
+}
+
+// Runs a script which has already been compiled to `code`
+#[allow(clippy::too_many_arguments)]
+pub async fn run_compiled(
+	ctx: &Context<'_>,
+	opt: &Options,
+	txn: Option<&Transaction>,
+	doc: Option<&Value>,
+	src: &Script,
+	code: &Arc<[u8]>,
+	arg: Vec<Value>,
+	bind: Vec<(&str, Value)>,
+) -> Result<Value, Error> {
+	execute(ctx, opt, txn, doc, src, Some(code), arg, bind).await
+}
+
+// Compiles a script to bytecode, which can be run by any runtime
+pub fn compile(src: &Script) -> Result<Arc<[u8]>, Error> {
+	let run = js::Runtime::new()?;
+	let ctx = js::Context::full(&run)?;
+	run.set_loader(resolver(), loader());
+	let res = ctx.with(|ctx| Module::new(ctx, "script", source(src))?.write_object(false));
+	match res {
+		Ok(v) => Ok(v.into()),
+		Err(e) => Err(exception(e, src)),
+	}
+}
+
+// Wraps a script in the main function structure, ensuring that any
+// rejected promise is rejected with an Error object. The name of the
+// value which was thrown is kept, and is empty if it was not an Error.
+fn source(src: &Script) -> String {
+	// Collect the values which the body yields, if it is a generator,
+	// stopping once there are more than the result can hold
+	let (kind, call) = match src.is_generator() {
+		true => ("async function*", "(async (it) => { const out = []; for await (const v of it) { out.push(v); if (out.length >= resultLimit) break; } return out; })(main.apply(this, args))"),
+		false => ("async function", "main.apply(this, args)"),
+	};
+	format!(
+		"let thrownName, resultLimit = Infinity; const main = {} () {{ {} \n}}; export default function(...args) {{ return {}.catch(e => {{ {} }}); }}; export function thrown() {{ return thrownName; }}; export function limit(n) {{ resultLimit = n; }}",
+		kind,
+		src,
+		call,
+		"if (e instanceof Error) { thrownName = String(e.name); throw e; } if (e !== null && typeof e === 'object' && typeof e.message === 'string') { thrownName = typeof e.name === 'string' ? e.name : 'Error'; throw Object.assign(new Error(e.message), { stack: typeof e.stack === 'string' ? e.stack : '' }); } thrownName = ''; throw Object.assign(new Error(String(e)), { stack: '' });",
+	)
+}
+
+// Prepares a runtime for running an embedded function, with a context in
+// which the built-in globals have been installed. The limits, bindings,
+// and globals which depend on the function are only set once it is run.
+pub(crate) fn prepare() -> Result<js::Context, js::Error> {
+	let run = js::Runtime::new()?;
+	let ctx = js::Context::full(&run)?;
+	ctx.with(|ctx| {
+		// Get the context global object
+		let global = ctx.globals();
+		// Register the Duration type as a global class
+		globals::duration::install(ctx)?;
+		// Register the Record type as a global class
+		global.init_def::<classes::record::Record>()?;
+		// Register the Uuid type as a global class
+		global.init_def::<classes::uuid::Uuid>()?;
+		// Register the Decimal type as a global class
+		globals::decimal::install(ctx)?;
+		// Register the surql object, for stringifying typed values
+		globals::surql::install(ctx)
+	})?;
+	Ok(ctx)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn execute(
+	ctx: &Context<'_>,
+	opt: &Options,
+	txn: Option<&Transaction>,
 	doc: Option<&Value>,
-	src: &str,
+	src: &Script,
+	code: Option<&Arc<[u8]>>,
 	arg: Vec<Value>,
+	bind: Vec<(&str, Value)>,
 ) -> Result<Value, Error> {
 	// Check the context
 	if ctx.is_done() {
 		return Ok(Value::None);
 	}
+	// Measure how long each phase of the run takes, if specified
+	let mut watch = Stopwatch::new(opt.scripting.timing);
+	let mut cached = false;
+	// Record the lines which the script runs, if it has not been compiled
+	let covered = match opt.scripting.coverage && code.is_none() {
+		true => Some(src.instrument(COVERAGE)),
+		false => None,
+	};
+	// Errors are still located within the script, as it has the same lines
+	let body = covered.as_ref().map_or(src, |(v, _)| v);
+	// Return the completion value of the body, if specified, which is
+	// compiled and cached as a different body
+	let completed = match opt.scripting.completion && code.is_none() {
+		true => body.returning_completion(),
+		false => None,
+	};
+	let body = completed.as_ref().unwrap_or(body);
+	let executed = Arc::new(Mutex::new(BTreeSet::new()));
+	watch.lap(|t| &mut t.parse);
 	// Create a new agent
 	let exe = Executor::default();
-	// Create an JavaScript context
-	let run = js::Runtime::new().unwrap();
-	// Explicitly set max stack size to 256 KiB
-	run.set_max_stack_size(262_144);
-	// Explicitly set max memory size to 2 MB
-	run.set_memory_limit(2_000_000);
-	// Ensure scripts are cancelled with context
+	// Take a prepared runtime from the pool, or prepare a new one
+	let env = match opt.pool.take() {
+		Some(v) => v,
+		None => prepare()?,
+	};
+	let run = env.runtime().clone();
+	// Explicitly set the max stack size
+	let stack = opt.scripting.stack_size;
+	run.set_max_stack_size(stack);
+	// Explicitly set the max memory size
+	let limit = opt.scripting.memory_limit;
+	run.set_memory_limit(limit);
+	// Ensure scripts are cancelled with context or when timed out
+	let timeout = opt.scripting.timeout;
+	let deadline = Instant::now() + timeout;
 	let cancellation = ctx.cancellation();
-	run.set_interrupt_handler(Some(Box::new(move || cancellation.is_done())));
-	// Create an execution context
-	let ctx = js::Context::full(&run).unwrap();
+	let interrupted = ctx.cancellation();
+	// Count the instructions which the script runs, if they are limited
+	let instructions = opt.scripting.instruction_limit;
+	let checks = Arc::new(AtomicU64::new(0));
+	let counted = checks.clone();
+	let exhausted = move |checks: u64| match instructions {
+		Some(v) => checks.saturating_mul(INSTRUCTIONS_PER_CHECK) > v,
+		None => false,
+	};
+	run.set_interrupt_handler(Some(Box::new(move || {
+		let checks = counted.fetch_add(1, Ordering::Relaxed) + 1;
+		exhausted(checks) || interrupted.is_done() || Instant::now() >= deadline
+	})));
+	// Keep the context, for running any queries which the script makes
+	let qry = ctx;
+	// The queries which the script has made, and which have not yet run
+	let pending = globals::query::Pending::default();
+	// Use the execution context which was prepared
+	let ctx = env;
+	// The host features which the script is permitted to use, which are
+	// those of the datastore if the script has no permissions of its own
+	let perms = src.permissions().unwrap_or(&opt.permissions);
+	let (import, http, query) = (perms.import, perms.http, perms.query);
 	// Set the module resolver and loader
-	run.set_loader(resolver(), loader());
+	match &opt.modules {
+		_ if !import => run.set_loader(Denied, loader()),
+		Some(v) => run.set_loader((resolver(), Custom(v.clone())), (loader(), Custom(v.clone()))),
+		None => run.set_loader(resolver(), loader()),
+	}
 	// Enable async code in the runtime
 	run.spawn_executor(&exe).detach();
-	// Create the main function structure
-	let src = format!("export default async function() {{ {} }}", src);
 	// Attempt to execute the script
-	let res: Result<Promise<Value>, js::Error> = ctx.with(|ctx| {
-		// Get the context global object
-		let global = ctx.globals();
-		// Register the fetch function as a global object
-		global.init_def::<globals::fetch::Fetch>()?;
-		// Register the Duration type as a global class
-		global.init_def::<classes::duration::Duration>()?;
-		// Register the Record type as a global class
-		global.init_def::<classes::record::Record>()?;
-		// Register the Uuid type as a global class
-		global.init_def::<classes::uuid::Uuid>()?;
-		// Attempt to compile the script
-		let res = ctx.compile("script", src)?;
-		// Attempt to fetch the main export
-		let fnc = res.get::<_, Function>("default")?;
-		// Execute the main function
-		fnc.call((This(doc), Rest(arg)))
-	});
+	let res: Result<(Promise<Persistent<js::Value>>, Persistent<Function>), js::Error> =
+		ctx.with(|ctx| {
+			// Get the context global object
+			let global = ctx.globals();
+			// Register the console object as a global object
+			global.set("console", globals::console::console(ctx, &opt.console)?)?;
+			// Register the fetch function as a global object, if permitted
+			if http {
+				global.init_def::<globals::fetch::Fetch>()?;
+			}
+			// Replace the nondeterministic globals if specified
+			if let Some(v) = opt.scripting.deterministic {
+				globals::deterministic::install(ctx, v)?;
+			}
+			// Register the query function, if the script can run queries
+			if opt.scripting.queries && txn.is_some() && query {
+				globals::query::install(ctx, &pending)?;
+			}
+			// Remove any globals which are not available
+			globals::restrict::restrict(ctx, &opt.globals)?;
+			// Keep only the globals which the script is permitted to use
+			globals::restrict::permit(ctx, perms)?;
+			// Register the function which records the lines which run
+			if covered.is_some() {
+				let executed = executed.clone();
+				let record = Func::new(COVERAGE, move |line: u32| {
+					executed.lock().unwrap().insert(line);
+				});
+				global.set(COVERAGE, record)?;
+			}
+			// Freeze the values which are passed in, if specified
+			let freeze = match opt.scripting.freeze_inputs
+				|| bind.iter().any(|(k, _)| opt.bindings.is_frozen(k))
+			{
+				true => Some(globals::freeze::freeze(ctx)?),
+				false => None,
+			};
+			let frozen = |v: &Value| -> Result<js::Value, js::Error> {
+				match &freeze {
+					Some(f) => f.call((v,)),
+					None => v.into_js(ctx),
+				}
+			};
+			let input = |v: &Value| match opt.scripting.freeze_inputs {
+				true => frozen(v),
+				false => v.into_js(ctx),
+			};
+			// Register the bound values as globals
+			for (k, v) in bind {
+				match opt.bindings.is_frozen(k) {
+					true => global.set(k, frozen(&v)?)?,
+					false => global.set(k, input(&v)?)?,
+				}
+			}
+			// Prevent any globals being added, if specified
+			if opt.scripting.seal_globals {
+				globals::seal::seal(ctx)?;
+			}
+			watch.lap(|t| &mut t.bindings);
+			// Use the compiled script, or attempt to fetch it from the cache
+			let res = match code.cloned().or_else(|| opt.cache.get(body)) {
+				Some(v) => {
+					cached = true;
+					Module::read_object(ctx, v)?
+				}
+				None => {
+					let text = source(body);
+					watch.lap(|t| &mut t.parse);
+					// Attempt to compile the script
+					let res = Module::new(ctx, "script", text)?;
+					// Store the compiled script in the cache
+					opt.cache.set(body.clone(), res.write_object(false)?.into());
+					res
+				}
+			};
+			// Attempt to evaluate the script
+			let res = res.eval()?;
+			// Attempt to fetch the main export
+			let fnc = res.get::<_, Function>("default")?;
+			// Keep the function which returns the name of any thrown value
+			let thrown = Persistent::save(ctx, res.get::<_, Function>("thrown")?);
+			// Limit how many values a generator body can yield
+			res.get::<_, Function>("limit")?.call::<_, ()>((opt.scripting.result_limit as f64,))?;
+			watch.lap(|t| &mut t.compile);
+			// Convert the document and the arguments
+			let doc = doc.map(input).transpose()?;
+			let arg = arg.iter().map(input).collect::<Result<Vec<_>, _>>()?;
+			watch.lap(|t| &mut t.bindings);
+			// Execute the main function
+			Ok((fnc.call((This(doc), Rest(arg)))?, thrown))
+		});
+	// Wait for the returned promise to settle
+	let (res, name) = match res {
+		// The script executed successfully
+		Ok((mut v, thrown)) => {
+			let res = loop {
+				// Run the script until it settles, or is waiting
+				if let Some(v) = exe.drive(&mut v) {
+					break Some(v);
+				}
+				// Run any queries which the script is waiting for
+				let requests = std::mem::take(&mut *pending.lock().unwrap());
+				if requests.is_empty() {
+					break None;
+				}
+				for r in requests {
+					let res = match txn {
+						Some(txn) => globals::query::run(qry, opt, txn, &r.sql, r.vars).await,
+						None => Ok(Value::None),
+					};
+					// Settle the promise which the script was given
+					let _ = ctx.with(|ctx| match res {
+						Ok(v) => r.resolve.restore(ctx)?.call::<_, ()>((&v,)),
+						Err(e) => {
+							let err = ctx.globals().get::<_, Function>("Error")?;
+							let err: js::Value = err.call((e.to_string(),))?;
+							r.reject.restore(ctx)?.call::<_, ()>((err,))
+						}
+					});
+				}
+			};
+			watch.lap(|t| &mut t.execute);
+			// Convert the result, unless it holds too many values
+			let res = res.map(|v| {
+				v.and_then(|v| {
+					ctx.with(|ctx| from::limited(ctx, v.restore(ctx)?, opt.scripting.result_limit))
+				})
+			});
+			// Find the name of the value which the script threw, if any
+			let name = match res {
+				Some(Err(_)) => ctx.with(|ctx| thrown.restore(ctx)?.call::<_, Option<String>>(())),
+				_ => Ok(None),
+			};
+			watch.lap(|t| &mut t.marshal);
+			(res, name.ok().flatten())
+		}
+		// There was an error running the script
+		Err(e) => (Some(Err(e)), None),
+	};
+	// Record how long each phase of the run took, if specified
+	if let Some(v) = watch.finish(src.offset(), cached) {
+		opt.timing.push(v);
+	}
+	// Record the lines which the script ran, if specified
+	if let Some((_, lines)) = &covered {
+		opt.coverage.push(ScriptCoverage {
+			offset: src.offset(),
+			lines: lines.clone(),
+			executed: std::mem::take(&mut *executed.lock().unwrap()),
+		});
+	}
 	// Return the script result
 	match res {
-		// The script executed successfully
-		Ok(v) => match exe.run(v).await {
-			// The promise fulfilled successfully
-			Ok(v) => Ok(v),
-			// There was an error awaiting the promise
-			Err(e) => Err(Error::from(e)),
+		// The promise fulfilled successfully
+		Some(Ok(Some(v))) => Ok(v),
+		// The promise fulfilled with too large a result
+		Some(Ok(None)) => Err(Error::ScriptResultTooLarge {
+			limit: opt.scripting.result_limit,
+		}),
+		// The script was interrupted after its instruction limit
+		_ if exhausted(checks.load(Ordering::Relaxed)) => Err(Error::ScriptInstructionLimit {
+			limit: instructions.unwrap_or_default(),
+		}),
+		// The script was interrupted because the query was cancelled
+		_ if cancellation.is_cancelled() => Err(Error::ScriptCancelled),
+		// The script was interrupted after its deadline
+		_ if Instant::now() >= deadline && !cancellation.is_done() => Err(Error::ScriptTimeout {
+			timeout,
+		}),
+		// The script ran out of memory
+		Some(Err(e)) if out_of_memory(&e) => Err(Error::ScriptMemoryLimit {
+			limit,
+		}),
+		// The script exceeded the stack size
+		Some(Err(e)) if stack_overflow(&e) => Err(Error::ScriptStackOverflow {
+			limit: stack,
+		}),
+		Some(Err(e)) => match name {
+			// The script threw a value which it did not catch
+			Some(name) => Err(thrown(e, name, src)),
+			// There was an error running the script
+			None => Err(exception(e, src)),
 		},
-		// There was an error running the script
-		Err(e) => Err(Error::from(e)),
+		// The promise can never be settled
+		None => Err(Error::InvalidScript {
+			message: String::from("The returned promise was never settled"),
+		}),
+	}
+}
+
+// Checks if an error was raised because the memory limit was reached
+fn out_of_memory(e: &js::Error) -> bool {
+	match e {
+		js::Error::Allocation => true,
+		js::Error::Exception {
+			message,
+			..
+		} => message == "out of memory",
+		_ => false,
+	}
+}
+
+// Checks if an error was raised because the stack size was exceeded
+fn stack_overflow(e: &js::Error) -> bool {
+	match e {
+		js::Error::Exception {
+			message,
+			..
+		} => message.starts_with("stack overflow"),
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::dbs::ScriptCache;
+	use crate::dbs::ScriptPool;
+	use crate::sql::ScriptPermissions;
+	use std::sync::Arc;
+	use std::time::Duration;
+
+	#[tokio::test]
+	async fn run_cached() {
+		let ctx = Context::default();
+		let mut opt = Options::default();
+		opt.cache = Arc::new(ScriptCache::new(10));
+		let one = Script::from("return 1;");
+		let two = Script::from("return [2, ...arguments];");
+		let res = run(&ctx, &opt, None, None, &one, vec![], vec![]).await;
+		assert_eq!(res.unwrap(), Value::from(1));
+		let res = run(&ctx, &opt, None, None, &one, vec![], vec![]).await;
+		assert_eq!(res.unwrap(), Value::from(1));
+		let res = run(&ctx, &opt, None, None, &two, vec![Value::from(3)], vec![]).await;
+		assert_eq!(res.unwrap(), Value::from(vec![2, 3]));
+		let res = run(&ctx, &opt, None, None, &two, vec![Value::from(4)], vec![]).await;
+		assert_eq!(res.unwrap(), Value::from(vec![2, 4]));
+		assert_eq!(opt.cache.hits(), 2);
+		assert_eq!(opt.cache.misses(), 2);
+		assert_eq!(opt.cache.len(), 2);
+	}
+
+	#[tokio::test]
+	async fn run_pooled() {
+		let ctx = Context::default();
+		let mut opt = Options::default();
+		opt.pool = Arc::new(ScriptPool::new(1));
+		opt.scripting = opt.scripting.with_timeout(Duration::from_millis(100));
+		let set = Script::from("globalThis.a = 1; return typeof a;");
+		let get = Script::from("return typeof a;");
+		let res = run(&ctx, &opt, None, None, &set, vec![], vec![]).await;
+		assert_eq!(res.unwrap(), Value::from("number"));
+		assert_eq!(filled(&opt.pool), 1);
+		let res = run(&ctx, &opt, None, None, &get, vec![], vec![]).await;
+		assert_eq!(res.unwrap(), Value::from("undefined"));
+		assert_eq!(filled(&opt.pool), 1);
+		let run_forever = Script::from("while(true) {}");
+		let res = run(&ctx, &opt, None, None, &run_forever, vec![], vec![]).await;
+		assert!(matches!(res, Err(Error::ScriptTimeout { .. })));
+		let res = run(&ctx, &opt, None, None, &get, vec![], vec![]).await;
+		assert_eq!(res.unwrap(), Value::from("undefined"));
+		assert_eq!(filled(&opt.pool), 1);
+		// The pooled runtimes already have the built-in globals
+		let env = opt.pool.take().unwrap();
+		let res =
+			env.with(|ctx| ctx.eval::<String, _>("typeof Record + typeof Duration + typeof surql"));
+		assert_eq!(res.unwrap(), "functionfunctionobject");
+	}
+
+	// Wait for the pool to be filled again in the background
+	fn filled(pool: &ScriptPool) -> usize {
+		for _ in 0..1000 {
+			if !pool.is_empty() {
+				break;
+			}
+			std::thread::sleep(Duration::from_millis(1));
+		}
+		pool.len()
+	}
+
+	#[tokio::test]
+	async fn run_isolated() {
+		let ctx = Context::default();
+		let mut opt = Options::default();
+		opt.pool = Arc::new(ScriptPool::new(1));
+		opt.permissions = Arc::new(ScriptPermissions {
+			import: true,
+			..Default::default()
+		});
+		let mut one = opt.clone();
+		one.ns = Some(Arc::from("one"));
+		let mut two = opt.clone();
+		two.ns = Some(Arc::from("two"));
+		let set = Script::from(
+			"globalThis.a = b; Object.prototype.c = 1; JSON.parse = () => 2; const { arch } = await import('os'); arch.d = 3;",
+		);
+		let get = Script::from(
+			"const { arch } = await import('os'); return [typeof a, typeof b, 'c' in {}, JSON.parse('4'), typeof arch.d];",
+		);
+		let val = Value::from(vec![
+			Value::from("undefined"),
+			Value::from("undefined"),
+			Value::from(false),
+			Value::from(4),
+			Value::from("undefined"),
+		]);
+		for _ in 0..3 {
+			let res =
+				run(&ctx, &one, None, None, &set, vec![], vec![("b", Value::from("x"))]).await;
+			assert!(res.is_ok());
+			let res = run(&ctx, &two, None, None, &get, vec![], vec![]).await;
+			assert_eq!(res.unwrap(), val);
+			assert_eq!(filled(&opt.pool), 1);
+		}
+	}
+
+	#[tokio::test]
+	async fn run_permissions() {
+		let ctx = Context::default();
+		let mut opt = Options::default();
+		opt.scripting = opt.scripting.with_queries(true);
+		let get = "return [typeof fetch, typeof Proxy, typeof Math, await import('os').then(() => 'ok', () => 'denied')];";
+		// Without any permissions, the host bindings are not available
+		let src = Script::from(get).with_permissions(ScriptPermissions::default());
+		let res = run(&ctx, &opt, None, None, &src, vec![], vec![]).await;
+		let val = Value::from(vec!["undefined", "undefined", "object", "denied"]);
+		assert_eq!(res.unwrap(), val);
+		// Only the permitted host bindings and globals are available
+		let perms = ScriptPermissions {
+			http: true,
+			import: true,
+			globals: ["Proxy".to_owned()].into(),
+			..Default::default()
+		};
+		let src = Script::from(get).with_permissions(perms.clone());
+		let res = run(&ctx, &opt, None, None, &src, vec![], vec![]).await;
+		let all = Value::from(vec!["function", "function", "object", "ok"]);
+		assert_eq!(res.unwrap(), all);
+		// Without a permission set, the permissions of the datastore are used
+		let src = Script::from(get);
+		let res = run(&ctx, &opt, None, None, &src, vec![], vec![]).await;
+		assert_eq!(res.unwrap(), val);
+		opt.permissions = Arc::new(perms);
+		let res = run(&ctx, &opt, None, None, &src, vec![], vec![]).await;
+		assert_eq!(res.unwrap(), all);
+	}
+
+	#[tokio::test]
+	async fn run_permissions_builtins() {
+		let ctx = Context::default();
+		let opt = Options::default();
+		// The built-in globals are kept, whatever the permissions
+		let src = Script::from(
+			"console.log(typeof Uuid, JSON.stringify(Object.keys({ a: Math.max(1, 2) })), surql.stringify([new Record('a', 1), new Duration('1s')])); throw 'failed';",
+		)
+		.with_permissions(ScriptPermissions::default());
+		let res = run(&ctx, &opt, None, None, &src, vec![], vec![]).await;
+		assert!(matches!(res, Err(Error::ScriptException { message, .. }) if message == "failed"));
+		let logs = opt.console.take();
+		assert_eq!(logs.len(), 1);
+		assert_eq!(
+			logs[0].message,
+			r#"function ["a"] [{"$surql":"record","tb":"a","id":1},{"$surql":"duration","value":"1s"}]"#
+		);
 	}
 }
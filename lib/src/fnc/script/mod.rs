@@ -1,8 +1,13 @@
 #![cfg(feature = "scripting")]
 
+pub use compiled::CompiledScript;
+pub use main::compile;
 pub use main::run;
 
+pub(crate) use main::prepare;
+
 mod classes;
+mod compiled;
 mod error;
 mod executor;
 mod from;
@@ -1,8 +1,16 @@
 pub mod os;
 pub mod surrealdb;
 
+use crate::dbs::Modules;
 use js::BuiltinResolver;
+use js::Ctx;
+use js::Error;
+use js::Loaded;
+use js::Loader;
+use js::Module;
 use js::ModuleLoader;
+use js::Resolver;
+use js::Result;
 
 pub fn resolver() -> BuiltinResolver {
 	BuiltinResolver::default().with_module("os").with_module("surrealdb")
@@ -13,3 +21,33 @@ pub fn loader() -> ModuleLoader {
 		.with_module("os", os::Package)
 		.with_module("surrealdb", surrealdb::Package)
 }
+
+// Resolves no modules, for scripts which are not permitted to import
+pub struct Denied;
+
+impl Resolver for Denied {
+	fn resolve<'js>(&mut self, _: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+		Err(Error::new_resolving(base, name))
+	}
+}
+
+// Resolves and loads the modules supplied by the embedder
+pub struct Custom(pub Modules);
+
+impl Resolver for Custom {
+	fn resolve<'js>(&mut self, _: Ctx<'js>, base: &str, name: &str) -> Result<String> {
+		match self.0 .0.source(name) {
+			Some(_) => Ok(name.to_owned()),
+			None => Err(Error::new_resolving(base, name)),
+		}
+	}
+}
+
+impl Loader for Custom {
+	fn load<'js>(&mut self, ctx: Ctx<'js>, name: &str) -> Result<Module<'js, Loaded>> {
+		match self.0 .0.source(name) {
+			Some(v) => Ok(Module::new(ctx, name, v)?.into_loaded()),
+			None => Err(Error::new_loading(name)),
+		}
+	}
+}
@@ -1,15 +1,24 @@
 use super::tx::Transaction;
+use crate::cnf;
+use crate::ctx::Canceller;
 use crate::ctx::Context;
 use crate::dbs::Attach;
 use crate::dbs::Executor;
+use crate::dbs::ModuleSource;
+use crate::dbs::Modules;
 use crate::dbs::Options;
 use crate::dbs::Response;
+use crate::dbs::ScriptCache;
+use crate::dbs::ScriptGlobals;
+use crate::dbs::ScriptPool;
+use crate::dbs::Scripting;
 use crate::dbs::Session;
 use crate::dbs::Variables;
 use crate::err::Error;
 use crate::kvs::LOG;
 use crate::sql;
 use crate::sql::Query;
+use crate::sql::ScriptPermissions;
 use crate::sql::Value;
 use channel::Sender;
 use futures::lock::Mutex;
@@ -18,6 +27,12 @@ use std::sync::Arc;
 /// The underlying datastore instance which stores the dataset.
 pub struct Datastore {
 	pub(super) inner: Inner,
+	pub(super) scripting: Scripting,
+	pub(super) modules: Option<Modules>,
+	pub(super) cache: Arc<ScriptCache>,
+	pub(super) globals: Arc<ScriptGlobals>,
+	pub(super) permissions: Arc<ScriptPermissions>,
+	pub(super) pool: Arc<ScriptPool>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -77,9 +92,9 @@ impl Datastore {
 			#[cfg(feature = "kv-mem")]
 			"memory" => {
 				info!(target: LOG, "Starting kvs store in {}", path);
-				let v = super::mem::Datastore::new().await.map(|v| Datastore {
-					inner: Inner::Mem(v),
-				});
+				let v = super::mem::Datastore::new()
+					.await
+					.map(|v| Datastore::from_inner(Inner::Mem(v)));
 				info!(target: LOG, "Started kvs store in {}", path);
 				v
 			}
@@ -89,9 +104,9 @@ impl Datastore {
 				info!(target: LOG, "Starting kvs store at {}", path);
 				let s = s.trim_start_matches("file://");
 				let s = s.trim_start_matches("file:");
-				let v = super::rocksdb::Datastore::new(s).await.map(|v| Datastore {
-					inner: Inner::RocksDB(v),
-				});
+				let v = super::rocksdb::Datastore::new(s)
+					.await
+					.map(|v| Datastore::from_inner(Inner::RocksDB(v)));
 				info!(target: LOG, "Started kvs store at {}", path);
 				v
 			}
@@ -101,9 +116,9 @@ impl Datastore {
 				info!(target: LOG, "Starting kvs store at {}", path);
 				let s = s.trim_start_matches("rocksdb://");
 				let s = s.trim_start_matches("rocksdb:");
-				let v = super::rocksdb::Datastore::new(s).await.map(|v| Datastore {
-					inner: Inner::RocksDB(v),
-				});
+				let v = super::rocksdb::Datastore::new(s)
+					.await
+					.map(|v| Datastore::from_inner(Inner::RocksDB(v)));
 				info!(target: LOG, "Started kvs store at {}", path);
 				v
 			}
@@ -113,9 +128,9 @@ impl Datastore {
 				info!(target: LOG, "Starting kvs store at {}", path);
 				let s = s.trim_start_matches("indxdb://");
 				let s = s.trim_start_matches("indxdb:");
-				let v = super::indxdb::Datastore::new(s).await.map(|v| Datastore {
-					inner: Inner::IndxDB(v),
-				});
+				let v = super::indxdb::Datastore::new(s)
+					.await
+					.map(|v| Datastore::from_inner(Inner::IndxDB(v)));
 				info!(target: LOG, "Started kvs store at {}", path);
 				v
 			}
@@ -125,9 +140,9 @@ impl Datastore {
 				info!(target: LOG, "Connecting to kvs store at {}", path);
 				let s = s.trim_start_matches("tikv://");
 				let s = s.trim_start_matches("tikv:");
-				let v = super::tikv::Datastore::new(s).await.map(|v| Datastore {
-					inner: Inner::TiKV(v),
-				});
+				let v = super::tikv::Datastore::new(s)
+					.await
+					.map(|v| Datastore::from_inner(Inner::TiKV(v)));
 				info!(target: LOG, "Connected to kvs store at {}", path);
 				v
 			}
@@ -137,9 +152,9 @@ impl Datastore {
 				info!(target: LOG, "Connecting to kvs store at {}", path);
 				let s = s.trim_start_matches("fdb://");
 				let s = s.trim_start_matches("fdb:");
-				let v = super::fdb::Datastore::new(s).await.map(|v| Datastore {
-					inner: Inner::FDB(v),
-				});
+				let v = super::fdb::Datastore::new(s)
+					.await
+					.map(|v| Datastore::from_inner(Inner::FDB(v)));
 				info!(target: LOG, "Connected to kvs store at {}", path);
 				v
 			}
@@ -148,6 +163,126 @@ impl Datastore {
 		}
 	}
 
+	// Creates a datastore for a storage engine, with the default options
+	// for embedded functions
+	fn from_inner(inner: Inner) -> Datastore {
+		Datastore {
+			inner,
+			scripting: Scripting::default(),
+			modules: None,
+			cache: Arc::new(ScriptCache::new(cnf::SCRIPT_CACHE_SIZE)),
+			globals: Arc::new(ScriptGlobals::default()),
+			permissions: Arc::new(ScriptPermissions::default()),
+			pool: Arc::new(ScriptPool::new(cnf::SCRIPT_POOL_SIZE)),
+		}
+	}
+
+	// Sets the options for embedded functions run on this datastore, with
+	// any limits and bindings of the session
+	fn apply_scripting(&self, opt: &mut Options, sess: &Session) {
+		opt.scripting = sess.scripting.unwrap_or(self.scripting);
+		opt.bindings = sess.bindings.clone();
+		opt.modules = self.modules.clone();
+		opt.cache = self.cache.clone();
+		opt.globals = self.globals.clone();
+		opt.permissions = self.permissions.clone();
+		opt.pool = self.pool.clone();
+	}
+
+	/// Set the default limits for embedded functions run on this datastore
+	///
+	/// ```rust,no_run
+	/// # use std::time::Duration;
+	/// # use surrealdb::Datastore;
+	/// # use surrealdb::Error;
+	/// # use surrealdb::Scripting;
+	/// # #[tokio::main]
+	/// # async fn main() -> Result<(), Error> {
+	/// let scripting = Scripting::default().with_timeout(Duration::from_secs(1));
+	/// let ds = Datastore::new("memory").await?.with_scripting(scripting);
+	/// # Ok(())
+	/// # }
+	/// ```
+	pub fn with_scripting(mut self, scripting: Scripting) -> Self {
+		self.scripting = scripting;
+		self
+	}
+
+	/// Allow embedded functions run on this datastore to import modules
+	///
+	/// ```rust,no_run
+	/// # use surrealdb::Datastore;
+	/// # use surrealdb::Error;
+	/// # use surrealdb::MemoryModules;
+	/// # #[tokio::main]
+	/// # async fn main() -> Result<(), Error> {
+	/// let modules = MemoryModules::new().with_module("fn::util", "export const two = 2;");
+	/// let ds = Datastore::new("memory").await?.with_modules(modules);
+	/// # Ok(())
+	/// # }
+	/// ```
+	pub fn with_modules<M>(mut self, modules: M) -> Self
+	where
+		M: ModuleSource + 'static,
+	{
+		self.modules = Some(Modules(Arc::new(modules)));
+		self
+	}
+
+	/// Set how many compiled embedded functions are cached by this datastore
+	///
+	/// A capacity of zero disables the cache.
+	pub fn with_script_cache(mut self, capacity: usize) -> Self {
+		self.cache = Arc::new(ScriptCache::new(capacity));
+		self
+	}
+
+	/// Set how many bytes of compiled embedded functions are cached by this datastore
+	///
+	/// The least recently used functions are evicted once the bytecode in
+	/// the cache is longer than the budget, which can be sized using
+	/// [`CompiledScript::bytecode_len`](crate::CompiledScript::bytecode_len).
+	/// A budget of zero disables the cache.
+	pub fn with_script_cache_bytes(mut self, budget: usize) -> Self {
+		self.cache = Arc::new(ScriptCache::with_budget(budget));
+		self
+	}
+
+	/// Get how many bytes of compiled embedded functions are cached by this datastore
+	pub fn script_cache_bytes(&self) -> usize {
+		self.cache.bytes()
+	}
+
+	/// Set how many runtimes for embedded functions are created ahead of time by this datastore
+	///
+	/// Each function call takes a runtime from the pool, which is then filled
+	/// again in the background, and a runtime is never returned to the pool
+	/// once it has been used. A capacity of zero prepares each runtime when
+	/// the function is called.
+	pub fn with_script_pool(mut self, capacity: usize) -> Self {
+		self.pool = Arc::new(ScriptPool::new(capacity));
+		self
+	}
+
+	/// Set which globals are available to embedded functions run on this datastore
+	///
+	/// By default, `eval` and `Function` are removed.
+	pub fn with_globals(mut self, globals: ScriptGlobals) -> Self {
+		self.globals = Arc::new(globals);
+		self
+	}
+
+	/// Set the permissions of embedded functions run on this datastore
+	///
+	/// These apply to each embedded function which has not been given
+	/// permissions of its own, with [`Script::with_permissions`](sql::Script::with_permissions).
+	/// By default, these permit nothing, so such a function can not run
+	/// queries, make HTTP requests, or import modules.
+	pub fn with_script_permissions(mut self, permissions: ScriptPermissions) -> Self {
+		self.permissions = Arc::new(permissions);
+		self
+	}
+
 	/// Create a new transaction on this datastore
 	///
 	/// *You must ensure that a [`Transaction`] does not ever outlive a [`Datastore`] instance.*
@@ -231,13 +366,49 @@ impl Datastore {
 		sess: &Session,
 		vars: Variables,
 		strict: bool,
+	) -> Result<Vec<Response>, Error> {
+		self.execute_cancellable(txt, sess, vars, strict, &Canceller::default()).await
+	}
+
+	/// Parse and execute an SQL query, which can be cancelled while it runs
+	///
+	/// Once the canceller is cancelled, any embedded function which is
+	/// running is interrupted, and fails with [`Error::ScriptCancelled`].
+	///
+	/// ```rust,no_run
+	/// use surrealdb::Canceller;
+	/// use surrealdb::Datastore;
+	/// use surrealdb::Error;
+	/// use surrealdb::Session;
+	///
+	/// #[tokio::main]
+	/// async fn main() -> Result<(), Error> {
+	///     let ds = Datastore::new("memory").await?;
+	///     let ses = Session::for_kv();
+	///     let ast = "USE NS test DB test; RETURN function() { while (true) {} };";
+	///     let canceller = Canceller::default();
+	///     let cancel = canceller.clone();
+	///     std::thread::spawn(move || cancel.cancel());
+	///     let res = ds.execute_cancellable(ast, &ses, None, false, &canceller).await?;
+	///     Ok(())
+	/// }
+	/// ```
+	pub async fn execute_cancellable(
+		&self,
+		txt: &str,
+		sess: &Session,
+		vars: Variables,
+		strict: bool,
+		canceller: &Canceller,
 	) -> Result<Vec<Response>, Error> {
 		// Create a new query options
 		let mut opt = Options::default();
 		// Create a new query executor
 		let mut exe = Executor::new(self);
 		// Create a default context
-		let ctx = Context::default();
+		let mut ctx = Context::default();
+		// Cancel the context with the canceller
+		ctx.add_canceller(canceller);
 		// Start an execution context
 		let ctx = sess.context(ctx);
 		// Store the query variables
@@ -253,6 +424,8 @@ impl Datastore {
 		opt.db = sess.db();
 		// Set strict config
 		opt.strict = strict;
+		// Set the embedded function options
+		self.apply_scripting(&mut opt, sess);
 		// Process all statements
 		exe.execute(ctx, opt, ast).await
 	}
@@ -300,6 +473,8 @@ impl Datastore {
 		opt.db = sess.db();
 		// Set strict config
 		opt.strict = strict;
+		// Set the embedded function options
+		self.apply_scripting(&mut opt, sess);
 		// Process all statements
 		exe.execute(ctx, opt, ast).await
 	}
@@ -348,6 +523,8 @@ impl Datastore {
 		opt.db = sess.db();
 		// Set strict config
 		opt.strict = strict;
+		// Set the embedded function options
+		self.apply_scripting(&mut opt, sess);
 		// Compute the value
 		let res = val.compute(&ctx, &opt, &txn, None).await?;
 		// Store any data
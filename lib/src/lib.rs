@@ -27,10 +27,23 @@ mod kvs;
 pub mod sql;
 
 // Exports
+pub use ctx::Canceller;
 pub use dbs::Auth;
+pub use dbs::Bindings;
+pub use dbs::DeterministicSeed;
+pub use dbs::LogEntry;
+pub use dbs::LogLevel;
+pub use dbs::MemoryModules;
+pub use dbs::ModuleSource;
 pub use dbs::Response;
+pub use dbs::ScriptCoverage;
+pub use dbs::ScriptGlobals;
+pub use dbs::ScriptTiming;
+pub use dbs::Scripting;
 pub use dbs::Session;
 pub use err::Error;
+#[cfg(feature = "scripting")]
+pub use fnc::script::CompiledScript;
 pub use kvs::Datastore;
 pub use kvs::Key;
 pub use kvs::Transaction;
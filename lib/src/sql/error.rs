@@ -6,6 +6,35 @@ use thiserror::Error;
 #[derive(Error, Debug)]
 pub enum Error<I> {
 	ParserError(I),
+	ScriptError {
+		// The input at the point where the problem starts
+		input: I,
+		// The byte offset of the problem within the script body
+		offset: usize,
+		// The kind of problem
+		kind: ScriptErrorKind,
+		// A description of the problem
+		message: &'static str,
+	},
+}
+
+// The kind of problem which was found in a script body
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScriptErrorKind {
+	// A string or template literal was never closed
+	UnterminatedString,
+	// A block comment was never closed
+	UnterminatedComment,
+	// A block was never closed
+	UnclosedBlock,
+	// The body was nested more deeply than allowed
+	DepthExceeded,
+	// The body was longer than allowed
+	TooLong,
+	// A quote style which is disabled was used
+	DisabledQuote,
+	// Any other problem, which is described by the message
+	Invalid,
 }
 
 pub type IResult<I, O, E = Error<I>> = Result<(I, O), Err<E>>;
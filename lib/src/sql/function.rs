@@ -5,7 +5,9 @@ use crate::err::Error;
 use crate::fnc;
 use crate::sql::comment::mightbespace;
 use crate::sql::common::commas;
+use crate::sql::error::Error::ScriptError;
 use crate::sql::error::IResult;
+use crate::sql::error::ScriptErrorKind;
 use crate::sql::fmt::Fmt;
 use crate::sql::script::{script as func, Script};
 use crate::sql::value::{single, value, Value};
@@ -13,6 +15,7 @@ use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::char;
 use nom::multi::separated_list0;
+use nom::Err;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
@@ -105,6 +108,22 @@ impl Function {
 }
 
 impl Function {
+	// Compiles the embedded functions within this function, without running them
+	pub(crate) fn validate(&self) -> Result<(), Error> {
+		match self {
+			Self::Future(v) => v.validate(),
+			Self::Cast(_, v) => v.validate(),
+			Self::Normal(_, x) => x.iter().try_for_each(Value::validate),
+			#[allow(unused_variables)]
+			Self::Script(s, x) => {
+				x.iter().try_for_each(Value::validate)?;
+				#[cfg(feature = "scripting")]
+				fnc::script::compile(s)?;
+				Ok(())
+			}
+		}
+	}
+
 	pub(crate) async fn compute(
 		&self,
 		ctx: &Context<'_>,
@@ -142,7 +161,11 @@ impl Function {
 					for v in x {
 						a.push(v.compute(ctx, opt, txn, doc).await?);
 					}
-					fnc::script::run(ctx, doc, s, a).await
+					let mut b = Vec::with_capacity(opt.bindings.len());
+					for (k, v) in opt.bindings.iter() {
+						b.push((k.as_str(), v.compute(ctx, opt, txn, doc).await?));
+					}
+					fnc::script::run(ctx, opt, Some(txn), doc, s, a, b).await
 				}
 				#[cfg(not(feature = "scripting"))]
 				{
@@ -160,9 +183,10 @@ impl fmt::Display for Function {
 		match self {
 			Self::Future(ref e) => write!(f, "<future> {{ {} }}", e),
 			Self::Cast(ref s, ref e) => write!(f, "<{}> {}", s, e),
-			Self::Script(ref s, ref e) => {
-				write!(f, "function({}) {{{}}}", Fmt::comma_separated(e), s)
-			}
+			Self::Script(ref s, ref e) => match s.ends_in_comment() {
+				true => write!(f, "function({}) {{{}\n}}", Fmt::comma_separated(e), s),
+				false => write!(f, "function({}) {{{}}}", Fmt::comma_separated(e), s),
+			},
 			Self::Normal(ref s, ref e) => write!(f, "{}({})", s, Fmt::comma_separated(e)),
 		}
 	}
@@ -186,13 +210,31 @@ fn script(i: &str) -> IResult<&str, Function> {
 	let (i, _) = alt((tag("fn::script"), tag("fn"), tag("function")))(i)?;
 	let (i, _) = mightbespace(i)?;
 	let (i, _) = tag("(")(i)?;
+	let (i, _) = mightbespace(i)?;
 	let (i, a) = separated_list0(commas, value)(i)?;
+	let (i, _) = mightbespace(i)?;
 	let (i, _) = tag(")")(i)?;
 	let (i, _) = mightbespace(i)?;
-	let (i, _) = char('{')(i)?;
-	let (i, v) = func(i)?;
-	let (i, _) = char('}')(i)?;
-	Ok((i, Function::Script(v, a)))
+	let (s, _) = char('{')(i)?;
+	// A body which is cut off is reported at its opening brace
+	let unclosed = || {
+		Err::Failure(ScriptError {
+			input: i,
+			offset: 0,
+			kind: ScriptErrorKind::UnclosedBlock,
+			message: "unclosed block in script",
+		})
+	};
+	let (s, v) = match func(s) {
+		Err(Err::Failure(ScriptError {
+			kind: ScriptErrorKind::UnclosedBlock,
+			..
+		})) => return Err(unclosed()),
+		Ok(("", _)) => return Err(unclosed()),
+		res => res?,
+	};
+	let (s, _) = char('}')(s)?;
+	Ok((s, Function::Script(v, a)))
 }
 
 fn future(i: &str) -> IResult<&str, Function> {
@@ -217,7 +259,7 @@ fn cast(i: &str) -> IResult<&str, Function> {
 	Ok((i, Function::Cast(s.to_string(), v)))
 }
 
-fn function_casts(i: &str) -> IResult<&str, &str> {
+pub(crate) fn function_casts(i: &str) -> IResult<&str, &str> {
 	alt((
 		tag("bool"),
 		tag("int"),
@@ -557,4 +599,14 @@ mod tests {
 			)
 		);
 	}
+
+	#[test]
+	fn function_script_empty() {
+		let sql = "function() {}";
+		let res = function(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("function() {}", format!("{}", out));
+		assert_eq!(out, Function::Script(Script::parse(""), vec![]));
+	}
 }
@@ -2,7 +2,9 @@ use crate::sql::comment::mightbespace;
 use crate::sql::common::commas;
 use crate::sql::error::IResult;
 use crate::sql::fmt::Fmt;
+use crate::sql::object::Object;
 use crate::sql::serde::is_internal_serialization;
+use crate::sql::value::Value;
 use geo::algorithm::contains::Contains;
 use geo::algorithm::intersects::Intersects;
 use geo::{LineString, Point, Polygon};
@@ -251,6 +253,131 @@ impl Geometry {
 			Self::Collection(v) => v.iter().all(|x| x.intersects(other)),
 		}
 	}
+
+	// -----------------------------------
+	// GeoJSON conversion
+	// -----------------------------------
+
+	/// Converts this geometry into a GeoJSON object
+	pub fn as_object(&self) -> Object {
+		let mut v = Object::default();
+		match self {
+			Self::Collection(w) => {
+				v.insert("type".to_owned(), "GeometryCollection".into());
+				let w: Vec<Value> = w.iter().map(|w| Value::from(w.as_object())).collect();
+				v.insert("geometries".to_owned(), w.into());
+			}
+			w => {
+				v.insert("type".to_owned(), w.as_type().into());
+				v.insert("coordinates".to_owned(), w.as_coordinates());
+			}
+		}
+		v
+	}
+
+	/// Converts a GeoJSON object into a geometry, if it is valid
+	pub fn from_object(v: &Object) -> Option<Geometry> {
+		let kind = match v.get("type") {
+			Some(Value::Strand(v)) => v.as_str(),
+			_ => return None,
+		};
+		if v.len() != 2 {
+			return None;
+		}
+		if kind == "GeometryCollection" {
+			return match v.get("geometries") {
+				Some(Value::Array(v)) => v
+					.iter()
+					.map(|v| match v {
+						Value::Object(v) => Geometry::from_object(v),
+						_ => None,
+					})
+					.collect::<Option<Vec<_>>>()
+					.map(Geometry::Collection),
+				_ => None,
+			};
+		}
+		let v = v.get("coordinates")?;
+		match kind {
+			"Point" => coords_point(v).map(Geometry::Point),
+			"LineString" => coords_line(v).map(Geometry::Line),
+			"Polygon" => coords_polygon(v).map(Geometry::Polygon),
+			"MultiPoint" => {
+				coords_list(v, coords_point).map(|v| Geometry::MultiPoint(MultiPoint(v)))
+			}
+			"MultiLineString" => {
+				coords_list(v, coords_line).map(|v| Geometry::MultiLine(MultiLineString(v)))
+			}
+			"MultiPolygon" => {
+				coords_list(v, coords_polygon).map(|v| Geometry::MultiPolygon(MultiPolygon(v)))
+			}
+			_ => None,
+		}
+	}
+
+	fn as_type(&self) -> &'static str {
+		match self {
+			Self::Point(_) => "Point",
+			Self::Line(_) => "LineString",
+			Self::Polygon(_) => "Polygon",
+			Self::MultiPoint(_) => "MultiPoint",
+			Self::MultiLine(_) => "MultiLineString",
+			Self::MultiPolygon(_) => "MultiPolygon",
+			Self::Collection(_) => "GeometryCollection",
+		}
+	}
+
+	fn as_coordinates(&self) -> Value {
+		fn point(v: &Point<f64>) -> Value {
+			vec![Value::from(v.x()), Value::from(v.y())].into()
+		}
+		fn line(v: &LineString<f64>) -> Value {
+			v.points().map(|v| point(&v)).collect::<Vec<Value>>().into()
+		}
+		fn polygon(v: &Polygon<f64>) -> Value {
+			let mut rings = vec![line(v.exterior())];
+			rings.extend(v.interiors().iter().map(line));
+			rings.into()
+		}
+		match self {
+			Self::Point(v) => point(v),
+			Self::Line(v) => line(v),
+			Self::Polygon(v) => polygon(v),
+			Self::MultiPoint(v) => v.iter().map(point).collect::<Vec<Value>>().into(),
+			Self::MultiLine(v) => v.iter().map(line).collect::<Vec<Value>>().into(),
+			Self::MultiPolygon(v) => v.iter().map(polygon).collect::<Vec<Value>>().into(),
+			Self::Collection(v) => {
+				v.iter().map(|v| v.as_coordinates()).collect::<Vec<Value>>().into()
+			}
+		}
+	}
+}
+
+fn coords_list<T>(v: &Value, f: fn(&Value) -> Option<T>) -> Option<Vec<T>> {
+	match v {
+		Value::Array(v) => v.iter().map(f).collect(),
+		_ => None,
+	}
+}
+
+fn coords_point(v: &Value) -> Option<Point<f64>> {
+	match v {
+		Value::Array(v) => match v.as_slice() {
+			[Value::Number(x), Value::Number(y)] => Some((x.to_float(), y.to_float()).into()),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+fn coords_line(v: &Value) -> Option<LineString<f64>> {
+	coords_list(v, coords_point).map(LineString::from)
+}
+
+fn coords_polygon(v: &Value) -> Option<Polygon<f64>> {
+	let mut rings = coords_list(v, coords_line)?.into_iter();
+	let exterior = rings.next()?;
+	Some(Polygon::new(exterior, rings.collect()))
 }
 
 impl fmt::Display for Geometry {
@@ -878,6 +1005,22 @@ mod tests {
 
 	use super::*;
 
+	#[test]
+	fn geojson_object() {
+		let exterior = LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+		let interior = LineString::from(vec![(1.0, 1.0), (2.0, 1.0), (2.0, 2.0)]);
+		let out = Geometry::from(Polygon::new(exterior, vec![interior]));
+		let obj = out.as_object();
+		assert_eq!(
+			"{ coordinates: [[[0, 0], [10, 0], [10, 10], [0, 0]], [[1, 1], [2, 1], [2, 2], [1, 1]]], type: \"Polygon\" }",
+			format!("{}", Value::from(obj.clone()))
+		);
+		assert_eq!(Geometry::from_object(&obj), Some(out));
+		let mut obj = obj;
+		obj.insert("other".to_owned(), Value::None);
+		assert_eq!(Geometry::from_object(&obj), None);
+	}
+
 	#[test]
 	fn simple() {
 		let sql = "(51.509865, -0.118092)";
@@ -0,0 +1,459 @@
+use crate::sql::array::Array;
+use crate::sql::cond::Cond;
+use crate::sql::data::Data;
+use crate::sql::edges::Edges;
+use crate::sql::expression::Expression;
+use crate::sql::fetch::Fetchs;
+use crate::sql::field::{Field, Fields};
+use crate::sql::function::Function;
+use crate::sql::graph::Graph;
+use crate::sql::group::Groups;
+use crate::sql::id::Id;
+use crate::sql::idiom::{Idiom, Idioms};
+use crate::sql::object::Object;
+use crate::sql::order::Orders;
+use crate::sql::output::Output;
+use crate::sql::part::Part;
+use crate::sql::permission::{Permission, Permissions};
+use crate::sql::query::Query;
+use crate::sql::range::Range;
+use crate::sql::script::Script;
+use crate::sql::split::Splits;
+use crate::sql::statement::{Statement, Statements};
+use crate::sql::statements::{
+	CreateStatement, DefineEventStatement, DefineFieldStatement, DefineScopeStatement,
+	DefineStatement, DefineTableStatement, DeleteStatement, IfelseStatement, InsertStatement,
+	LiveStatement, OutputStatement, RelateStatement, SelectStatement, SetStatement,
+	UpdateStatement,
+};
+use crate::sql::subquery::Subquery;
+use crate::sql::thing::Thing;
+use crate::sql::value::{Value, Values};
+use crate::sql::view::View;
+
+// Resolves where each script body began within a query, once the whole
+// query has been parsed, given the length of the query. Each script body
+// is visited wherever it appears within the parsed query.
+pub(crate) trait Locate {
+	fn locate(&mut self, len: usize);
+}
+
+impl<T: Locate> Locate for Vec<T> {
+	fn locate(&mut self, len: usize) {
+		self.iter_mut().for_each(|v| v.locate(len))
+	}
+}
+
+impl<T: Locate> Locate for Option<T> {
+	fn locate(&mut self, len: usize) {
+		if let Some(v) = self {
+			v.locate(len)
+		}
+	}
+}
+
+impl<T: Locate> Locate for Box<T> {
+	fn locate(&mut self, len: usize) {
+		self.as_mut().locate(len)
+	}
+}
+
+impl Locate for Script {
+	fn locate(&mut self, len: usize) {
+		Script::locate(self, len)
+	}
+}
+
+impl Locate for Query {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Statements {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Statement {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Set(v) => v.locate(len),
+			Self::Live(v) => v.locate(len),
+			Self::Output(v) => v.locate(len),
+			Self::Ifelse(v) => v.locate(len),
+			Self::Select(v) => v.locate(len),
+			Self::Create(v) => v.locate(len),
+			Self::Update(v) => v.locate(len),
+			Self::Relate(v) => v.locate(len),
+			Self::Delete(v) => v.locate(len),
+			Self::Insert(v) => v.locate(len),
+			Self::Define(v) => v.locate(len),
+			_ => (),
+		}
+	}
+}
+
+impl Locate for SetStatement {
+	fn locate(&mut self, len: usize) {
+		self.what.locate(len)
+	}
+}
+
+impl Locate for LiveStatement {
+	fn locate(&mut self, len: usize) {
+		self.expr.locate(len);
+		self.what.locate(len);
+		self.cond.locate(len);
+		self.fetch.locate(len);
+	}
+}
+
+impl Locate for OutputStatement {
+	fn locate(&mut self, len: usize) {
+		self.what.locate(len)
+	}
+}
+
+impl Locate for IfelseStatement {
+	fn locate(&mut self, len: usize) {
+		for (cond, then) in self.exprs.iter_mut() {
+			cond.locate(len);
+			then.locate(len);
+		}
+		self.close.locate(len);
+	}
+}
+
+impl Locate for SelectStatement {
+	fn locate(&mut self, len: usize) {
+		self.expr.locate(len);
+		self.what.locate(len);
+		self.cond.locate(len);
+		self.split.locate(len);
+		self.group.locate(len);
+		self.order.locate(len);
+		self.fetch.locate(len);
+	}
+}
+
+impl Locate for CreateStatement {
+	fn locate(&mut self, len: usize) {
+		self.what.locate(len);
+		self.data.locate(len);
+		self.output.locate(len);
+	}
+}
+
+impl Locate for UpdateStatement {
+	fn locate(&mut self, len: usize) {
+		self.what.locate(len);
+		self.data.locate(len);
+		self.cond.locate(len);
+		self.output.locate(len);
+	}
+}
+
+impl Locate for RelateStatement {
+	fn locate(&mut self, len: usize) {
+		self.from.locate(len);
+		self.with.locate(len);
+		self.data.locate(len);
+		self.output.locate(len);
+	}
+}
+
+impl Locate for DeleteStatement {
+	fn locate(&mut self, len: usize) {
+		self.what.locate(len);
+		self.cond.locate(len);
+		self.output.locate(len);
+	}
+}
+
+impl Locate for InsertStatement {
+	fn locate(&mut self, len: usize) {
+		self.data.locate(len);
+		self.update.locate(len);
+		self.output.locate(len);
+	}
+}
+
+impl Locate for DefineStatement {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Scope(v) => v.locate(len),
+			Self::Table(v) => v.locate(len),
+			Self::Event(v) => v.locate(len),
+			Self::Field(v) => v.locate(len),
+			_ => (),
+		}
+	}
+}
+
+impl Locate for DefineScopeStatement {
+	fn locate(&mut self, len: usize) {
+		self.signup.locate(len);
+		self.signin.locate(len);
+	}
+}
+
+impl Locate for DefineTableStatement {
+	fn locate(&mut self, len: usize) {
+		self.view.locate(len);
+		self.permissions.locate(len);
+	}
+}
+
+impl Locate for DefineEventStatement {
+	fn locate(&mut self, len: usize) {
+		self.when.locate(len);
+		self.then.locate(len);
+	}
+}
+
+impl Locate for DefineFieldStatement {
+	fn locate(&mut self, len: usize) {
+		self.name.locate(len);
+		self.value.locate(len);
+		self.assert.locate(len);
+		self.permissions.locate(len);
+	}
+}
+
+impl Locate for View {
+	fn locate(&mut self, len: usize) {
+		self.expr.locate(len);
+		self.cond.locate(len);
+		self.group.locate(len);
+	}
+}
+
+impl Locate for Permissions {
+	fn locate(&mut self, len: usize) {
+		self.select.locate(len);
+		self.create.locate(len);
+		self.update.locate(len);
+		self.delete.locate(len);
+	}
+}
+
+impl Locate for Permission {
+	fn locate(&mut self, len: usize) {
+		if let Self::Specific(v) = self {
+			v.locate(len)
+		}
+	}
+}
+
+impl Locate for Data {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::SetExpression(v) | Self::UpdateExpression(v) => {
+				for (idiom, _, value) in v.iter_mut() {
+					idiom.locate(len);
+					value.locate(len);
+				}
+			}
+			Self::ValuesExpression(v) => {
+				for (idiom, value) in v.iter_mut().flatten() {
+					idiom.locate(len);
+					value.locate(len);
+				}
+			}
+			Self::PatchExpression(v)
+			| Self::MergeExpression(v)
+			| Self::ReplaceExpression(v)
+			| Self::ContentExpression(v)
+			| Self::SingleExpression(v) => v.locate(len),
+			Self::EmptyExpression => (),
+		}
+	}
+}
+
+impl Locate for Output {
+	fn locate(&mut self, len: usize) {
+		if let Self::Fields(v) = self {
+			v.locate(len)
+		}
+	}
+}
+
+impl Locate for Fields {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Field {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Alone(v) => v.locate(len),
+			Self::Alias(v, i) => {
+				v.locate(len);
+				i.locate(len);
+			}
+			Self::All => (),
+		}
+	}
+}
+
+impl Locate for Cond {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Splits {
+	fn locate(&mut self, len: usize) {
+		self.0.iter_mut().for_each(|v| v.0.locate(len))
+	}
+}
+
+impl Locate for Groups {
+	fn locate(&mut self, len: usize) {
+		self.0.iter_mut().for_each(|v| v.0.locate(len))
+	}
+}
+
+impl Locate for Orders {
+	fn locate(&mut self, len: usize) {
+		self.0.iter_mut().for_each(|v| v.order.locate(len))
+	}
+}
+
+impl Locate for Fetchs {
+	fn locate(&mut self, len: usize) {
+		self.0.iter_mut().for_each(|v| v.0.locate(len))
+	}
+}
+
+impl Locate for Idioms {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Idiom {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Part {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Where(v) => v.locate(len),
+			Self::Thing(v) => v.locate(len),
+			Self::Graph(v) => v.locate(len),
+			_ => (),
+		}
+	}
+}
+
+impl Locate for Graph {
+	fn locate(&mut self, len: usize) {
+		self.cond.locate(len);
+		self.alias.locate(len);
+	}
+}
+
+impl Locate for Values {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Value {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Array(v) => v.locate(len),
+			Self::Object(v) => v.locate(len),
+			Self::Idiom(v) => v.locate(len),
+			Self::Thing(v) => v.locate(len),
+			Self::Range(v) => v.locate(len),
+			Self::Edges(v) => v.locate(len),
+			Self::Function(v) => v.locate(len),
+			Self::Subquery(v) => v.locate(len),
+			Self::Expression(v) => v.locate(len),
+			_ => (),
+		}
+	}
+}
+
+impl Locate for Array {
+	fn locate(&mut self, len: usize) {
+		self.0.locate(len)
+	}
+}
+
+impl Locate for Object {
+	fn locate(&mut self, len: usize) {
+		self.0.values_mut().for_each(|v| v.locate(len))
+	}
+}
+
+impl Locate for Thing {
+	fn locate(&mut self, len: usize) {
+		self.id.locate(len)
+	}
+}
+
+impl Locate for Id {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Array(v) => v.locate(len),
+			Self::Object(v) => v.locate(len),
+			_ => (),
+		}
+	}
+}
+
+impl Locate for Range {
+	fn locate(&mut self, len: usize) {
+		self.beg.locate(len);
+		self.end.locate(len);
+	}
+}
+
+impl Locate for Edges {
+	fn locate(&mut self, len: usize) {
+		self.from.locate(len)
+	}
+}
+
+impl Locate for Function {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Future(v) | Self::Cast(_, v) => v.locate(len),
+			Self::Normal(_, a) => a.locate(len),
+			Self::Script(s, a) => {
+				s.locate(len);
+				a.locate(len);
+			}
+		}
+	}
+}
+
+impl Locate for Subquery {
+	fn locate(&mut self, len: usize) {
+		match self {
+			Self::Value(v) => v.locate(len),
+			Self::Ifelse(v) => v.locate(len),
+			Self::Select(v) => v.locate(len),
+			Self::Create(v) => v.locate(len),
+			Self::Update(v) => v.locate(len),
+			Self::Delete(v) => v.locate(len),
+			Self::Relate(v) => v.locate(len),
+			Self::Insert(v) => v.locate(len),
+		}
+	}
+}
+
+impl Locate for Expression {
+	fn locate(&mut self, len: usize) {
+		self.l.locate(len);
+		self.r.locate(len);
+	}
+}
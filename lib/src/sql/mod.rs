@@ -26,6 +26,7 @@ pub(crate) mod ident;
 pub(crate) mod idiom;
 pub(crate) mod kind;
 pub(crate) mod limit;
+pub(crate) mod locate;
 pub(crate) mod model;
 pub(crate) mod number;
 pub(crate) mod object;
@@ -105,7 +106,33 @@ pub use self::permission::Permissions;
 pub use self::query::Query;
 pub use self::range::Range;
 pub use self::regex::Regex;
+pub use self::script::EditorPosition;
+pub use self::script::EditorRange;
+pub use self::script::EditorSpans;
+pub use self::script::EmbeddedSql;
+pub use self::script::LiteralSpan;
 pub use self::script::Script;
+pub use self::script::ScriptAnalysis;
+pub use self::script::ScriptBody;
+pub use self::script::ScriptBuilder;
+pub use self::script::ScriptBuilderError;
+pub use self::script::ScriptCapabilities;
+pub use self::script::ScriptError;
+pub use self::script::ScriptInfo;
+pub use self::script::ScriptInterner;
+pub use self::script::ScriptLang;
+pub use self::script::ScriptOptions;
+pub use self::script::ScriptParser;
+pub use self::script::ScriptPart;
+pub use self::script::ScriptPermissions;
+pub use self::script::ScriptRef;
+pub use self::script::ScriptState;
+pub use self::script::ScriptWriter;
+pub use self::script::StatementSpan;
+pub use self::script::Token;
+pub use self::script::TokenDiff;
+pub use self::script::TokenKind;
+pub use self::script::TokenKindPattern;
 pub use self::split::Split;
 pub use self::split::Splits;
 pub use self::start::Start;
@@ -1,6 +1,8 @@
 use crate::err::Error;
 use crate::sql::error::Error::ParserError;
+use crate::sql::error::Error::ScriptError;
 use crate::sql::error::IResult;
+use crate::sql::locate::Locate;
 use crate::sql::query::{query, Query};
 use crate::sql::thing::Thing;
 use crate::sql::value::Value;
@@ -8,7 +10,10 @@ use nom::Err;
 use std::str;
 
 pub fn parse(input: &str) -> Result<Query, Error> {
-	parse_impl(input, query)
+	let mut query = parse_impl(input, query)?;
+	// Each script only knows how much of the query follows its body
+	query.locate(input.len());
+	Ok(query)
 }
 
 pub fn thing(input: &str) -> Result<Thing, Error> {
@@ -25,7 +30,11 @@ fn parse_impl<O>(input: &str, parser: impl Fn(&str) -> IResult<&str, O>) -> Resu
 		_ => match parser(input) {
 			Ok((_, parsed)) => Ok(parsed),
 			Err(Err::Error(e)) | Err(Err::Failure(e)) => match e {
-				ParserError(e) => {
+				ParserError(e)
+				| ScriptError {
+					input: e,
+					..
+				} => {
 					let (s, l, c) = locate(input, e);
 					Err(Error::InvalidQuery {
 						line: l,
@@ -104,6 +113,121 @@ mod tests {
 		assert!(res.is_err());
 	}
 
+	#[test]
+	fn parse_unterminated_script_string() {
+		let sql = "CREATE test SET value = function() {\n\treturn 'oops;\n}";
+		let res = parse(sql);
+		assert!(matches!(
+			res,
+			Err(Error::InvalidQuery {
+				line: 2,
+				char: 8,
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn parse_unclosed_script_block() {
+		let sql = "CREATE test SET value = function() {\n\tif (a) {\n\t\treturn { a: 1 };\n}";
+		let res = parse(sql);
+		assert!(matches!(
+			res,
+			Err(Error::InvalidQuery {
+				line: 1,
+				char: 35,
+				..
+			})
+		));
+		let sql = "CREATE test SET value = function() {\n\tif (a) {\n\t\treturn { a: 1 ";
+		let res = parse(sql);
+		assert!(matches!(
+			res,
+			Err(Error::InvalidQuery {
+				line: 1,
+				char: 35,
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn parse_script_offset() {
+		use crate::sql::statement::Statement;
+		use crate::sql::{Function, Value};
+		let sql = "SELECT * FROM test;\nRETURN function() { return 1; };";
+		let res = parse(sql).unwrap();
+		let offset = match &res.0 .0[1] {
+			Statement::Output(v) => match &v.what {
+				Value::Function(f) => match f.as_ref() {
+					Function::Script(s, _) => s.offset(),
+					_ => None,
+				},
+				_ => None,
+			},
+			_ => None,
+		};
+		assert_eq!(offset, Some(39));
+		assert_eq!(&sql[39..49], " return 1;");
+	}
+
+	#[test]
+	fn parse_script_offset_signature() {
+		use crate::sql::statement::Statement;
+		use crate::sql::{Data, Function, Value};
+		let sql =
+			"CREATE person SET value = function(\n\t$name,\n\t$age\n)\n{\n\treturn $name;\n};";
+		let res = parse(sql).unwrap();
+		let offset = match &res.0 .0[0] {
+			Statement::Create(v) => match &v.data {
+				Some(Data::SetExpression(v)) => match &v[0].2 {
+					Value::Function(f) => match f.as_ref() {
+						Function::Script(s, _) => s.offset(),
+						_ => None,
+					},
+					_ => None,
+				},
+				_ => None,
+			},
+			_ => None,
+		};
+		assert_eq!(offset, Some(53));
+		assert_eq!(&sql[..53], "CREATE person SET value = function(\n\t$name,\n\t$age\n)\n{");
+		assert_eq!(&sql[53..68], "\n\treturn $name;");
+	}
+
+	#[test]
+	fn parse_script_offset_nested() {
+		use crate::sql::statement::Statement;
+		use crate::sql::{Function, Subquery, Value};
+		let sql = "IF true THEN (SELECT * FROM test WHERE [{ a: function() { return 1; } }]) END";
+		let res = parse(sql).unwrap();
+		let offset = match &res.0 .0[0] {
+			Statement::Ifelse(v) => match &v.exprs[0].1 {
+				Value::Subquery(v) => match v.as_ref() {
+					Subquery::Select(v) => match &v.cond.as_ref().unwrap().0 {
+						Value::Array(v) => match &v[0] {
+							Value::Object(v) => match v.get("a") {
+								Some(Value::Function(f)) => match f.as_ref() {
+									Function::Script(s, _) => s.offset(),
+									_ => None,
+								},
+								_ => None,
+							},
+							_ => None,
+						},
+						_ => None,
+					},
+					_ => None,
+				},
+				_ => None,
+			},
+			_ => None,
+		};
+		assert_eq!(offset, Some(57));
+		assert_eq!(&sql[57..67], " return 1;");
+	}
+
 	#[test]
 	fn parser_try() {
 		let sql = "
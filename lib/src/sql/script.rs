@@ -1,153 +1,7185 @@
+use crate::cnf;
+use crate::sql::common::val_char;
+use crate::sql::error::Error;
+use crate::sql::error::Error::ParserError;
 use crate::sql::error::IResult;
+use crate::sql::error::ScriptErrorKind;
+use crate::sql::function::{function_casts, Function};
+use crate::sql::idiom::Idiom;
+use crate::sql::param::Param;
+use crate::sql::value::Value;
+use memchr::{memchr, memchr2, memmem};
 use nom::branch::alt;
-use nom::bytes::complete::escaped;
 use nom::bytes::complete::is_not;
 use nom::bytes::complete::tag;
-use nom::character::complete::one_of;
+use nom::bytes::complete::take_while;
+use nom::character::complete::anychar;
+use nom::combinator::not;
 use nom::combinator::recognize;
-use nom::multi::many1;
-use serde::{Deserialize, Serialize};
+use nom::multi::many0;
+use nom::sequence::pair;
+use nom::Err;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt::{self, Display, Formatter};
-use std::ops::Deref;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::ops::{Deref, Range};
 use std::str;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
-const SINGLE: &str = r#"'"#;
-const SINGLE_ESC: &str = r#"\'"#;
+const BACKTICK_ESC: &str = r#"\`$"#;
 
-const DOUBLE: &str = r#"""#;
-const DOUBLE_ESC: &str = r#"\""#;
+const TEMPLATE_BEG: &str = "${";
 
-const BACKTICK: &str = r#"`"#;
-const BACKTICK_ESC: &str = r#"\`"#;
+const SLASH: &str = "/";
 
-const OBJECT_BEG: &str = "{";
-const OBJECT_END: &str = "}";
+const COMMENT_LINE: &str = "//";
 
-#[derive(Clone, Debug, Default, Eq, PartialEq, PartialOrd, Serialize, Deserialize)]
-pub struct Script(pub String);
+const HASHBANG: &str = "#!";
 
+const BOM: &str = "\u{feff}";
+
+const COMMENT_BEG: &str = "/*";
+const COMMENT_END: &str = "*/";
+
+const REGEX_CLASS_BEG: &str = "[";
+const REGEX_CLASS_END: &str = "]";
+
+// The keywords after which a `/` starts a regex literal rather than a division.
+const REGEX_KEYWORDS: [&str; 14] = [
+	"await",
+	"case",
+	"delete",
+	"do",
+	"else",
+	"in",
+	"instanceof",
+	"new",
+	"of",
+	"return",
+	"throw",
+	"typeof",
+	"void",
+	"yield",
+];
+
+// The keywords which begin a statement which is not an expression.
+const STATEMENT_KEYWORDS: [&str; 20] = [
+	"break", "class", "const", "continue", "debugger", "do", "export", "for", "function", "if",
+	"import", "let", "return", "switch", "throw", "try", "var", "while", "with", "yield",
+];
+
+// The keywords and literals which are never free identifiers.
+const JS_KEYWORDS: [&str; 47] = [
+	"arguments",
+	"async",
+	"await",
+	"break",
+	"case",
+	"catch",
+	"class",
+	"const",
+	"continue",
+	"debugger",
+	"default",
+	"delete",
+	"do",
+	"else",
+	"export",
+	"extends",
+	"false",
+	"finally",
+	"for",
+	"function",
+	"if",
+	"import",
+	"in",
+	"instanceof",
+	"let",
+	"new",
+	"null",
+	"of",
+	"return",
+	"static",
+	"super",
+	"switch",
+	"this",
+	"throw",
+	"true",
+	"try",
+	"typeof",
+	"undefined",
+	"var",
+	"void",
+	"while",
+	"with",
+	"yield",
+	"NaN",
+	"Infinity",
+	"get",
+	"set",
+];
+
+// The globals which are available to every script.
+const JS_BUILTINS: [&str; 38] = [
+	"Array",
+	"ArrayBuffer",
+	"BigInt",
+	"Boolean",
+	"DataView",
+	"Date",
+	"Duration",
+	"Error",
+	"EvalError",
+	"Float32Array",
+	"Float64Array",
+	"Int16Array",
+	"Int32Array",
+	"Int8Array",
+	"JSON",
+	"Map",
+	"Math",
+	"Number",
+	"Object",
+	"Promise",
+	"Proxy",
+	"RangeError",
+	"Record",
+	"ReferenceError",
+	"Reflect",
+	"RegExp",
+	"Set",
+	"String",
+	"Symbol",
+	"SyntaxError",
+	"TypeError",
+	"Uint8Array",
+	"Uuid",
+	"WeakMap",
+	"WeakSet",
+	"fetch",
+	"globalThis",
+	"parseInt",
+];
+
+// The globals which reach beyond the script itself, such as the network,
+// timers, or code built at runtime, whether or not the runtime provides them.
+const JS_GLOBALS: [&str; 22] = [
+	"Blob",
+	"Function",
+	"Headers",
+	"Request",
+	"Response",
+	"WebAssembly",
+	"WebSocket",
+	"Worker",
+	"XMLHttpRequest",
+	"clearImmediate",
+	"clearInterval",
+	"clearTimeout",
+	"eval",
+	"fetch",
+	"globalThis",
+	"importScripts",
+	"process",
+	"queueMicrotask",
+	"require",
+	"setImmediate",
+	"setInterval",
+	"setTimeout",
+];
+
+// The operators of more than one character, longest first, so that a run
+// of punctuation can be split into operators as JavaScript splits it.
+const JS_OPERATORS: [&str; 33] = [
+	">>>=", "...", "===", "!==", "**=", "<<=", ">>=", ">>>", "&&=", "||=", "??=", "=>", "==", "!=",
+	"<=", ">=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "**", "<<", ">>", "&&", "||", "??",
+	"?.", "++", "--",
+];
+
+// The operators which assign to their left operand.
+const JS_ASSIGNMENTS: [&str; 16] = [
+	"=", "+=", "-=", "*=", "/=", "%=", "**=", "<<=", ">>=", ">>>=", "&=", "|=", "^=", "&&=", "||=",
+	"??=",
+];
+
+/// The body of an embedded JavaScript function.
+///
+/// When the function runs, the current record is bound to `this`, so a
+/// computed field can use `return this.price * 1.2;`. The record is copied
+/// into plain JavaScript values: objects become objects, arrays become
+/// arrays, and record ids, durations and uuids become instances of the
+/// `Record`, `Duration` and `Uuid` classes. Where there is no current
+/// record, such as in a `RETURN` statement, `this` is `undefined`. Any
+/// arguments are available through `arguments`.
+///
+/// The function returns the value of the `return` statement which it runs.
+/// If it runs none, it returns `NONE`, unless the completion value of the
+/// body is returned, as set with
+/// [`Scripting::with_completion`](crate::Scripting::with_completion), in
+/// which case it returns the value of its last statement, if that is an
+/// expression, as described in [`Script::completion`]. A value of
+/// `undefined` is always returned as `NONE`, as is a function, so a body
+/// which is only an arrow function returns `NONE`, as described in
+/// [`Script::is_arrow_function`].
+/// A number which is `NaN`, `Infinity` or `-Infinity` can not be held by
+/// a SurrealQL number, so returning one, whether on its own or within an
+/// array or object, fails with an error rather than returning a number.
+#[derive(Clone, Debug, Default)]
+pub struct Script(pub ScriptBody, Offset, ScriptLang, Option<ScriptPermissions>);
+
+// A script without permissions is serialized as its body, as it was before
+// scripts could have permissions, and a script with permissions is
+// serialized along with them. Either form can be deserialized.
+#[derive(Serialize, Deserialize)]
+struct Permitted<B, P> {
+	body: B,
+	permissions: P,
+}
+
+impl Serialize for Script {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match &self.3 {
+			None => self.0.serialize(serializer),
+			Some(v) => Permitted {
+				body: &self.0,
+				permissions: v,
+			}
+			.serialize(serializer),
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for Script {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		deserializer.deserialize_any(ScriptVisitor)
+	}
+}
+
+struct ScriptVisitor;
+
+impl<'de> Visitor<'de> for ScriptVisitor {
+	type Value = Script;
+	fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+		f.write_str("a script body, or a script body with permissions")
+	}
+	fn visit_str<E: de::Error>(self, v: &str) -> Result<Script, E> {
+		Ok(Script::new_unchecked(v))
+	}
+	fn visit_string<E: de::Error>(self, v: String) -> Result<Script, E> {
+		Ok(Script::new_unchecked(v))
+	}
+	fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Script, A::Error> {
+		let v: Permitted<String, _> = Deserialize::deserialize(SeqAccessDeserializer::new(seq))?;
+		Ok(Script::new_unchecked(v.body).with_permissions(v.permissions))
+	}
+	fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Script, A::Error> {
+		let v: Permitted<String, _> = Deserialize::deserialize(MapAccessDeserializer::new(map))?;
+		Ok(Script::new_unchecked(v.body).with_permissions(v.permissions))
+	}
+}
+
+/// The text of a script body, which is either owned by the script, or
+/// shared with the other scripts with the same body which were interned by
+/// a [`ScriptInterner`]. Either way, a body is compared, hashed, ordered
+/// and serialized exactly as its text is.
+#[derive(Clone)]
+pub enum ScriptBody {
+	Owned(String),
+	Interned(Arc<str>),
+}
+
+impl ScriptBody {
+	pub fn as_str(&self) -> &str {
+		match self {
+			Self::Owned(v) => v,
+			Self::Interned(v) => v,
+		}
+	}
+}
+
+impl Default for ScriptBody {
+	fn default() -> Self {
+		Self::Owned(String::new())
+	}
+}
+
+impl From<String> for ScriptBody {
+	fn from(v: String) -> Self {
+		Self::Owned(v)
+	}
+}
+
+impl From<Arc<str>> for ScriptBody {
+	fn from(v: Arc<str>) -> Self {
+		Self::Interned(v)
+	}
+}
+
+impl Deref for ScriptBody {
+	type Target = str;
+	fn deref(&self) -> &Self::Target {
+		self.as_str()
+	}
+}
+
+impl PartialEq for ScriptBody {
+	fn eq(&self, other: &Self) -> bool {
+		self.as_str() == other.as_str()
+	}
+}
+
+impl Eq for ScriptBody {}
+
+impl PartialOrd for ScriptBody {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for ScriptBody {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.as_str().cmp(other.as_str())
+	}
+}
+
+impl Hash for ScriptBody {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.as_str().hash(state)
+	}
+}
+
+impl PartialEq<str> for ScriptBody {
+	fn eq(&self, other: &str) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl PartialEq<&str> for ScriptBody {
+	fn eq(&self, other: &&str) -> bool {
+		self.as_str() == *other
+	}
+}
+
+impl PartialEq<String> for ScriptBody {
+	fn eq(&self, other: &String) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl fmt::Debug for ScriptBody {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self.as_str(), f)
+	}
+}
+
+impl Display for ScriptBody {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		Display::fmt(self.as_str(), f)
+	}
+}
+
+impl Serialize for ScriptBody {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.as_str())
+	}
+}
+
+impl<'de> Deserialize<'de> for ScriptBody {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		String::deserialize(deserializer).map(Self::Owned)
+	}
+}
+
+/// A set of script bodies which are shared between scripts, so that the
+/// scripts with the same body, such as the computed fields of many records,
+/// hold one allocation of it between them, rather than one each. Clones of
+/// an interner share the same set. A body is kept in the set until it is
+/// purged, once no script uses it, so an interner should only be used for
+/// bodies which are likely to be repeated.
+///
+/// ```rust
+/// # use surrealdb::sql::{Script, ScriptInterner};
+/// let interner = ScriptInterner::default();
+/// let a = interner.intern(&Script::from("return 1;"));
+/// let b = interner.intern(&Script::from("return 1;"));
+/// assert!(a.is_interned() && a == b);
+/// assert_eq!(a.as_str().as_ptr(), b.as_str().as_ptr());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ScriptInterner {
+	min_len: usize,
+	inner: Arc<Mutex<HashSet<Arc<str>>>>,
+}
+
+impl ScriptInterner {
+	/// Creates an interner which leaves any body shorter than the given
+	/// number of bytes owned by its script, as sharing a short body saves
+	/// little, if anything
+	pub fn with_min_len(bytes: usize) -> ScriptInterner {
+		ScriptInterner {
+			min_len: bytes,
+			inner: Arc::default(),
+		}
+	}
+	/// Returns the script with its body shared with every other script the
+	/// interner has returned with the same body, adding the body to the
+	/// interner if it is not yet held
+	pub fn intern(&self, script: &Script) -> Script {
+		let mut out = script.clone();
+		if script.len() < self.min_len {
+			return out;
+		}
+		let mut set = self.inner.lock().unwrap();
+		let body = match set.get(script.as_str()) {
+			Some(v) => v.clone(),
+			None => {
+				let v: Arc<str> = Arc::from(script.as_str());
+				set.insert(v.clone());
+				v
+			}
+		};
+		out.0 = ScriptBody::Interned(body);
+		out
+	}
+	/// Returns the number of bodies which the interner holds
+	pub fn len(&self) -> usize {
+		self.inner.lock().unwrap().len()
+	}
+	/// Checks whether the interner holds no bodies
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	/// Removes the bodies which are no longer used by any script, returning
+	/// how many were removed
+	pub fn purge(&self) -> usize {
+		let mut set = self.inner.lock().unwrap();
+		let len = set.len();
+		set.retain(|v| Arc::strong_count(v) > 1);
+		len - set.len()
+	}
+}
+
+/// The language which the body of an embedded function is written in.
+///
+/// JavaScript is the only language for now, so the language is not part
+/// of the serialized form of a script, which is only its body.
+#[derive(
+	Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize,
+)]
+pub enum ScriptLang {
+	#[default]
+	JavaScript,
+}
+
+/// The host features which an embedded function can use, in addition to
+/// the limits which apply to every embedded function. A feature is only
+/// available if it is available to every embedded function, and is also
+/// permitted here. The default permits nothing.
+///
+/// A function which may not query does not get `surrealdb.query()`, one
+/// which may not use HTTP does not get `fetch`, and one which may not
+/// import can not import any module. The built-in objects of the language,
+/// such as `Object`, `JSON` and `Math`, along with `console` and the
+/// SurrealDB classes, are always kept. Of the other globals, such as
+/// `Proxy` and `eval`, only those in the allowlist are kept.
+///
+/// Permissions are attached to a script by the embedder, and are kept when
+/// the script is serialized. A script without permissions of its own uses
+/// the permissions of the datastore, which permit nothing by default, so a
+/// script which was stored without permissions is restricted when it is
+/// read back.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct ScriptPermissions {
+	/// Whether the function can run queries
+	pub query: bool,
+	/// Whether the function can make HTTP requests with `fetch`
+	pub http: bool,
+	/// Whether the function can import modules
+	pub import: bool,
+	/// The other globals which are kept
+	pub globals: BTreeSet<String>,
+}
+
+// Where a script body began within the query it was parsed from. While
+// the query is parsed, only the length of the query from the start of the
+// body onward is known, which is resolved to an offset from the start of
+// the query once the whole query has been parsed. This is not part of the
+// script itself, so it is ignored when scripts are compared, hashed, or
+// serialized.
+#[derive(Clone, Copy, Debug, Default)]
+enum Offset {
+	#[default]
+	None,
+	// The number of bytes from the start of the body to the end of the query
+	Remaining(usize),
+	// The byte offset of the body within the query
+	At(usize),
+}
+
+// Only the script body is compared, ordered and hashed, exactly as a
+// `str` is, so that a map keyed by scripts can be searched with a `&str`
+// through `Borrow<str>`. The language and permissions are ignored.
+impl PartialEq for Script {
+	fn eq(&self, other: &Self) -> bool {
+		self.0 == other.0
+	}
+}
+
+impl Eq for Script {}
+
+impl PartialOrd for Script {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Script {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.0.cmp(&other.0)
+	}
+}
+
+impl Hash for Script {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.0.as_str().hash(state)
+	}
+}
+
+// With the `strict-scripts` feature, a script can only be made from text
+// without checking it by calling `Script::new_unchecked`
+#[cfg(not(feature = "strict-scripts"))]
 impl From<String> for Script {
 	fn from(s: String) -> Self {
-		Self(s)
+		Self::new_unchecked(s)
+	}
+}
+
+#[cfg(not(feature = "strict-scripts"))]
+impl From<&str> for Script {
+	fn from(s: &str) -> Self {
+		Self::new_unchecked(s)
+	}
+}
+
+// An owned body is used as it is, and only a borrowed body is copied
+#[cfg(not(feature = "strict-scripts"))]
+impl From<Cow<'_, str>> for Script {
+	fn from(s: Cow<'_, str>) -> Self {
+		Self::new_unchecked(s)
+	}
+}
+
+impl Deref for Script {
+	type Target = str;
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+
+impl Borrow<str> for Script {
+	fn borrow(&self) -> &str {
+		self.0.as_str()
+	}
+}
+
+impl AsRef<str> for Script {
+	fn as_ref(&self) -> &str {
+		self.0.as_str()
+	}
+}
+
+impl AsRef<[u8]> for Script {
+	fn as_ref(&self) -> &[u8] {
+		self.0.as_bytes()
+	}
+}
+
+impl Script {
+	/// Creates a script from a body, checking that it parses, so that a
+	/// body which does not parse is rejected when the script is made,
+	/// rather than when it is first run
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// assert!(Script::new("return { a: 1 };").is_ok());
+	/// assert!(Script::new("return { a: 1 ;").is_err());
+	/// ```
+	pub fn new<S: Into<String>>(s: S) -> Result<Script, ScriptError> {
+		let s = s.into();
+		Script::validate(&s)?;
+		Ok(Script::new_unchecked(s))
+	}
+	/// Creates a script from a body without checking that it parses, as
+	/// the `From` conversions do. With the `strict-scripts` feature, the
+	/// `From` conversions are removed, so this is the only way to make a
+	/// script which has not been checked.
+	pub fn new_unchecked<S: Into<String>>(s: S) -> Script {
+		Script(ScriptBody::Owned(s.into()), Offset::default(), ScriptLang::default(), None)
+	}
+	/// Returns the script body as a string slice
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("return true;");
+	/// assert_eq!(script.as_str(), "return true;");
+	/// ```
+	pub fn as_str(&self) -> &str {
+		self.0.as_str()
+	}
+	/// Checks whether the script body is shared with other scripts, having
+	/// been interned by a [`ScriptInterner`]
+	pub fn is_interned(&self) -> bool {
+		matches!(self.0, ScriptBody::Interned(_))
+	}
+	/// Returns the length of the script body in bytes
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("return 'é';");
+	/// assert_eq!(script.len(), 12);
+	/// ```
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+	/// Checks whether the script body is empty
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// assert!(Script::from("").is_empty());
+	/// assert!(!Script::from("return;").is_empty());
+	/// ```
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+	/// Returns an iterator over the lines of the script body
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 1;\nreturn a;");
+	/// assert_eq!(script.lines().collect::<Vec<_>>(), vec!["let a = 1;", "return a;"]);
+	/// ```
+	pub fn lines(&self) -> std::str::Lines<'_> {
+		self.0.lines()
+	}
+	/// Rebuilds the script with each of its lines replaced by the result
+	/// of `f`, keeping the line break which each line ended with. The new
+	/// script is checked again, and is rejected if it no longer parses.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 1;\r\nreturn a;\n");
+	/// let out = script.map_lines(|l| format!("\t{}", l)).unwrap();
+	/// assert_eq!(out, "\tlet a = 1;\r\n\treturn a;\n");
+	/// assert!(script.map_lines(|l| l.replace(';', " {")).is_err());
+	/// ```
+	pub fn map_lines<F>(&self, mut f: F) -> Result<Script, ScriptError>
+	where
+		F: FnMut(&str) -> String,
+	{
+		let mut out = String::with_capacity(self.0.len());
+		for v in self.0.split_inclusive('\n') {
+			let l = v.strip_suffix('\n').map_or(v, |v| v.strip_suffix('\r').unwrap_or(v));
+			out.push_str(&f(l));
+			out.push_str(&v[l.len()..]);
+		}
+		Script::from_str(&out)
+	}
+	/// Builds a script from lines, joining them with line breaks. The
+	/// script is checked, and is rejected if it does not parse.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from_lines(["let a = 1;", "return a;"]).unwrap();
+	/// assert_eq!(script, "let a = 1;\nreturn a;");
+	/// assert!(Script::from_lines(["return {"]).is_err());
+	/// ```
+	pub fn from_lines<I, S>(lines: I) -> Result<Script, ScriptError>
+	where
+		I: IntoIterator<Item = S>,
+		S: AsRef<str>,
+	{
+		let mut out = String::new();
+		for (i, v) in lines.into_iter().enumerate() {
+			if i > 0 {
+				out.push('\n');
+			}
+			out.push_str(v.as_ref());
+		}
+		Script::from_str(&out)
+	}
+	/// Checks whether two scripts differ only by comments and insignificant
+	/// whitespace, by comparing their minified forms. A line break which
+	/// may end a statement is significant, and so is anything in a literal.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let a = Script::from("let a = 1; // one\nreturn a;");
+	/// assert!(a.semantically_eq(&Script::from("let a=1;\n\treturn a;")));
+	/// assert!(!a.semantically_eq(&Script::from("let a = 2; return a;")));
+	/// ```
+	pub fn semantically_eq(&self, other: &Script) -> bool {
+		self == other || self.minify() == other.minify()
+	}
+	/// Returns a canonical form of the script, with comments removed and
+	/// insignificant whitespace collapsed. Literals are left untouched,
+	/// and a script which does not parse is returned unchanged.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 'x  y'; // comment\n\treturn a;");
+	/// assert_eq!(script.minify(), "let a='x  y';return a;");
+	/// ```
+	pub fn minify(&self) -> Script {
+		match minify(&self.0) {
+			Some(v) => Script::new_unchecked(v),
+			None => self.clone(),
+		}
+	}
+	/// Returns a checksum of the minified script, so that scripts which only
+	/// differ in comments and whitespace have the same checksum. This is the
+	/// start of a SHA-256 digest, so it is the same on every platform and
+	/// release, and can be stored, unlike the `Hash` of the script.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let a = Script::from("return  1; // one");
+	/// assert_eq!(a.checksum(), Script::from("return 1;").checksum());
+	/// assert_ne!(a.checksum(), Script::from("return 2;").checksum());
+	/// ```
+	pub fn checksum(&self) -> u64 {
+		let val = Sha256::digest(self.minify().0.as_bytes());
+		let mut out = [0; 8];
+		out.copy_from_slice(&val[..8]);
+		u64::from_be_bytes(out)
+	}
+	/// Returns the script with each string literal, and each static part of
+	/// an untagged template literal, written with the fewest escapes which
+	/// give the same value, so `'\x41'`, `'\u0041'` and `'A'` are all written
+	/// as `'A'`. Quotes, backslashes, line breaks and control characters are
+	/// escaped, and everything else is written as it is. The code outside
+	/// of the literals is left untouched, as is any literal whose escapes
+	/// are not valid, and a script which does not parse is returned unchanged.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from(r"return '\x41\u{42}' + `\u0043${d}`;");
+	/// assert_eq!(script.canonicalize_literals(), "return 'AB' + `C${d}`;");
+	/// ```
+	pub fn canonicalize_literals(&self) -> Script {
+		if Script::validate(&self.0).is_err() {
+			return self.clone();
+		}
+		let scanned = self.scanned();
+		// The start of each static part of a tagged template literal, as the
+		// tag receives the raw text, so that text can not be changed
+		let mut tagged = BTreeSet::new();
+		let mut open = Vec::new();
+		let mut last: Option<(TokenKind, &str)> = None;
+		for t in scanned.tokens.iter() {
+			let v = &self.0[t.span.clone()];
+			match t.kind {
+				TokenKind::Whitespace | TokenKind::Comment => continue,
+				TokenKind::TemplateLiteral => {
+					if v.starts_with('`') {
+						open.push(match last {
+							None => false,
+							Some((TokenKind::Punct, p)) => matches!(p, ")" | "]" | "}"),
+							Some((TokenKind::Identifier, w)) => !REGEX_KEYWORDS.contains(&w),
+							Some(_) => true,
+						});
+					}
+					if open.last() == Some(&true) {
+						tagged.insert(t.span.start + 1);
+					}
+					if v.len() > 1 && v.ends_with('`') {
+						open.pop();
+					}
+				}
+				_ => (),
+			}
+			last = Some((t.kind, v));
+		}
+		let mut out = String::with_capacity(self.0.len());
+		let mut pos = 0;
+		for lit in string_literals(&self.0, &scanned.literals) {
+			let raw = &self.0[lit.span.clone()];
+			let quote = match lit.kind {
+				TokenKind::StringLiteral => self.0[..lit.span.start].chars().next_back(),
+				_ if tagged.contains(&lit.span.start) => None,
+				_ => Some('`'),
+			};
+			let quote = match (quote, valid_escapes(raw)) {
+				(Some(quote), true) => quote,
+				_ => continue,
+			};
+			// An unpaired surrogate can not be written without an escape
+			if lit.value.contains(char::REPLACEMENT_CHARACTER)
+				&& !raw.contains(char::REPLACEMENT_CHARACTER)
+			{
+				continue;
+			}
+			out.push_str(&self.0[pos..lit.span.start]);
+			out.push_str(&escape(&lit.value, quote));
+			pos = lit.span.end;
+		}
+		out.push_str(&self.0[pos..]);
+		Script::new_unchecked(out)
+	}
+	/// Replaces each string literal whose decoded value is `old` with a
+	/// literal of `new`, using the same quotes, and escaping whatever the
+	/// new value needs. Only the values of string literals are compared,
+	/// so identifiers, comments and template literals are left untouched,
+	/// even where their text is the same as `old`. Fails if the script does
+	/// not parse, either before or after the literals are replaced.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("const old = 'old'; return f({ old });");
+	/// let script = script.replace_literal("old", "n'ew").unwrap();
+	/// assert_eq!(script, r"const old = 'n\'ew'; return f({ old });");
+	/// ```
+	pub fn replace_literal(&self, old: &str, new: &str) -> Result<Script, ScriptError> {
+		Script::validate(&self.0)?;
+		let scanned = self.scanned();
+		let mut out = String::with_capacity(self.0.len());
+		let mut pos = 0;
+		for lit in string_literals(&self.0, &scanned.literals) {
+			if lit.kind != TokenKind::StringLiteral || lit.value != old {
+				continue;
+			}
+			let quote = match self.0[..lit.span.start].chars().next_back() {
+				Some(v) => v,
+				None => continue,
+			};
+			out.push_str(&self.0[pos..lit.span.start]);
+			out.push_str(&escape(new, quote));
+			pos = lit.span.end;
+		}
+		out.push_str(&self.0[pos..]);
+		Script::new(out)
+	}
+	/// Returns the script re-indented by `indent` spaces for each level of
+	/// nesting, with line breaks inside blocks and after statements. This is a
+	/// best-effort formatter, rather than a full JavaScript beautifier, but
+	/// it only ever changes whitespace which has no effect on the program.
+	/// A script which does not parse is returned unchanged.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("if (a) { return 1; } return 2;");
+	/// assert_eq!(script.format(2), "if(a){\n  return 1;\n} return 2;");
+	/// ```
+	pub fn format(&self, indent: usize) -> Script {
+		match minify(&self.0).and_then(|v| format(&v, indent)) {
+			Some(v) => Script::new_unchecked(v),
+			None => self.clone(),
+		}
+	}
+	/// Returns an iterator over the tokens of the script body. A script
+	/// which does not parse yields the tokens up to the point of failure.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, TokenKind};
+	/// let script = Script::from("return 'a';");
+	/// let kinds: Vec<_> = script.tokens().map(|t| t.kind).collect();
+	/// assert_eq!(kinds, vec![TokenKind::Identifier, TokenKind::Whitespace, TokenKind::StringLiteral, TokenKind::Punct]);
+	/// ```
+	pub fn tokens(&self) -> impl Iterator<Item = Token> {
+		self.scanned().tokens.into_iter()
+	}
+	/// Compares the tokens of two scripts, skipping whitespace, so that a
+	/// script which has only been reformatted is equal throughout. The
+	/// result is a list of runs of tokens which are in both scripts, which
+	/// are only in this script, or which are only in the other script.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, TokenDiff};
+	/// let one = Script::from("return a;");
+	/// let two = Script::from("return  b;");
+	/// assert_eq!(one.token_diff(&two), vec![
+	///     TokenDiff::Equal(vec!["return".into()]),
+	///     TokenDiff::Delete(vec!["a".into()]),
+	///     TokenDiff::Insert(vec!["b".into()]),
+	///     TokenDiff::Equal(vec![";".into()]),
+	/// ]);
+	/// ```
+	pub fn token_diff(&self, other: &Script) -> Vec<TokenDiff> {
+		let texts = |s: &Script| -> Vec<(TokenKind, String)> {
+			s.tokens()
+				.filter(|t| t.kind != TokenKind::Whitespace)
+				.map(|t| (t.kind, s.0[t.span].to_owned()))
+				.collect()
+		};
+		token_diff(&texts(self), &texts(other))
+	}
+	/// Returns the byte offset of each match of a pattern of tokens within
+	/// the script body. Whitespace and comments are skipped, so formatting
+	/// can not hide a match, and text within a literal is a single token,
+	/// so it can not cause one. Matches may overlap.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, TokenKind, TokenKindPattern};
+	/// let pattern = [TokenKindPattern::Text(TokenKind::Identifier, "eval".into()), TokenKindPattern::Any];
+	/// let script = Script::from("eval /* x */ ('1'); return 'eval(2)';");
+	/// assert_eq!(script.contains_tokens(&pattern), vec![0]);
+	/// ```
+	pub fn contains_tokens(&self, pattern: &[TokenKindPattern]) -> Vec<usize> {
+		let tokens: Vec<_> = self
+			.tokens()
+			.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+			.collect();
+		match pattern.is_empty() {
+			true => vec![],
+			false => (0..tokens.len())
+				.filter(|&k| pattern_matches(&self.0, pattern, &tokens[k..]))
+				.map(|k| tokens[k].span.start)
+				.collect(),
+		}
+	}
+	/// Returns the identifiers which the script body uses, but which are not
+	/// declared within it, and are not keywords or known builtins. This is
+	/// a best-effort analysis of simple lexical scopes, rather than a full
+	/// resolver, so unusual declarations such as pattern defaults are missed.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 1; return a + b;");
+	/// assert_eq!(script.free_identifiers().into_iter().collect::<Vec<_>>(), vec!["b"]);
+	/// ```
+	pub fn free_identifiers(&self) -> BTreeSet<String> {
+		free_identifiers(&significant(&self.0, &self.scanned().tokens))
+	}
+	/// Returns the globals which the script body uses, and which reach
+	/// beyond the script itself, such as `fetch`, `setTimeout`, `eval` or
+	/// `WebAssembly`. A name which is declared within the body is not
+	/// reported, so a local variable named `fetch` is not a use of `fetch`.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("setTimeout(() => {}, 0); return fetch(url);");
+	/// let globals = script.referenced_globals().into_iter().collect::<Vec<_>>();
+	/// assert_eq!(globals, vec!["fetch", "setTimeout"]);
+	/// ```
+	pub fn referenced_globals(&self) -> BTreeSet<String> {
+		let tokens = significant(&self.0, &self.scanned().tokens);
+		unbound(&tokens, &JS_GLOBALS)
+			.into_iter()
+			.filter(|v| JS_GLOBALS.contains(&v.as_str()))
+			.collect()
+	}
+	/// Checks whether the script body is a single expression, either on its
+	/// own or as the value of a single `return` statement, which does not
+	/// assign to anything, increment or decrement anything, or use `delete`,
+	/// `await` or `yield`. This is a best-effort check of the tokens of the
+	/// body, so functions which the expression calls are not checked. A
+	/// body which is only an arrow function is always pure, as its body is
+	/// not run when the expression is evaluated.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// assert!(Script::from("return this.price * 1.2;").is_pure_expression());
+	/// assert!(Script::from("async v => { await v; }").is_pure_expression());
+	/// assert!(!Script::from("let a = 1; return a;").is_pure_expression());
+	/// ```
+	pub fn is_pure_expression(&self) -> bool {
+		let scanned = self.scanned();
+		if statements(&self.0, &scanned).len() != 1 {
+			return false;
+		}
+		let t: Vec<_> = scanned
+			.tokens
+			.iter()
+			.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+			.map(|t| (t.kind, &self.0[t.span.clone()], t.span.clone()))
+			.collect();
+		let t = match t.split_last() {
+			Some(((TokenKind::Punct, ";", _), v)) => v,
+			_ => &t[..],
+		};
+		let t = match t.split_first() {
+			Some(((TokenKind::Identifier, "return", _), v)) => v,
+			// A body which begins with a brace is a block
+			Some(((TokenKind::Punct, "{", _), _)) => return false,
+			_ => t,
+		};
+		if arrow(&t.iter().map(|v| (v.0, v.1)).collect::<Vec<_>>()) {
+			return true;
+		}
+		let text = |k: usize| t.get(k).map(|v| v.1);
+		match t.first() {
+			None => return false,
+			Some((TokenKind::Identifier, v, _)) if STATEMENT_KEYWORDS.contains(v) => return false,
+			Some((TokenKind::Identifier, "async", _)) if text(1) == Some("function") => {
+				return false
+			}
+			_ => (),
+		}
+		if t.iter().any(|(kind, v, _)| {
+			*kind == TokenKind::Identifier && matches!(*v, "delete" | "await" | "yield")
+		}) {
+			return false;
+		}
+		// Each run of adjacent punctuation is split into its operators, to
+		// find an assignment, including a compound assignment such as `>>=`,
+		// or an increment or a decrement
+		let mut k = 0;
+		while k < t.len() {
+			let (beg, mut end) = (t[k].2.start, t[k].2.end);
+			k += 1;
+			if t[k - 1].0 != TokenKind::Punct {
+				continue;
+			}
+			while let Some((TokenKind::Punct, _, span)) = t.get(k).filter(|v| v.2.start == end) {
+				end = span.end;
+				k += 1;
+			}
+			if operators(&self.0[beg..end])
+				.any(|v| JS_ASSIGNMENTS.contains(&v) || matches!(v, "++" | "--"))
+			{
+				return false;
+			}
+		}
+		true
+	}
+	/// Checks whether the script body is a single arrow function, such as
+	/// `v => v * 2`, either on its own or as the value of a single `return`
+	/// statement. The value of such a body is the function itself, which
+	/// has no SurrealQL value, so running it returns `NONE` rather than
+	/// calling the function. To apply the function to the arguments of the
+	/// embedded function, call it within the body, as in
+	/// `return (v => v * 2)(...arguments);`.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// assert!(Script::from("v => v * 2").is_arrow_function());
+	/// assert!(Script::from("return async (a, b) => { return a + b; };").is_arrow_function());
+	/// assert!(!Script::from("(v => v * 2)(...arguments)").is_arrow_function());
+	/// ```
+	pub fn is_arrow_function(&self) -> bool {
+		let scanned = self.scanned();
+		if statements(&self.0, &scanned).len() != 1 {
+			return false;
+		}
+		let t = significant(&self.0, &scanned.tokens);
+		let t = match t.split_last() {
+			Some(((TokenKind::Punct, ";"), v)) => v,
+			_ => &t[..],
+		};
+		match t.split_first() {
+			Some(((TokenKind::Identifier, "return"), v)) => arrow(v),
+			_ => arrow(t),
+		}
+	}
+	/// Returns the number of top-level statements in the script body, as
+	/// found by [`Script::statements`]
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 1; if (a) { a++; } else { a--; } return a;");
+	/// assert_eq!(script.statement_count(), 3);
+	/// ```
+	pub fn statement_count(&self) -> usize {
+		self.statements().len()
+	}
+	/// Returns the span of each top-level statement in the script body, as
+	/// delimited by a `;` outside of any brackets, by the `}` which closes
+	/// a top-level block, or by a line break where JavaScript would insert
+	/// a `;`. An `else` is part of the `if` before it, even after a `;`.
+	/// Each span begins at the first token of the statement and ends after
+	/// its last, including any `;`, so no span begins or ends with whitespace.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 1;\nif (a) { a++; }\nreturn a");
+	/// let out: Vec<_> = script.statements().into_iter().map(|s| &script.as_str()[s.span]).collect();
+	/// assert_eq!(out, vec!["let a = 1;", "if (a) { a++; }", "return a"]);
+	/// ```
+	pub fn statements(&self) -> Vec<StatementSpan> {
+		statements(&self.0, &self.scanned())
+	}
+	/// Splits the script body before the top-level statement at `index`,
+	/// so that code can be inserted between the two parts, with any
+	/// whitespace and comments before the statement kept in the first part.
+	/// An index of the number of statements splits after the last of them.
+	/// The body is only ever split between top-level statements, so never
+	/// within a literal, a block, the head of a loop, or an `if` and its
+	/// `else`. `None` is returned if the index is past the end, or if the
+	/// body does not parse. The parts are not checked, so a script which
+	/// is put back together should be checked again.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 1; return a;");
+	/// let (head, tail) = script.split_at_statement(1).unwrap();
+	/// assert_eq!((head.as_str(), tail.as_str()), ("let a = 1; ", "return a;"));
+	/// let out = Script::new(format!("{}a++; {}", head, tail)).unwrap();
+	/// assert_eq!(out, "let a = 1; a++; return a;");
+	/// ```
+	pub fn split_at_statement(&self, index: usize) -> Option<(Script, Script)> {
+		Script::validate(&self.0).ok()?;
+		let stmts = self.statements();
+		let at = match index.cmp(&stmts.len()) {
+			Ordering::Less => stmts[index].span.start,
+			Ordering::Equal => self.len(),
+			Ordering::Greater => return None,
+		};
+		let mut head = self.clone();
+		head.0 = ScriptBody::Owned(self.0[..at].to_owned());
+		let mut tail = self.clone().with_offset(match self.1 {
+			Offset::At(v) => Offset::At(v + at),
+			Offset::Remaining(v) => Offset::Remaining(v - at),
+			Offset::None => Offset::None,
+		});
+		tail.0 = ScriptBody::Owned(self.0[at..].to_owned());
+		Some((head, tail))
+	}
+	/// Returns the last top-level statement of the script body, if it is an
+	/// expression statement, as its value is the completion value of the
+	/// body. When the completion value is returned, and a function ends
+	/// without running a `return` statement, it returns this value, so
+	/// `let a = 1; a + 1` returns `2`. The statements are those found by
+	/// [`Script::statements`], so the last may follow a line break without
+	/// a `;`. If the body ends with any other statement, such as a
+	/// declaration, a block or an `if`, with or without an `else`, there is
+	/// no completion value, and the function returns `NONE`.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 1; a + 1");
+	/// assert_eq!(&script.as_str()[script.completion().unwrap().span], "a + 1");
+	/// assert_eq!(Script::from("let a = 1;").completion(), None);
+	/// ```
+	pub fn completion(&self) -> Option<StatementSpan> {
+		let scanned = self.scanned();
+		let last = statements(&self.0, &scanned).pop()?;
+		let t: Vec<_> = scanned
+			.tokens
+			.iter()
+			.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+			.filter(|t| t.span.start >= last.span.start && t.span.end <= last.span.end)
+			.map(|t| (t.kind, &self.0[t.span.clone()], t.span.end))
+			.collect();
+		let text = |k: usize| t.get(k).map(|v| v.1);
+		match (t.first()?, text(1)) {
+			// A body which ends with a brace is a block
+			((TokenKind::Punct, "{", _), _) => return None,
+			((TokenKind::Identifier, "async", _), Some("function")) => return None,
+			// A labelled statement is not an expression
+			((TokenKind::Identifier, _, _), Some(":")) => return None,
+			_ => (),
+		}
+		// The statement must not begin, or continue after a line break
+		// without a `;`, with a keyword which begins another statement
+		let mut depth = 0usize;
+		let mut marks = scanned.marks.iter().skip_while(|(at, _)| *at < last.span.start).peekable();
+		for (k, (kind, v, end)) in t.iter().enumerate() {
+			while let Some((_, opens)) = marks.next_if(|(at, _)| at < end) {
+				match opens {
+					true => depth += 1,
+					false => depth = depth.saturating_sub(1),
+				}
+			}
+			match (kind, *v) {
+				(TokenKind::Punct, "(" | "[" | "{") => depth += 1,
+				// A bracket which closes one that opened before the statement
+				// means that the statement is not complete
+				(TokenKind::Punct, ")" | "]" | "}") => depth = depth.checked_sub(1)?,
+				(TokenKind::Identifier, "function" | "class") if k > 0 => (),
+				(TokenKind::Identifier, v) if depth == 0 && STATEMENT_KEYWORDS.contains(&v) => {
+					return None
+				}
+				_ => (),
+			}
+		}
+		Some(last)
+	}
+	// Returns the script with a `return` inserted before its completion
+	// value, if it has one and is not the body of a generator
+	#[cfg(feature = "scripting")]
+	pub(crate) fn returning_completion(&self) -> Option<Script> {
+		let v = self.completion().filter(|_| !self.is_generator())?;
+		let mut out = self.clone();
+		out.0 =
+			ScriptBody::Owned(format!("{}return {}", &self[..v.span.start], &self[v.span.start..]));
+		Some(out)
+	}
+	/// Checks whether the script body is the body of a generator, which
+	/// is when it uses `yield` outside of any function nested within it.
+	/// The values which such a body yields are returned as an array.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// assert!(Script::from("yield 1; yield 2;").is_generator());
+	/// assert!(!Script::from("return [1, 2].map(function*(v) { yield v; });").is_generator());
+	/// ```
+	pub fn is_generator(&self) -> bool {
+		generator(&significant(&self.0, &self.scanned().tokens))
+	}
+	// Returns the script with a call of `hook`, with the number of the
+	// line, inserted before the first statement on each line, and the
+	// lines into which calls were inserted. A script which does not
+	// parse is returned unchanged, with no lines.
+	#[cfg(feature = "scripting")]
+	pub(crate) fn instrument(&self, hook: &str) -> (Script, BTreeSet<u32>) {
+		if Script::validate(&self.0).is_err() {
+			return (self.clone(), BTreeSet::new());
+		}
+		let scanned = self.scanned();
+		let points = instrument(&self.0, &scanned.tokens);
+		let mut out = String::with_capacity(self.0.len() + points.len() * (hook.len() + 8));
+		let mut pos = 0;
+		for (at, line) in points.iter() {
+			out.push_str(&self.0[pos..*at]);
+			out.push_str(&format!("{}({}); ", hook, line));
+			pos = *at;
+		}
+		out.push_str(&self.0[pos..]);
+		let mut script = self.clone();
+		script.0 = ScriptBody::Owned(out);
+		(script, points.into_iter().map(|(_, v)| v).collect())
+	}
+	/// Returns the deepest level of block nesting in the script body
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("if (a) { return { b: '{{' }; }");
+	/// assert_eq!(script.brace_depth_max(), 2);
+	/// ```
+	pub fn brace_depth_max(&self) -> usize {
+		brace_depth_max(&self.0, &self.scanned().tokens)
+	}
+	/// Returns a summary of the script body for diagnostics. This only
+	/// describes the source, as the compiled form of a script is held in
+	/// the script cache of the datastore which runs it.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let info = Script::from("let a = 1;\nreturn { a };").describe();
+	/// assert!(info.valid);
+	/// assert_eq!((info.length, info.lines, info.statements, info.depth), (24, 2, 2, 1));
+	/// ```
+	pub fn describe(&self) -> ScriptInfo {
+		ScriptInfo {
+			valid: Script::validate(&self.0).is_ok(),
+			length: self.len(),
+			lines: self.lines().count(),
+			statements: self.statement_count(),
+			depth: self.brace_depth_max(),
+		}
+	}
+	/// Returns the string literals in the script body, and the static parts
+	/// of its template literals, each with its escapes applied. The parts
+	/// of a template literal are returned separately, in order, with any
+	/// interpolations between them left out. A script which does not parse
+	/// yields the literals up to the point of failure.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from(r"return 'a\tb' + `c${d}e`;");
+	/// let values: Vec<_> = script.string_literals().into_iter().map(|v| v.value).collect();
+	/// assert_eq!(values, vec!["a\tb", "c", "e"]);
+	/// ```
+	pub fn string_literals(&self) -> Vec<LiteralSpan> {
+		string_literals(&self.0, &self.scanned().literals)
+	}
+	/// Returns the features which the script body may use, found from its
+	/// tokens. This is conservative, so a feature may be reported which is
+	/// not used, but a use of `import` is never missed, and any script which
+	/// could build code at runtime, with `eval` or `Function`, is reported
+	/// as using it.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let caps = Script::from("const m = await import('lib'); return m.run();").capabilities();
+	/// assert!(caps.uses_async && caps.uses_import);
+	/// assert!(!caps.uses_generators && !caps.uses_fetch);
+	/// ```
+	pub fn capabilities(&self) -> ScriptCapabilities {
+		capabilities(&significant(&self.0, &self.scanned().tokens))
+	}
+	/// Checks whether the script body uses `surrealdb.query()`, either with
+	/// a literal query, or with one which is built when the function runs
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// assert!(Script::from("return surrealdb.query('SELECT * FROM a');").embeds_sql());
+	/// assert!(!Script::from("return query('SELECT * FROM a');").embeds_sql());
+	/// ```
+	pub fn embeds_sql(&self) -> bool {
+		let out = self.embedded_sql();
+		!out.queries.is_empty() || !out.dynamic.is_empty()
+	}
+	/// Returns the queries which the script body passes to
+	/// `surrealdb.query()` as a string literal, or a template literal with
+	/// no interpolations, with their escapes applied. The queries which are
+	/// built when the function runs can not be known in advance, so only
+	/// where they are found is returned, as is where `surrealdb.query` or
+	/// `surrealdb` is used in a way which can not be followed, such as by
+	/// being assigned to a variable.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("await surrealdb.query('SELECT * FROM a'); await surrealdb.query(b);");
+	/// let out = script.embedded_sql();
+	/// assert_eq!(out.queries[0].value, "SELECT * FROM a");
+	/// assert_eq!(&script[out.dynamic[0].clone()], "b");
+	/// ```
+	pub fn embedded_sql(&self) -> EmbeddedSql {
+		let scanned = self.scanned();
+		let literals = string_literals(&self.0, &scanned.literals);
+		embedded_sql(&self.0, &scanned.tokens, literals)
+	}
+	/// Returns the results of each of the analyses of the script body,
+	/// which are found together from a single scan through the body
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let out = Script::from("if (a) { return fetch('x'); }").analyze();
+	/// assert_eq!(out.free_identifiers.into_iter().collect::<Vec<_>>(), vec!["a"]);
+	/// assert_eq!((out.statement_count, out.max_depth), (1, 1));
+	/// assert!(out.capabilities.uses_fetch);
+	/// assert_eq!(out.string_literals[0].value, "x");
+	/// ```
+	pub fn analyze(&self) -> ScriptAnalysis {
+		let scanned = self.scanned();
+		let tokens = significant(&self.0, &scanned.tokens);
+		ScriptAnalysis {
+			free_identifiers: free_identifiers(&tokens),
+			string_literals: string_literals(&self.0, &scanned.literals),
+			capabilities: capabilities(&tokens),
+			statement_count: statements(&self.0, &scanned).len(),
+			max_depth: brace_depth_max(&self.0, &scanned.tokens),
+		}
+	}
+	// Scans the body once, finding its tokens, the template interpolations,
+	// and the literals within it, up to the point of any failure
+	fn scanned(&self) -> Scanned {
+		let mut out = Scanned::default();
+		// The template literals and interpolations which are open, with
+		// the offset at which the current text of each template began
+		let mut open: Vec<Option<usize>> = Vec::new();
+		let mut pos = 0;
+		let _ = scan(&self.0, cnf::MAX_SCRIPT_DEPTH, |part, v| {
+			let span = pos..pos + v.len();
+			pos += v.len();
+			let kind = match part {
+				ScriptPart::Code => return tokenize(v, span.start, &mut out.tokens),
+				ScriptPart::String => {
+					out.literals.push((TokenKind::StringLiteral, span.start + 1..span.end - 1));
+					TokenKind::StringLiteral
+				}
+				ScriptPart::Regex => TokenKind::Regex,
+				ScriptPart::Comment => TokenKind::Comment,
+				ScriptPart::Template => {
+					match (open.last().copied(), v) {
+						// Text within a template literal
+						(Some(Some(start)), "`") => {
+							out.literals.push((TokenKind::TemplateLiteral, start..span.start));
+							open.pop();
+						}
+						(Some(Some(start)), TEMPLATE_BEG) => {
+							out.literals.push((TokenKind::TemplateLiteral, start..span.start));
+							out.marks.push((span.start, true));
+							open.push(None);
+						}
+						(Some(Some(_)), _) => (),
+						// A template literal which opens, or an interpolation which closes
+						(_, "`") => open.push(Some(span.end)),
+						(_, _) => {
+							out.marks.push((span.start, false));
+							open.pop();
+							if let Some(v) = open.last_mut() {
+								*v = Some(span.end);
+							}
+						}
+					}
+					match out.tokens.last_mut() {
+						// A template literal is only split by interpolations
+						Some(t)
+							if t.kind == TokenKind::TemplateLiteral && t.span.end == span.start =>
+						{
+							t.span.end = span.end;
+							return;
+						}
+						_ => TokenKind::TemplateLiteral,
+					}
+				}
+			};
+			out.tokens.push(Token {
+				kind,
+				span,
+			});
+		});
+		out.tokens = join_escapes(&self.0, out.tokens);
+		out
+	}
+	/// Replaces each `$name` placeholder in the script code with the given
+	/// JavaScript expression for `name`, wrapped in parentheses. Anything
+	/// within string, template or regex literals and comments is left as
+	/// it was, as are property names, and placeholders with no expression.
+	/// The expressions are not escaped, and the result is checked again.
+	///
+	/// ```rust
+	/// # use std::collections::BTreeMap;
+	/// # use surrealdb::sql::Script;
+	/// let params = BTreeMap::from([("limit".to_owned(), "10".to_owned())]);
+	/// let script = Script::from("return $limit + ' $limit';");
+	/// assert_eq!(script.with_params(&params).unwrap(), "return (10) + ' $limit';");
+	/// ```
+	pub fn with_params(&self, params: &BTreeMap<String, String>) -> Result<Script, ScriptError> {
+		let tokens: Vec<_> = self
+			.tokens()
+			.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+			.collect();
+		let kinds: Vec<_> = tokens.iter().map(|t| (t.kind, &self.0[t.span.clone()])).collect();
+		let mut out = String::with_capacity(self.0.len());
+		let mut pos = 0;
+		for (k, t) in tokens.iter().enumerate() {
+			let v = match kinds[k] {
+				(TokenKind::Identifier, v) if !property(&kinds, k) => v,
+				_ => continue,
+			};
+			if let Some(e) = v.strip_prefix('$').and_then(|v| params.get(v)) {
+				out.push_str(&self.0[pos..t.span.start]);
+				out.push('(');
+				out.push_str(e);
+				out.push(')');
+				pos = t.span.end;
+			}
+		}
+		out.push_str(&self.0[pos..]);
+		Script::from_str(&out)
+	}
+	/// Returns the script with the contents of every string and template
+	/// literal masked, so that it can be logged without leaking secrets.
+	/// Code within template interpolations is kept, and anything after a
+	/// point at which the script does not parse is masked entirely.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("return fetch('https://key@host', { k: `a${b}c` });");
+	/// assert_eq!(script.redact(), "return fetch('***', { k: `***${b}***` });");
+	/// ```
+	pub fn redact(&self) -> Script {
+		Script::new_unchecked(redact(&self.0))
+	}
+	/// Returns the script wrapped as a SurrealQL embedded function, which
+	/// parses back to the same script. A line break is added if the body
+	/// ends within a line comment, so that the closing `}` is not hidden.
+	/// A body which is not a complete script, such as one with an unmatched
+	/// `}`, can not be represented, so [`Script::validate`] should be used
+	/// to check any body which was not itself produced by the parser.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("return 1; // one");
+	/// assert_eq!(script.to_sql(), "function() {return 1; // one\n}");
+	/// ```
+	pub fn to_sql(&self) -> String {
+		match self.ends_in_comment() {
+			true => format!("function() {{{}\n}}", self.0),
+			false => format!("function() {{{}}}", self.0),
+		}
+	}
+	/// Checks whether the script body ends within a line comment
+	pub(crate) fn ends_in_comment(&self) -> bool {
+		match self.tokens().last() {
+			Some(t) => t.kind == TokenKind::Comment && !self.0[t.span].starts_with(COMMENT_BEG),
+			None => false,
+		}
+	}
+	/// Returns the directives in the directive prologue of the script body,
+	/// which are the string literal statements before any other statement
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("'use strict'; return 1;");
+	/// assert_eq!(script.directives(), vec!["use strict"]);
+	/// ```
+	pub fn directives(&self) -> Vec<String> {
+		let mut out = Vec::new();
+		let mut tokens = self.tokens().filter(|t| t.kind != TokenKind::Comment).peekable();
+		loop {
+			while tokens.next_if(|t| t.kind == TokenKind::Whitespace).is_some() {}
+			let v = match tokens.next_if(|t| t.kind == TokenKind::StringLiteral) {
+				Some(t) => &self.0[t.span],
+				None => break,
+			};
+			// The literal must be a statement of its own
+			let mut newline = false;
+			while let Some(t) = tokens.next_if(|t| t.kind == TokenKind::Whitespace) {
+				newline |= self.0[t.span].contains(['\n', '\r']);
+			}
+			let end = match tokens.peek() {
+				None => true,
+				Some(t) if &self.0[t.span.clone()] == ";" => {
+					tokens.next();
+					true
+				}
+				Some(t) => {
+					newline && matches!(t.kind, TokenKind::Identifier | TokenKind::StringLiteral)
+				}
+			};
+			if !end {
+				break;
+			}
+			out.push(v[1..v.len() - 1].to_owned());
+		}
+		out
+	}
+	/// Returns the language which the script body is written in
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, ScriptLang};
+	/// assert_eq!(Script::from("return 1;").lang(), ScriptLang::JavaScript);
+	/// ```
+	pub fn lang(&self) -> ScriptLang {
+		self.2
+	}
+	/// Returns the script with the language which its body is written in
+	pub fn with_lang(mut self, lang: ScriptLang) -> Script {
+		self.2 = lang;
+		self
+	}
+	/// Returns the host features which the script is permitted to use, if
+	/// it has been given permissions
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, ScriptPermissions};
+	/// let script = Script::from("return 1;");
+	/// assert_eq!(script.permissions(), None);
+	/// let script = script.with_permissions(ScriptPermissions::default());
+	/// assert!(!script.permissions().unwrap().http);
+	/// ```
+	pub fn permissions(&self) -> Option<&ScriptPermissions> {
+		self.3.as_ref()
+	}
+	/// Returns the script with the host features which it is permitted to use
+	pub fn with_permissions(mut self, permissions: ScriptPermissions) -> Script {
+		self.3 = Some(permissions);
+		self
+	}
+	/// Returns the byte offset at which the script body began within the
+	/// query it was parsed from, if it was parsed from a query
+	pub fn offset(&self) -> Option<usize> {
+		match self.1 {
+			Offset::At(v) => Some(v),
+			_ => None,
+		}
+	}
+	// Returns the script with the offset at which its body began
+	fn with_offset(mut self, offset: Offset) -> Script {
+		self.1 = offset;
+		self
+	}
+	// Resolves where the script body began, once the length of the whole
+	// query which it was parsed from is known
+	pub(crate) fn locate(&mut self, len: usize) {
+		if let Offset::Remaining(v) = self.1 {
+			self.1 = match len.checked_sub(v) {
+				Some(v) => Offset::At(v),
+				None => Offset::None,
+			};
+		}
+	}
+	/// Returns the byte offset at which a 1-based line of the script body
+	/// begins, counting line breaks in the same way as [`Script::spans`]
+	pub fn line_offset(&self, line: u32) -> Option<usize> {
+		if line == 0 {
+			return None;
+		}
+		let mut cur = 1;
+		let mut it = self.0.char_indices().peekable();
+		while cur < line {
+			match it.next()? {
+				(_, '\r') if matches!(it.peek(), Some((_, '\n'))) => (),
+				(_, '\n' | '\r') => cur += 1,
+				_ => (),
+			}
+		}
+		Some(it.next().map_or(self.0.len(), |(i, _)| i))
+	}
+	/// Returns a table of the byte offset, line, and column at which each
+	/// part of the script body begins, for mapping positions reported by
+	/// the JavaScript engine back to the script. Lines are numbered from
+	/// 1, and columns are counted in characters from 0. As in JavaScript,
+	/// `\r\n` and a lone `\r` each end a line.
+	pub fn spans(&self) -> Vec<(usize, u32, u32)> {
+		let mut out = Vec::new();
+		let (mut line, mut col) = (1u32, 0u32);
+		let mut pos = 0;
+		// Whether the last character was a carriage return
+		let mut cr = false;
+		let _ = scan(&self.0, cnf::MAX_SCRIPT_DEPTH, |_, v| {
+			out.push((pos, line, col));
+			for c in v.chars() {
+				match c {
+					// A line feed after a carriage return ends the same line
+					'\n' if std::mem::take(&mut cr) => (),
+					'\n' | '\r' => {
+						cr = c == '\r';
+						line += 1;
+						col = 0;
+					}
+					_ => {
+						cr = false;
+						col += 1;
+					}
+				}
+			}
+			pos += v.len();
+		});
+		out
+	}
+	/// Returns the script body, shortened to at most `max_chars` characters
+	/// for display, including the `…` which is added when it is shortened.
+	/// The body is only cut between tokens, and outside of any template
+	/// literal, so a literal, an escape, or a character is never split. A
+	/// body which is already short enough is returned as it is.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let script = Script::from("let a = 'a long string'; return a;");
+	/// assert_eq!(script.preview(20), "let a =…");
+	/// assert_eq!(script.preview(40), "let a = 'a long string'; return a;");
+	/// ```
+	pub fn preview(&self, max_chars: usize) -> String {
+		if self.0.chars().count() <= max_chars {
+			return self.0.to_string();
+		}
+		let limit = max_chars.saturating_sub(1);
+		let mut cut = 0;
+		let (mut chars, mut pos) = (0, 0);
+		// The template literals which are open at the end of a token
+		let mut open = 0usize;
+		for t in self.scanned().tokens {
+			chars += self.0[pos..t.span.end].chars().count();
+			pos = t.span.end;
+			if chars > limit {
+				break;
+			}
+			let v = &self.0[t.span.clone()];
+			if t.kind == TokenKind::TemplateLiteral {
+				if v.starts_with('`') {
+					open += 1;
+				}
+				if v.len() > 1 && v.ends_with('`') {
+					open = open.saturating_sub(1);
+				}
+			}
+			if open == 0 {
+				cut = t.span.end;
+			}
+		}
+		format!("{}…", self.0[..cut].trim_end())
+	}
+	/// Returns the ranges of the literals, comments and identifiers in the
+	/// script body, and of any point at which it fails to parse, with the
+	/// positions counted as editors count them, in UTF-16 code units.
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{EditorPosition, Script};
+	/// let out = Script::from("let a = '🦀';\nreturn a;").editor_spans();
+	/// assert_eq!(out.literals[0].end, EditorPosition { line: 0, character: 12 });
+	/// assert_eq!(out.identifiers[3].start, EditorPosition { line: 1, character: 7 });
+	/// ```
+	pub fn editor_spans(&self) -> EditorSpans {
+		let lines = line_starts(&self.0);
+		let range = |span: Range<usize>| EditorRange {
+			start: editor_position(&self.0, &lines, span.start),
+			end: editor_position(&self.0, &lines, span.end),
+		};
+		let mut out = EditorSpans::default();
+		for t in self.scanned().tokens {
+			match t.kind {
+				TokenKind::StringLiteral
+				| TokenKind::TemplateLiteral
+				| TokenKind::Regex
+				| TokenKind::Number => out.literals.push(range(t.span)),
+				TokenKind::Comment => out.comments.push(range(t.span)),
+				TokenKind::Identifier | TokenKind::PrivateIdentifier => {
+					out.identifiers.push(range(t.span))
+				}
+				TokenKind::Punct | TokenKind::Whitespace => (),
+			}
+		}
+		if let Err(e) = Script::validate(&self.0) {
+			let at = e.offset().min(self.0.len());
+			let len = self.0[at..].chars().next().map_or(0, char::len_utf8);
+			out.errors.push(range(at..at + len));
+		}
+		out
+	}
+	/// Parses and checks a script body, according to the given options
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, ScriptOptions};
+	/// let opts = ScriptOptions { reject_control: false, ..Default::default() };
+	/// assert!(Script::parse_with("return\x01 1;", &opts).is_ok());
+	/// assert!(Script::parse_with("return\x01 1;", &ScriptOptions::default()).is_err());
+	/// ```
+	pub fn parse_with(s: &str, opts: &ScriptOptions) -> Result<Self, ScriptError> {
+		match script_with(s, opts) {
+			Ok(("", v)) => Ok(v),
+			Ok((i, _)) => Err(ScriptError::TrailingInput {
+				offset: s.len() - i.len(),
+			}),
+			Err(e) => Err(ScriptError::from_parser(s, e)),
+		}
+	}
+	/// Parses a script body from the start of the input, returning the
+	/// script and the input which follows it, which is either empty or
+	/// begins with the `}` which ended the body
+	///
+	/// ```rust
+	/// # use surrealdb::sql::Script;
+	/// let (script, rest) = Script::parse_prefix("return 1; } RETURN 2;").unwrap();
+	/// assert_eq!(script, "return 1; ");
+	/// assert_eq!(rest, "} RETURN 2;");
+	/// ```
+	pub fn parse_prefix(i: &str) -> Result<(Self, &str), ScriptError> {
+		match script_with(i, &ScriptOptions::default()) {
+			Ok((s, v)) => Ok((v, s)),
+			Err(e) => Err(ScriptError::from_parser(i, e)),
+		}
+	}
+	/// Parses a script body from the whole input, failing if anything
+	/// other than whitespace follows the body
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, ScriptError};
+	/// assert_eq!(Script::parse_all("return 1;\n").unwrap(), "return 1;\n");
+	/// assert_eq!(Script::parse_all("return 1; }"), Err(ScriptError::TrailingInput { offset: 10 }));
+	/// ```
+	pub fn parse_all(i: &str) -> Result<Self, ScriptError> {
+		let (v, s) = Script::parse_prefix(i)?;
+		match s.trim_start().is_empty() {
+			true => Ok(v),
+			false => Err(ScriptError::TrailingInput {
+				offset: i.len() - s.len(),
+			}),
+		}
+	}
+	/// Checks that a script body is complete, reporting where it is not
+	///
+	/// ```rust
+	/// # use surrealdb::sql::{Script, ScriptError};
+	/// assert_eq!(Script::validate("return { a: 1 };"), Ok(()));
+	/// assert_eq!(Script::validate("return { a: 1;"), Err(ScriptError::UnmatchedOpen { offset: 7 }));
+	/// ```
+	pub fn validate(s: &str) -> Result<(), ScriptError> {
+		match script_raw(s, &ScriptOptions::default()) {
+			Ok(("", _)) => Ok(()),
+			Ok((i, _)) => Err(ScriptError::UnmatchedClose {
+				offset: s.len() - i.len(),
+			}),
+			Err(e) => Err(ScriptError::from_parser(s, e)),
+		}
+	}
+	/// Deserializes a script body, rejecting any body which does not parse
+	///
+	/// This can be used with `#[serde(deserialize_with = "...")]` on fields
+	/// which accept scripts from an untrusted source. Only a body is
+	/// accepted, so the script has no permissions of its own.
+	pub fn deserialize_checked<'de, D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let v = String::deserialize(deserializer)?;
+		Script::from_str(&v).map_err(serde::de::Error::custom)
+	}
+}
+
+impl PartialEq<str> for Script {
+	fn eq(&self, other: &str) -> bool {
+		self.0 == other
+	}
+}
+
+impl PartialEq<&str> for Script {
+	fn eq(&self, other: &&str) -> bool {
+		self.0 == *other
+	}
+}
+
+impl PartialEq<String> for Script {
+	fn eq(&self, other: &String) -> bool {
+		&self.0 == other
+	}
+}
+
+impl PartialEq<Script> for str {
+	fn eq(&self, other: &Script) -> bool {
+		self == other.as_str()
+	}
+}
+
+impl PartialEq<Script> for &str {
+	fn eq(&self, other: &Script) -> bool {
+		*self == other.as_str()
+	}
+}
+
+impl PartialEq<Script> for String {
+	fn eq(&self, other: &Script) -> bool {
+		self == other.as_str()
+	}
+}
+
+impl Display for Script {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		Display::fmt(&self.0, f)
+	}
+}
+
+// This is the checked conversion for a script body. The infallible
+// `From` implementations also provide `TryFrom` through the blanket
+// implementation in the standard library, so `TryFrom<&str>` and
+// `TryFrom<String>` can not perform any validation of their own.
+impl FromStr for Script {
+	type Err = ScriptError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Script::parse_with(s, &ScriptOptions::default())
+	}
+}
+
+/// The kind of a token within a script body
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TokenKind {
+	Identifier,
+	/// The name of a private class member, such as `#count`
+	PrivateIdentifier,
+	StringLiteral,
+	TemplateLiteral,
+	Regex,
+	Comment,
+	Punct,
+	Number,
+	Whitespace,
+}
+
+/// A token within a script body, and its byte span within the body
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Token {
+	pub kind: TokenKind,
+	pub span: Range<usize>,
+}
+
+/// A pattern which matches tokens within a script body, as used by
+/// [`Script::contains_tokens`]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TokenKindPattern {
+	/// Matches any single token of the given kind
+	Kind(TokenKind),
+	/// Matches a single token of the given kind with exactly this text
+	Text(TokenKind, String),
+	/// Matches any single token
+	Any,
+	/// Matches any run of tokens, including an empty run
+	Many,
+}
+
+impl TokenKindPattern {
+	fn matches(&self, kind: TokenKind, text: &str) -> bool {
+		match self {
+			Self::Kind(k) => *k == kind,
+			Self::Text(k, v) => *k == kind && v == text,
+			Self::Any => true,
+			Self::Many => false,
+		}
+	}
+}
+
+/// A run of tokens within a comparison of two scripts, as returned by
+/// [`Script::token_diff`]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TokenDiff {
+	/// Tokens which are in both scripts
+	Equal(Vec<String>),
+	/// Tokens which are only in the other script
+	Insert(Vec<String>),
+	/// Tokens which are only in this script
+	Delete(Vec<String>),
+}
+
+impl TokenDiff {
+	// Adds a token to a diff, extending the last run if it is of the
+	// same kind, or starting a new run otherwise
+	fn push(out: &mut Vec<TokenDiff>, run: fn(Vec<String>) -> TokenDiff, v: &str) {
+		let next = run(vec![]);
+		match out.last_mut() {
+			Some(last) if mem::discriminant(last) == mem::discriminant(&next) => match last {
+				TokenDiff::Equal(r) | TokenDiff::Insert(r) | TokenDiff::Delete(r) => {
+					r.push(v.to_owned())
+				}
+			},
+			_ => out.push(run(vec![v.to_owned()])),
+		}
+	}
+}
+
+/// A top-level statement within a script body, as returned by
+/// [`Script::statements`]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct StatementSpan {
+	/// The byte span of the statement, including any `;` which ends it
+	pub span: Range<usize>,
+}
+
+/// A string literal, or a static part of a template literal, within a
+/// script body, as returned by [`Script::string_literals`]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LiteralSpan {
+	/// Either [`TokenKind::StringLiteral`] or [`TokenKind::TemplateLiteral`]
+	pub kind: TokenKind,
+	/// The byte span of the text, without any quotes or delimiters
+	pub span: Range<usize>,
+	/// The value of the text, with its escapes applied
+	pub value: String,
+}
+
+/// A position within a script body, as editors and the Language Server
+/// Protocol count them, with lines and columns both numbered from 0, and
+/// columns counted in UTF-16 code units
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct EditorPosition {
+	pub line: u32,
+	pub character: u32,
+}
+
+/// A range within a script body, from its start up to its end
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct EditorRange {
+	pub start: EditorPosition,
+	pub end: EditorPosition,
+}
+
+/// The ranges of the parts of a script body, as returned by
+/// [`Script::editor_spans`]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize)]
+pub struct EditorSpans {
+	/// The string, template, regex and number literals
+	pub literals: Vec<EditorRange>,
+	/// The line and block comments, and any hashbang
+	pub comments: Vec<EditorRange>,
+	/// The identifiers, including keywords
+	pub identifiers: Vec<EditorRange>,
+	/// The point at which the body fails to parse, if it does not
+	pub errors: Vec<EditorRange>,
+}
+
+/// The features which a script body may use, as returned by
+/// [`Script::capabilities`]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize)]
+pub struct ScriptCapabilities {
+	/// Whether the body uses `async` or `await`
+	pub uses_async: bool,
+	/// Whether the body may import a module
+	pub uses_import: bool,
+	/// Whether the body uses generator functions, or `yield`
+	pub uses_generators: bool,
+	/// Whether the body uses `fetch`
+	pub uses_fetch: bool,
+}
+
+/// The queries which a script body passes to `surrealdb.query()`, as
+/// returned by [`Script::embedded_sql`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EmbeddedSql {
+	/// The queries which are given as literals, in the order in which they
+	/// appear in the body
+	pub queries: Vec<LiteralSpan>,
+	/// The byte spans of the queries which are built when the function
+	/// runs, and of the uses of `surrealdb` which can not be followed
+	pub dynamic: Vec<Range<usize>>,
+}
+
+/// The results of the analyses of a script body, as returned by
+/// [`Script::analyze`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScriptAnalysis {
+	/// As returned by [`Script::free_identifiers`]
+	pub free_identifiers: BTreeSet<String>,
+	/// As returned by [`Script::string_literals`]
+	pub string_literals: Vec<LiteralSpan>,
+	/// As returned by [`Script::capabilities`]
+	pub capabilities: ScriptCapabilities,
+	/// As returned by [`Script::statement_count`]
+	pub statement_count: usize,
+	/// As returned by [`Script::brace_depth_max`]
+	pub max_depth: usize,
+}
+
+/// A summary of a script body, as returned by [`Script::describe`]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+pub struct ScriptInfo {
+	/// Whether the body is a complete script
+	pub valid: bool,
+	/// The length of the body in bytes
+	pub length: usize,
+	/// The number of lines in the body
+	pub lines: usize,
+	/// The number of top-level statements in the body
+	pub statements: usize,
+	/// The deepest level of block nesting in the body
+	pub depth: usize,
+}
+
+/// A builder for an embedded function with named, and optionally typed,
+/// parameters. Each parameter is passed to the script, in order, through
+/// the `arguments` object, and typed parameters are cast before the call.
+///
+/// ```rust
+/// # use surrealdb::sql::{Script, ScriptBuilder};
+/// let out = ScriptBuilder::new(Script::from("return arguments[0] * 2;"))
+///     .typed_param("a", "int")
+///     .build()
+///     .unwrap();
+/// assert_eq!(out.to_string(), "function(<int> $a) {return arguments[0] * 2;}");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ScriptBuilder {
+	params: Vec<(String, Option<String>)>,
+	body: Script,
+}
+
+impl ScriptBuilder {
+	pub fn new(body: Script) -> Self {
+		Self {
+			params: Vec::new(),
+			body,
+		}
+	}
+	/// Adds a parameter which is passed to the script as it is
+	pub fn param(mut self, name: &str) -> Self {
+		self.params.push((name.to_owned(), None));
+		self
+	}
+	/// Adds a parameter which is cast to `kind` before it is passed
+	pub fn typed_param(mut self, name: &str, kind: &str) -> Self {
+		self.params.push((name.to_owned(), Some(kind.to_owned())));
+		self
+	}
+	/// Builds the function, checking the parameters and the script body
+	pub fn build(self) -> Result<Function, ScriptBuilderError> {
+		let mut args = Vec::with_capacity(self.params.len());
+		for (i, (name, kind)) in self.params.iter().enumerate() {
+			if name.is_empty() || !name.chars().all(val_char) {
+				return Err(ScriptBuilderError::InvalidParam(name.to_owned()));
+			}
+			if self.params[..i].iter().any(|(v, _)| v == name) {
+				return Err(ScriptBuilderError::DuplicateParam(name.to_owned()));
+			}
+			let v = Value::Param(Param::from(Idiom::from(name.to_owned())));
+			args.push(match kind {
+				Some(kind) => match function_casts(kind) {
+					Ok(("", _)) => Value::from(Function::Cast(kind.to_owned(), v)),
+					_ => return Err(ScriptBuilderError::InvalidKind(kind.to_owned())),
+				},
+				None => v,
+			});
+		}
+		Script::validate(&self.body)?;
+		Ok(Function::Script(self.body, args))
+	}
+}
+
+/// An error which occurred when building an embedded function
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum ScriptBuilderError {
+	/// A parameter name was not a valid identifier
+	#[error("Invalid parameter name '{0}'")]
+	InvalidParam(String),
+	/// A parameter name was used more than once
+	#[error("Duplicate parameter name '{0}'")]
+	DuplicateParam(String),
+	/// A parameter type was not one which values can be cast to
+	#[error("Invalid parameter type '{0}'")]
+	InvalidKind(String),
+	/// The script body was not valid
+	#[error(transparent)]
+	Body(#[from] ScriptError),
+}
+
+/// An error which occurred when checking a script body
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum ScriptError {
+	/// The script body could not be parsed
+	#[error("Problem parsing script at byte {offset}: {message}")]
+	Parse {
+		offset: usize,
+		message: &'static str,
+	},
+	/// The script body was followed by unexpected input
+	#[error("Unexpected input after the script at byte {offset}")]
+	TrailingInput {
+		offset: usize,
+	},
+	/// A `{` in the script body was never closed
+	#[error("Unmatched '{{' in script at byte {offset}")]
+	UnmatchedOpen {
+		offset: usize,
+	},
+	/// A `}` in the script body was never opened
+	#[error("Unmatched '}}' in script at byte {offset}")]
+	UnmatchedClose {
+		offset: usize,
+	},
+	/// A string or template literal in the script body was never closed
+	#[error("Unterminated string in script at byte {offset}")]
+	UnterminatedString {
+		offset: usize,
+	},
+	/// A block comment in the script body was never closed
+	#[error("Unterminated comment in script at byte {offset}")]
+	UnterminatedComment {
+		offset: usize,
+	},
+	/// The script body was nested more deeply than allowed
+	#[error("Script exceeds the maximum nesting depth at byte {offset}")]
+	DepthExceeded {
+		offset: usize,
+	},
+	/// The script body was longer than allowed
+	#[error("Script exceeds the maximum length at byte {offset}")]
+	TooLong {
+		offset: usize,
+	},
+}
+
+impl ScriptError {
+	/// Returns the byte offset within the body at which the error was found
+	pub fn offset(&self) -> usize {
+		match self {
+			Self::Parse {
+				offset,
+				..
+			}
+			| Self::TrailingInput {
+				offset,
+			}
+			| Self::UnmatchedOpen {
+				offset,
+			}
+			| Self::UnmatchedClose {
+				offset,
+			}
+			| Self::UnterminatedString {
+				offset,
+			}
+			| Self::UnterminatedComment {
+				offset,
+			}
+			| Self::DepthExceeded {
+				offset,
+			}
+			| Self::TooLong {
+				offset,
+			} => *offset,
+		}
+	}
+	// Converts a parser error into an error located within the input
+	fn from_parser(i: &str, e: Err<Error<&str>>) -> Self {
+		match e {
+			Err::Error(e) | Err::Failure(e) => match e {
+				Error::ScriptError {
+					input,
+					kind,
+					message,
+					..
+				} => {
+					let offset = i.len() - input.len();
+					match kind {
+						ScriptErrorKind::UnterminatedString => ScriptError::UnterminatedString {
+							offset,
+						},
+						ScriptErrorKind::UnterminatedComment => ScriptError::UnterminatedComment {
+							offset,
+						},
+						ScriptErrorKind::DepthExceeded => ScriptError::DepthExceeded {
+							offset,
+						},
+						ScriptErrorKind::TooLong => ScriptError::TooLong {
+							offset,
+						},
+						ScriptErrorKind::UnclosedBlock => ScriptError::UnmatchedOpen {
+							offset,
+						},
+						ScriptErrorKind::DisabledQuote | ScriptErrorKind::Invalid => {
+							ScriptError::Parse {
+								offset,
+								message,
+							}
+						}
+					}
+				}
+				ParserError(input) => ScriptError::Parse {
+					offset: i.len() - input.len(),
+					message: "invalid script",
+				},
+			},
+			Err::Incomplete(_) => ScriptError::Parse {
+				offset: i.len(),
+				message: "incomplete script",
+			},
+		}
+	}
+}
+
+/// A borrowed view of a script body, which avoids copying the input
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ScriptRef<'a>(pub &'a str);
+
+impl<'a> ScriptRef<'a> {
+	pub fn as_str(&self) -> &'a str {
+		self.0
+	}
+	pub fn to_owned(&self) -> Script {
+		Script::new_unchecked(self.0)
+	}
+}
+
+impl<'a> From<ScriptRef<'a>> for Script {
+	fn from(s: ScriptRef<'a>) -> Self {
+		s.to_owned()
+	}
+}
+
+impl<'a> Display for ScriptRef<'a> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		Display::fmt(self.0, f)
+	}
+}
+
+/// Options which control how a script body is parsed
+#[derive(Clone, Debug)]
+pub struct ScriptOptions {
+	/// The maximum nesting depth of blocks and template literals
+	pub depth: usize,
+	/// The maximum length of the script body in bytes
+	pub max_len: usize,
+	/// Whether control characters, other than tabs and line breaks, are
+	/// rejected when they appear outside of string and template literals.
+	/// A byte order mark is rejected too, unless it begins the body, where
+	/// it is always removed.
+	pub reject_control: bool,
+	/// Whether `\r\n` and lone `\r` line endings are converted to `\n`
+	/// outside of string and template literals
+	pub normalize_newlines: bool,
+	/// Whether `'` begins a single-quoted string literal
+	pub string_single: bool,
+	/// Whether `"` begins a double-quoted string literal
+	pub string_double: bool,
+	/// Whether `` ` `` begins a template literal
+	pub string_backtick: bool,
+	/// Whether a quote of a disabled style is rejected, rather than being
+	/// treated as ordinary code
+	pub reject_disabled_quotes: bool,
+	/// Whether a hashbang line at the start of the body is kept, so that
+	/// the body round-trips exactly, rather than being removed
+	pub keep_hashbang: bool,
+}
+
+impl Default for ScriptOptions {
+	fn default() -> Self {
+		Self {
+			depth: cnf::MAX_SCRIPT_DEPTH,
+			max_len: cnf::MAX_SCRIPT_LENGTH,
+			reject_control: true,
+			normalize_newlines: false,
+			string_single: true,
+			string_double: true,
+			string_backtick: true,
+			reject_disabled_quotes: false,
+			keep_hashbang: false,
+		}
+	}
+}
+
+/// An incremental parser for a script body, which can be fed the body in
+/// chunks as it arrives. Each chunk is checked as far as it can be before
+/// the rest of the body is known, and the finished script is accepted or
+/// rejected just as [`Script::parse_with`] would for the whole body.
+///
+/// ```rust
+/// # use surrealdb::sql::{Script, ScriptParser, ScriptPart};
+/// let mut parser = ScriptParser::default();
+/// let state = parser.feed(b"return { a: 'x").unwrap();
+/// assert_eq!((state.depth, state.part), (1, ScriptPart::String));
+/// parser.feed(b"y' };").unwrap();
+/// assert_eq!(parser.finish().unwrap(), Script::from("return { a: 'xy' };"));
+/// ```
+#[derive(Debug)]
+pub struct ScriptParser {
+	opts: ScriptOptions,
+	// The valid UTF-8 which has been fed
+	buf: String,
+	// The bytes of a character which has only been fed in part
+	partial: Vec<u8>,
+	scanner: Scanner,
+	// The offset of the first control character found outside of a literal
+	control: Option<usize>,
+	// The error which the body has already been rejected with
+	error: Option<ScriptError>,
+}
+
+/// A writer which builds a script body piece by piece, such as with the
+/// `write!` macro. Each piece is checked as it is written, and any error
+/// is returned once the body is finished, rather than by the writes.
+///
+/// ```rust
+/// # use std::fmt::Write;
+/// # use surrealdb::sql::{Script, ScriptWriter};
+/// let mut out = ScriptWriter::default();
+/// write!(out, "return {} * {};", "a", 2).unwrap();
+/// assert_eq!(out.finish().unwrap(), Script::from("return a * 2;"));
+/// ```
+#[derive(Debug, Default)]
+pub struct ScriptWriter {
+	parser: ScriptParser,
+}
+
+impl ScriptWriter {
+	pub fn new(opts: ScriptOptions) -> Self {
+		Self {
+			parser: ScriptParser::new(opts),
+		}
+	}
+	/// Checks the whole body which has been written
+	pub fn finish(self) -> Result<Script, ScriptError> {
+		self.parser.finish()
+	}
+}
+
+impl fmt::Write for ScriptWriter {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		// Any error is kept by the parser until the body is finished
+		let _ = self.parser.feed(s.as_bytes());
+		Ok(())
+	}
+}
+
+/// The state of a script body which is being parsed incrementally
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ScriptState {
+	/// The number of bytes which have been fed
+	pub length: usize,
+	/// The number of blocks and template literals which are open
+	pub depth: usize,
+	/// The part of the body which the input fed so far ends within
+	pub part: ScriptPart,
+}
+
+impl Default for ScriptParser {
+	fn default() -> Self {
+		Self::new(ScriptOptions::default())
+	}
+}
+
+impl ScriptParser {
+	pub fn new(opts: ScriptOptions) -> Self {
+		Self {
+			scanner: Scanner::new(&opts),
+			opts,
+			buf: String::new(),
+			partial: Vec::new(),
+			control: None,
+			error: None,
+		}
+	}
+	/// Feeds the next chunk of the body, which may end part way through a
+	/// character. Once the body has been rejected, every later call to
+	/// `feed` or `finish` returns the same error.
+	pub fn feed(&mut self, chunk: &[u8]) -> Result<ScriptState, ScriptError> {
+		if let Some(e) = &self.error {
+			return Err(e.clone());
+		}
+		self.partial.extend_from_slice(chunk);
+		let valid = match std::str::from_utf8(&self.partial) {
+			Ok(v) => v.len(),
+			// A character may be completed by the next chunk
+			Err(e) if e.error_len().is_none() => e.valid_up_to(),
+			Err(e) => {
+				return Err(self.fail(ScriptError::Parse {
+					offset: self.buf.len() + e.valid_up_to(),
+					message: "invalid utf-8 in script",
+				}))
+			}
+		};
+		if let Ok(v) = std::str::from_utf8(&self.partial[..valid]) {
+			self.buf.push_str(v);
+		}
+		self.partial.drain(..valid);
+		// A body which is too long is only scanned as far as the limit
+		let end = floor_char_boundary(&self.buf, self.opts.max_len.saturating_add(1));
+		let res =
+			script_scan(&self.buf[..end], &self.opts, &mut self.scanner, &mut self.control, false);
+		if let Err(e) = res {
+			let e = ScriptError::from_parser(&self.buf[..end], e);
+			return Err(self.fail(e));
+		}
+		Ok(self.state())
+	}
+	/// Returns the state of the body which has been fed so far
+	pub fn state(&self) -> ScriptState {
+		ScriptState {
+			length: self.buf.len() + self.partial.len(),
+			depth: self.scanner.open.len(),
+			part: self.scanner.part(&self.buf),
+		}
+	}
+	/// Checks the whole body, once every chunk of it has been fed
+	pub fn finish(mut self) -> Result<Script, ScriptError> {
+		if let Some(e) = self.error {
+			return Err(e);
+		}
+		if !self.partial.is_empty() {
+			return Err(ScriptError::Parse {
+				offset: self.buf.len(),
+				message: "invalid utf-8 in script",
+			});
+		}
+		// A body which is too long is rejected as it is by the batch parser
+		if self.buf.len() > self.opts.max_len {
+			return Script::parse_with(&self.buf, &self.opts);
+		}
+		let i = self.buf.as_str();
+		let res = script_scan(i, &self.opts, &mut self.scanner, &mut self.control, true)
+			.and_then(|_| script_end(i, &self.scanner, self.control));
+		match res {
+			Ok(("", v)) => Ok(Script::new_unchecked(match self.opts.normalize_newlines {
+				true => normalize_newlines(&v[preamble(v, &self.opts)..]),
+				false => v[preamble(v, &self.opts)..].to_owned(),
+			})),
+			Ok((s, _)) => Err(ScriptError::TrailingInput {
+				offset: i.len() - s.len(),
+			}),
+			Err(e) => Err(ScriptError::from_parser(i, e)),
+		}
+	}
+	// Records the error which the body has been rejected with
+	fn fail(&mut self, e: ScriptError) -> ScriptError {
+		self.error = Some(e.clone());
+		e
+	}
+}
+
+pub fn script(i: &str) -> IResult<&str, Script> {
+	let (s, v) = script_ref(i)?;
+	// Only the length of the query which follows the body is known here
+	let remaining = Offset::Remaining(s.len() + v.0.len());
+	Ok((s, Script::new_unchecked(v.0).with_offset(remaining)))
+}
+
+pub fn script_with<'a>(i: &'a str, opts: &ScriptOptions) -> IResult<&'a str, Script> {
+	let (s, v) = script_ref_with(i, opts)?;
+	let remaining = Offset::Remaining(s.len() + v.0.len());
+	let v = match opts.normalize_newlines && v.0.contains('\r') {
+		true => Cow::Owned(normalize_newlines(v.0)),
+		false => Cow::Borrowed(v.0),
+	};
+	Ok((s, Script::new_unchecked(v).with_offset(remaining)))
+}
+
+pub fn script_ref(i: &str) -> IResult<&str, ScriptRef<'_>> {
+	script_ref_with(i, &ScriptOptions::default())
+}
+
+pub fn script_ref_with<'a>(i: &'a str, opts: &ScriptOptions) -> IResult<&'a str, ScriptRef<'a>> {
+	// Only scan as far as the `}` which could close the longest allowed body
+	let end = floor_char_boundary(i, opts.max_len.saturating_add(1));
+	match script_raw(&i[..end], opts) {
+		// The body ended within the allowed length
+		Ok((_, v))
+			if v.len() <= opts.max_len && (end == i.len() || i[v.len()..].starts_with('}')) =>
+		{
+			Ok((&i[v.len()..], ScriptRef(&v[preamble(v, opts)..])))
+		}
+		// The body was invalid before the allowed length was reached
+		Err(e) if end == i.len() || !truncated(&e) => Err(located(i, end, e)),
+		// The body did not end within the allowed length
+		_ => {
+			let max = floor_char_boundary(i, opts.max_len);
+			Err(Err::Failure(Error::ScriptError {
+				input: &i[max..],
+				offset: max,
+				kind: ScriptErrorKind::TooLong,
+				message: "script exceeds the maximum length",
+			}))
+		}
+	}
+}
+
+// Returns the length of the byte order mark and the hashbang line which
+// begin the body `i`, if there are any which are to be removed
+fn preamble(i: &str, opts: &ScriptOptions) -> usize {
+	let bom = match i.starts_with(BOM) {
+		true => BOM.len(),
+		false => 0,
+	};
+	bom + hashbang(&i[bom..], opts)
+}
+
+// Returns the length of the hashbang line which begins the body `i`, not
+// including its line break, if the hashbang is to be removed
+fn hashbang(i: &str, opts: &ScriptOptions) -> usize {
+	match i.starts_with(HASHBANG) && !opts.keep_hashbang {
+		true => memchr2(b'\n', b'\r', i.as_bytes()).unwrap_or(i.len()),
+		false => 0,
+	}
+}
+
+// Checks whether an error could be caused by a literal or block which was cut off
+fn truncated(e: &Err<Error<&str>>) -> bool {
+	match e {
+		Err::Error(e) | Err::Failure(e) => match e {
+			Error::ScriptError {
+				input,
+				kind,
+				..
+			} => match kind {
+				ScriptErrorKind::UnclosedBlock => true,
+				ScriptErrorKind::DisabledQuote => false,
+				_ => input.starts_with(['\'', '"', '`', '/']),
+			},
+			ParserError(_) => true,
+		},
+		Err::Incomplete(_) => true,
+	}
+}
+
+// Returns the largest character boundary in `i` which is at most `n`
+fn floor_char_boundary(i: &str, n: usize) -> usize {
+	let mut n = n.min(i.len());
+	while !i.is_char_boundary(n) {
+		n -= 1;
+	}
+	n
+}
+
+// Moves an error from scanning the first `end` bytes of `i` onto `i`
+// itself, and sets its offset relative to the start of the body
+fn located<'a>(i: &'a str, end: usize, e: Err<Error<&'a str>>) -> Err<Error<&'a str>> {
+	e.map(|e| match e {
+		Error::ScriptError {
+			input,
+			kind,
+			message,
+			..
+		} => Error::ScriptError {
+			input: &i[end - input.len()..],
+			offset: end - input.len(),
+			kind,
+			message,
+		},
+		ParserError(input) => ParserError(&i[end - input.len()..]),
+	})
+}
+
+// A nested part of a script which is currently open
+#[derive(Debug)]
+enum Open {
+	// A block or object literal
+	Block,
+	// The text of a template literal
+	Template,
+	// An interpolation within a template literal
+	Interpolation,
+}
+
+/// A part of a script body, as reported by the scanner
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ScriptPart {
+	/// Script code, including any whitespace
+	Code,
+	/// A single or double quoted string literal
+	String,
+	/// A part of a template literal, outside of any interpolations
+	Template,
+	/// A regex literal
+	Regex,
+	/// A line or block comment
+	Comment,
+}
+
+// Parses a script body, checking it according to the options
+fn script_raw<'a>(i: &'a str, opts: &ScriptOptions) -> IResult<&'a str, &'a str> {
+	let mut scanner = Scanner::new(opts);
+	let mut control = None;
+	script_scan(i, opts, &mut scanner, &mut control, true)?;
+	script_end(i, &scanner, control)
+}
+
+// Scans as much of a script body as possible with `scanner`, recording
+// the offset of the first control character found outside of a literal.
+// A byte order mark is treated as a control character, unless it is the
+// first character of the body, where it is removed.
+fn script_scan<'a>(
+	i: &'a str,
+	opts: &ScriptOptions,
+	scanner: &mut Scanner,
+	control: &mut Option<usize>,
+	end: bool,
+) -> Result<(), Err<Error<&'a str>>> {
+	let mut pos = scanner.pos;
+	scanner.run(i, end, |part, v| {
+		if opts.reject_control
+			&& control.is_none()
+			&& matches!(part, ScriptPart::Code | ScriptPart::Comment)
+		{
+			let p = v.bytes().position(|b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r'));
+			let bom = v.match_indices(BOM).map(|(p, _)| p).find(|p| pos + p > 0);
+			if let Some(p) = p.into_iter().chain(bom).min() {
+				*control = Some(pos + p);
+			}
+		}
+		pos += v.len();
+	})
+}
+
+// Completes a scan of a script body, failing if a control character was
+// found, or if the input ended while a block was still open
+fn script_end<'a>(
+	i: &'a str,
+	scanner: &Scanner,
+	control: Option<usize>,
+) -> IResult<&'a str, &'a str> {
+	let (s, v) = scanner.finish(i)?;
+	match control {
+		Some(p) => Err(Err::Failure(Error::ScriptError {
+			input: &i[p..],
+			offset: 0,
+			kind: ScriptErrorKind::Invalid,
+			message: "invalid control character in script",
+		})),
+		// The earliest block which is still open is reported
+		None if s.starts_with('{') => Err(Err::Failure(Error::ScriptError {
+			input: s,
+			offset: 0,
+			kind: ScriptErrorKind::UnclosedBlock,
+			message: "unclosed block in script",
+		})),
+		None => Ok((s, v)),
+	}
+}
+
+// Scans a script body, passing each part of it to `emit`
+fn scan<'a>(
+	i: &'a str,
+	depth: usize,
+	emit: impl FnMut(ScriptPart, &'a str),
+) -> IResult<&'a str, &'a str> {
+	let mut scanner = Scanner::new(&ScriptOptions {
+		depth,
+		..Default::default()
+	});
+	scanner.run(i, true, emit)?;
+	scanner.finish(i)
+}
+
+#[cfg(test)]
+thread_local! {
+	// The number of bytes which the scanner has examined on this thread
+	static VISITED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+// Records that the scanner has examined `n` bytes of a script body, so
+// that tests can check the cost of scanning grows linearly with its size
+#[inline]
+fn visited(_n: usize) {
+	#[cfg(test)]
+	VISITED.with(|v| v.set(v.get() + _n));
+}
+
+// The state of a scan through a script body. The body is scanned in a
+// single pass, with any open blocks and template literals tracked on an
+// explicit stack, rather than by recursing, so that a scan can be paused
+// at the end of the input which is available, and resumed once more of
+// the body arrives.
+#[derive(Debug)]
+struct Scanner {
+	// The nested parts which are open, and the offset where each began
+	open: Vec<(Open, usize)>,
+	// Whether a `/` at this point would start a regex
+	regex: bool,
+	// The offset before which a `/` is never taken to start a regex
+	plain: usize,
+	// The remaining nesting depth which is allowed
+	depth: usize,
+	// The offset up to which the body has been scanned
+	pos: usize,
+	// Whether the body has ended at a `}` which closes it
+	done: bool,
+	// The kind of part which could not be settled at the end of the input,
+	// and the offset up to which it has been scanned without ending, so it
+	// is not scanned again from its start each time more input arrives
+	seen: Option<(ScriptPart, usize)>,
+	// The quote styles which begin a literal, and whether others are rejected
+	single: bool,
+	double: bool,
+	backtick: bool,
+	reject_quotes: bool,
+}
+
+impl Scanner {
+	fn new(opts: &ScriptOptions) -> Self {
+		Self {
+			open: Vec::new(),
+			regex: true,
+			plain: 0,
+			depth: opts.depth,
+			pos: 0,
+			done: false,
+			seen: None,
+			single: opts.string_single,
+			double: opts.string_double,
+			backtick: opts.string_backtick,
+			reject_quotes: opts.reject_disabled_quotes,
+		}
+	}
+	// Scans as much of the body `i` as possible, passing each part of it
+	// to `emit`. Unless `end` is set, more of the body may follow `i`, so
+	// any part which could be scanned differently once it does is left to
+	// be scanned by a later call, with the body which has arrived by then.
+	fn run<'a>(
+		&mut self,
+		i: &'a str,
+		end: bool,
+		mut emit: impl FnMut(ScriptPart, &'a str),
+	) -> Result<(), Err<Error<&'a str>>> {
+		while !self.done && self.pos < i.len() {
+			let s = &i[self.pos..];
+			let skip = self.seen.map_or(0, |(_, n)| n - self.pos);
+			let (part, n) = match self.step(s, end)? {
+				Some(v) => v,
+				// The rest of the input may have been examined
+				None => {
+					visited(s.len() - skip);
+					break;
+				}
+			};
+			visited((s.len() - n.len()).saturating_sub(skip));
+			emit(part, &s[..s.len() - n.len()]);
+			self.pos = i.len() - n.len();
+			self.seen = None;
+		}
+		Ok(())
+	}
+	// Scans the part of the body at the start of `s`, returning the part
+	// and the input which follows it, or None if the part is not settled
+	fn step<'a>(
+		&mut self,
+		s: &'a str,
+		end: bool,
+	) -> Result<Option<(ScriptPart, &'a str)>, Err<Error<&'a str>>> {
+		let c = match s.chars().next() {
+			Some(c) => c,
+			None => return Ok(None),
+		};
+		// Template literal text is scanned separately from code
+		if let Some((Open::Template, _)) = self.open.last() {
+			let n = match c {
+				'`' => {
+					self.open.pop();
+					self.regex = false;
+					&s[1..]
+				}
+				'$' if s.starts_with(TEMPLATE_BEG) => {
+					self.depth = nested(s, self.depth)?;
+					self.open.push((Open::Interpolation, self.pos));
+					self.regex = true;
+					&s[2..]
+				}
+				_ => match template_text(s) {
+					// Plain text is settled at the end of the input, as
+					// any text which follows it is scanned as another part
+					Ok((n, _)) if !s.starts_with(['\\', '$']) => n,
+					res => match settled(res, end)? {
+						Some(n) => n,
+						None => return Ok(None),
+					},
+				},
+			};
+			return Ok(Some((ScriptPart::Template, n)));
+		}
+		// A quote of a disabled style is ordinary code
+		let disabled = match c {
+			'\'' => !self.single,
+			'"' => !self.double,
+			'`' => !self.backtick,
+			_ => false,
+		};
+		if disabled {
+			if self.reject_quotes {
+				return Err(Err::Failure(Error::ScriptError {
+					input: s,
+					offset: 0,
+					kind: ScriptErrorKind::DisabledQuote,
+					message: "disabled quote style in script",
+				}));
+			}
+			self.regex = false;
+			return Ok(Some((ScriptPart::Code, &s[1..])));
+		}
+		// A hashbang is only a comment at the very start of the body
+		let hashbang = self.pos == 0 && s.starts_with(HASHBANG);
+		let res = match c {
+			'{' => {
+				self.depth = nested(s, self.depth)?;
+				self.open.push((Open::Block, self.pos));
+				self.regex = true;
+				(ScriptPart::Code, &s[1..])
+			}
+			'}' => match self.open.pop() {
+				Some((Open::Interpolation, _)) => {
+					self.depth += 1;
+					(ScriptPart::Template, &s[1..])
+				}
+				Some(_) => {
+					self.depth += 1;
+					self.regex = true;
+					(ScriptPart::Code, &s[1..])
+				}
+				None => {
+					self.done = true;
+					return Ok(None);
+				}
+			},
+			'`' => {
+				self.open.push((Open::Template, self.pos));
+				(ScriptPart::Template, &s[1..])
+			}
+			'\'' | '"' => match string_literal(s, self.skip(ScriptPart::String)) {
+				Ok(n) => {
+					self.regex = false;
+					(ScriptPart::String, n)
+				}
+				Err(_) if end => {
+					return Err(Err::Failure(Error::ScriptError {
+						input: s,
+						offset: 0,
+						kind: ScriptErrorKind::UnterminatedString,
+						message: match c {
+							'\'' => "unterminated single-quoted string",
+							_ => "unterminated double-quoted string",
+						},
+					}))
+				}
+				Err(n) => return Ok(self.unsettled(ScriptPart::String, n)),
+			},
+			'#' | '/' if hashbang || c == '/' && s[1..].starts_with(['/', '*']) => {
+				match comment(s, self.skip(ScriptPart::Comment)) {
+					Ok(n) => (ScriptPart::Comment, n),
+					// A line comment may end with the body
+					Err(_) if end && !s.starts_with(COMMENT_BEG) => (ScriptPart::Comment, ""),
+					Err(_) if end => {
+						return Err(Err::Failure(Error::ScriptError {
+							input: s,
+							offset: 0,
+							kind: ScriptErrorKind::UnterminatedComment,
+							message: "unterminated block comment",
+						}))
+					}
+					Err(n) => return Ok(self.unsettled(ScriptPart::Comment, n)),
+				}
+			}
+			'/' => match self.regex {
+				true if self.pos < self.plain => (ScriptPart::Code, &s[1..]),
+				// A regex can not span lines, so a line break settles it
+				true if !end
+					&& memchr(b'\n', &s.as_bytes()[self.skip(ScriptPart::Regex)..]).is_none() =>
+				{
+					return Ok(self.unsettled(ScriptPart::Regex, s.len()))
+				}
+				true => match regex_literal(s) {
+					Ok((n, _)) => {
+						self.regex = false;
+						(ScriptPart::Regex, n)
+					}
+					// A `/` which does not begin a regex is division,
+					// and so is every `/` before the end of the line,
+					// so the rest of the line is not rescanned for each
+					Err(Err::Error(_)) => {
+						let n = memchr(b'\n', s.as_bytes()).unwrap_or(s.len());
+						visited(n);
+						self.plain = self.pos + n;
+						(ScriptPart::Code, &s[1..])
+					}
+					Err(e) => return Err(e),
+				},
+				// A `/` could still begin a comment
+				false if !end && s.len() == 1 => return Ok(None),
+				false => {
+					self.regex = true;
+					(ScriptPart::Code, &s[1..])
+				}
+			},
+			_ => {
+				// The text before the skipped offset is known to be code
+				let skip = self.skip(ScriptPart::Code);
+				let n = char_any(&s[skip..]).map_or(&s[skip..], |(n, _)| n);
+				if !end && n.is_empty() {
+					return Ok(self.unsettled(ScriptPart::Code, s.len()));
+				}
+				self.regex = regex_allowed(&s[..s.len() - n.len()], self.regex);
+				(ScriptPart::Code, n)
+			}
+		};
+		Ok(Some(res))
+	}
+	// Returns the number of bytes of the part at the current offset which
+	// have already been scanned, if the part is of the given kind
+	fn skip(&self, part: ScriptPart) -> usize {
+		match self.seen {
+			Some((p, n)) if p == part => n - self.pos,
+			_ => 0,
+		}
+	}
+	// Records that a part has been scanned for `n` bytes without ending
+	fn unsettled<T>(&mut self, part: ScriptPart, n: usize) -> Option<T> {
+		self.seen = Some((part, self.pos + n));
+		None
+	}
+	// Completes the scan, once the whole of the body `i` has been scanned
+	fn finish<'a>(&self, i: &'a str) -> IResult<&'a str, &'a str> {
+		// Any template literal which is still open is unterminated
+		if let Some((_, t)) = self.open.iter().rev().find(|(o, _)| matches!(o, Open::Template)) {
+			return Err(Err::Failure(Error::ScriptError {
+				input: &i[*t..],
+				offset: 0,
+				kind: ScriptErrorKind::UnterminatedString,
+				message: "unterminated template literal",
+			}));
+		}
+		// Any block which is still open is not part of the script
+		let pos = match self.open.first() {
+			Some((_, b)) => *b,
+			None => self.pos,
+		};
+		Ok((&i[pos..], &i[..pos]))
+	}
+	// Returns the part of the body which the scanned input ends within
+	fn part(&self, i: &str) -> ScriptPart {
+		let s = &i[self.pos..];
+		match self.open.last() {
+			Some((Open::Template, _)) => ScriptPart::Template,
+			_ if s.starts_with('\'') && self.single => ScriptPart::String,
+			_ if s.starts_with('"') && self.double => ScriptPart::String,
+			_ if s.starts_with(COMMENT_LINE) || s.starts_with(COMMENT_BEG) => ScriptPart::Comment,
+			_ if s.starts_with(HASHBANG) && self.pos == 0 => ScriptPart::Comment,
+			_ if s.len() > 1 && s.starts_with(SLASH) && self.regex => ScriptPart::Regex,
+			_ => ScriptPart::Code,
+		}
+	}
+}
+
+// Returns the input which follows a part which was scanned with `res`,
+// or None if more of the body may follow, and could change the result
+fn settled<'a>(
+	res: IResult<&'a str, &'a str>,
+	end: bool,
+) -> Result<Option<&'a str>, Err<Error<&'a str>>> {
+	match res {
+		Ok((n, _)) if end || !n.is_empty() => Ok(Some(n)),
+		Err(e) if end => Err(e),
+		_ => Ok(None),
+	}
+}
+
+// Removes comments and collapses whitespace in a script body. A run of
+// whitespace which contains a line break is kept as a single line break,
+// as it may be significant for automatic semicolon insertion.
+fn minify(i: &str) -> Option<String> {
+	let mut out = String::with_capacity(i.len());
+	// The whitespace which is pending, if any
+	let mut gap: Option<char> = None;
+	let res = scan(i, cnf::MAX_SCRIPT_DEPTH, |part, v| match part {
+		ScriptPart::Comment => {
+			if !v.starts_with(COMMENT_BEG) || v.contains(['\n', '\r']) {
+				gap = Some('\n');
+			} else {
+				gap.get_or_insert(' ');
+			}
+		}
+		ScriptPart::String | ScriptPart::Template | ScriptPart::Regex => {
+			if let Some(c) = v.chars().next() {
+				minify_gap(&mut out, gap.take(), c);
+			}
+			out.push_str(v);
+		}
+		ScriptPart::Code => {
+			for c in v.chars() {
+				if c.is_whitespace() {
+					if c == '\n' || c == '\r' {
+						gap = Some('\n');
+					} else {
+						gap.get_or_insert(' ');
+					}
+				} else {
+					minify_gap(&mut out, gap.take(), c);
+					out.push(c);
+				}
+			}
+		}
+	});
+	match res {
+		Ok(("", _)) => Some(out),
+		_ => None,
+	}
+}
+
+// Writes any pending whitespace which is needed before the character `c`
+fn minify_gap(out: &mut String, gap: Option<char>, c: char) {
+	let (gap, last) = match (gap, out.chars().last()) {
+		(Some(gap), Some(last)) => (gap, last),
+		_ => return,
+	};
+	let keep = match gap {
+		// A line break is only insignificant between certain tokens
+		'\n' => {
+			!matches!(last, ';' | '{' | '(' | '[' | ',')
+				&& !matches!(c, '}' | ')' | ']' | ';' | ',')
+		}
+		// A space is only needed where tokens would otherwise merge
+		_ => {
+			(is_ident_char(last) && (is_ident_char(c) || matches!(c, '.' | '#')))
+				|| (last == c && matches!(c, '+' | '-'))
+				|| (last == '/' && matches!(c, '/' | '*'))
+		}
+	};
+	if keep {
+		out.push(gap);
+	}
+}
+
+// Re-indents a minified script body. Line breaks are only added after a
+// block is opened, before a block is closed, and after a statement, none
+// of which affect automatic semicolon insertion. A line break after a
+// block is closed might, so only the line breaks which are kept by the
+// minifier are indented in place there.
+fn format(i: &str, indent: usize) -> Option<String> {
+	let mut out = String::with_capacity(i.len() * 2);
+	// The current level of block nesting
+	let mut depth = 0usize;
+	// The current level of parentheses, within which `;` is not a break
+	let mut parens = 0usize;
+	// The number of template interpolations which are open
+	let mut interp = 0usize;
+	// Whether a line break is pending before the next character
+	let mut brk = false;
+	let res = scan(i, cnf::MAX_SCRIPT_DEPTH, |part, v| {
+		// Code within template interpolations is left as it is
+		if part != ScriptPart::Code || interp > 0 {
+			if part == ScriptPart::Template {
+				match v {
+					TEMPLATE_BEG => interp += 1,
+					"}" => interp = interp.saturating_sub(1),
+					_ => (),
+				}
+			}
+			format_break(&mut out, &mut brk, depth, indent);
+			out.push_str(v);
+			return;
+		}
+		for c in v.chars() {
+			match c {
+				'\n' => brk = true,
+				'{' => {
+					format_break(&mut out, &mut brk, depth, indent);
+					out.push(c);
+					depth += 1;
+					brk = true;
+				}
+				'}' => {
+					depth = depth.saturating_sub(1);
+					brk = !out.ends_with('{');
+					format_break(&mut out, &mut brk, depth, indent);
+					out.push(c);
+				}
+				';' if parens == 0 => {
+					format_break(&mut out, &mut brk, depth, indent);
+					out.push(c);
+					brk = true;
+				}
+				_ => {
+					match c {
+						'(' => parens += 1,
+						')' => parens = parens.saturating_sub(1),
+						_ => (),
+					}
+					// Keep a word after a closed block apart from it
+					if !brk && out.ends_with('}') && is_ident_char(c) {
+						out.push(' ');
+					}
+					format_break(&mut out, &mut brk, depth, indent);
+					out.push(c);
+				}
+			}
+		}
+	});
+	match res {
+		Ok(("", _)) => Some(out),
+		_ => None,
+	}
+}
+
+// Writes a pending line break, followed by the indentation for `depth`
+fn format_break(out: &mut String, brk: &mut bool, depth: usize, indent: usize) {
+	if std::mem::take(brk) && !out.is_empty() {
+		out.push('\n');
+		out.push_str(&" ".repeat(depth * indent));
+	}
+}
+
+// Splits a run of script code, beginning at byte `pos`, into tokens
+fn tokenize(v: &str, pos: usize, out: &mut Vec<Token>) {
+	let mut chars = v.char_indices().peekable();
+	while let Some((beg, c)) = chars.next() {
+		let kind = match c {
+			c if c.is_whitespace() => {
+				while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+				TokenKind::Whitespace
+			}
+			c if c.is_ascii_digit()
+				|| (c == '.' && v[beg + 1..].starts_with(|c: char| c.is_ascii_digit())) =>
+			{
+				let end = beg + number(&v[beg..]);
+				while chars.next_if(|(i, _)| *i < end).is_some() {}
+				TokenKind::Number
+			}
+			c if is_ident_char(c) => {
+				while chars.next_if(|(_, c)| is_ident_char(*c)).is_some() {}
+				TokenKind::Identifier
+			}
+			'#' if v[beg + 1..].starts_with(|c: char| is_ident_char(c) && !c.is_ascii_digit()) => {
+				while chars.next_if(|(_, c)| is_ident_char(*c)).is_some() {}
+				TokenKind::PrivateIdentifier
+			}
+			_ => TokenKind::Punct,
+		};
+		let end = chars.peek().map_or(v.len(), |(i, _)| *i);
+		out.push(Token {
+			kind,
+			span: pos + beg..pos + end,
+		});
+	}
+}
+
+// Joins the tokens of each identifier which is written with unicode
+// escapes, as a `\` on its own is punctuation, and the braces of a
+// `\u{...}` escape are split from the rest of the code when scanning
+fn join_escapes(i: &str, tokens: Vec<Token>) -> Vec<Token> {
+	let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+	let mut end = 0;
+	for t in tokens {
+		if t.span.start < end {
+			continue;
+		}
+		if &i[t.span.clone()] == "\\" && ident_escape(&i[t.span.start..]).is_some() {
+			let (kind, start) = match out.last() {
+				Some(v)
+					if matches!(v.kind, TokenKind::Identifier | TokenKind::PrivateIdentifier)
+						&& v.span.end == t.span.start =>
+				{
+					out.pop()
+						.map_or((TokenKind::Identifier, t.span.start), |v| (v.kind, v.span.start))
+				}
+				// A `#` which is followed by an escape names a private member
+				Some(v) if &i[v.span.clone()] == "#" && v.span.end == t.span.start => {
+					out.pop().map_or((TokenKind::Identifier, t.span.start), |v| {
+						(TokenKind::PrivateIdentifier, v.span.start)
+					})
+				}
+				_ => (TokenKind::Identifier, t.span.start),
+			};
+			end = t.span.start + ident(&i[t.span.start..]);
+			out.push(Token {
+				kind,
+				span: start..end,
+			});
+			continue;
+		}
+		out.push(t);
+	}
+	out
+}
+
+// Returns the length of the identifier at the start of `v`, which may
+// include unicode escapes, such as `\u0061` or `\u{61}`
+fn ident(v: &str) -> usize {
+	let mut i = 0;
+	while i < v.len() {
+		match ident_escape(&v[i..]) {
+			Some((_, len)) => i += len,
+			None => match v[i..].chars().next().filter(|c| is_ident_char(*c)) {
+				Some(c) => i += c.len_utf8(),
+				None => break,
+			},
+		}
+	}
+	i
+}
+
+// Returns the length of the unicode escape at the start of `v`, and the
+// character which it is an escape for. JavaScript only allows the `\u`
+// escapes in identifiers, and not the `\x` escapes of string literals.
+fn ident_escape(v: &str) -> Option<(char, usize)> {
+	let (code, len) = hex_escape('u', v.strip_prefix("\\u")?)?;
+	let c = char::from_u32(code).filter(|c| is_ident_char(*c))?;
+	Some((c, len + 2))
+}
+
+// Replaces the names of the identifiers which are written with unicode
+// escapes with their decoded names, so that they are analysed by name
+fn decoded<'a>(t: &[(TokenKind, &'a str)]) -> Vec<(TokenKind, Cow<'a, str>)> {
+	t.iter()
+		.map(|&(k, v)| match k == TokenKind::Identifier && v.contains('\\') {
+			true => {
+				let mut out = String::with_capacity(v.len());
+				let mut i = 0;
+				while let Some(c) = v[i..].chars().next() {
+					match ident_escape(&v[i..]) {
+						Some((c, len)) => {
+							out.push(c);
+							i += len;
+						}
+						None => {
+							out.push(c);
+							i += c.len_utf8();
+						}
+					}
+				}
+				(k, Cow::Owned(out))
+			}
+			false => (k, Cow::Borrowed(v)),
+		})
+		.collect()
+}
+
+// Returns the length of the numeric literal at the start of `v`. This
+// covers prefixed integers, such as `0xFF`, `0b10` and `0o17`, numeric
+// separators, fractions, and signed exponents. Any letters which follow
+// the literal, such as the `n` of a BigInt, are part of the same token.
+fn number(v: &str) -> usize {
+	let b = v.as_bytes();
+	let digits =
+		|i: usize| i + b[i..].iter().take_while(|c| c.is_ascii_digit() || **c == b'_').count();
+	let mut i = match b {
+		[b'0', b'x' | b'X' | b'b' | b'B' | b'o' | b'O', ..] => 2,
+		_ => {
+			let mut i = digits(0);
+			if b.get(i) == Some(&b'.') {
+				i = digits(i + 1);
+			}
+			if matches!(b.get(i), Some(b'e' | b'E')) {
+				match b.get(i + 1) {
+					Some(b'+' | b'-') if matches!(b.get(i + 2), Some(c) if c.is_ascii_digit()) => {
+						i = digits(i + 2)
+					}
+					Some(c) if c.is_ascii_digit() => i = digits(i + 1),
+					_ => (),
+				}
+			}
+			i
+		}
+	};
+	while let Some(c) = v[i..].chars().next().filter(|c| is_ident_char(*c)) {
+		i += c.len_utf8();
+	}
+	i
+}
+
+// A lexical scope which is open while analysing a script
+struct Scope {
+	// The index of the scope's declared names
+	id: usize,
+	// The bracket depth at which an arrow function's expression body began
+	arrow: Option<usize>,
+}
+
+// Finds the identifiers which are used in a list of significant tokens,
+// but which are not declared in any scope which encloses the use. Names
+// are resolved once every token has been seen, as declarations hoist.
+// Whether the identifier at `k` is a property name or an object key,
+// rather than a keyword or a name, which depends on the tokens around it
+fn property(t: &[(TokenKind, &str)], k: usize) -> bool {
+	let punct = |k: usize, p: &str| matches!(t.get(k), Some((TokenKind::Punct, v)) if *v == p);
+	(punct(k.wrapping_sub(1), ".") && !punct(k.wrapping_sub(2), "."))
+		|| (punct(k + 1, ":") && (punct(k.wrapping_sub(1), "{") || punct(k.wrapping_sub(1), ",")))
+}
+
+// Splits a run of adjacent punctuation into the operators which it holds,
+// taking the longest operator at each point, as JavaScript does
+fn operators(run: &str) -> impl Iterator<Item = &str> {
+	let mut rest = run;
+	std::iter::from_fn(move || {
+		let c = rest.chars().next()?;
+		let n =
+			JS_OPERATORS.iter().find(|v| rest.starts_with(*v)).map_or(c.len_utf8(), |v| v.len());
+		let (v, next) = rest.split_at(n);
+		rest = next;
+		Some(v)
+	})
+}
+
+// The results of a single scan through a script body
+#[derive(Default)]
+struct Scanned {
+	// The tokens of the body
+	tokens: Vec<Token>,
+	// The offsets at which each template interpolation opens, or closes
+	marks: Vec<(usize, bool)>,
+	// The span of each string literal, and static part of a template
+	// literal, without its quotes or delimiters
+	literals: Vec<(TokenKind, Range<usize>)>,
+}
+
+// Returns the tokens which are not whitespace or comments, with their text
+fn significant<'a>(i: &'a str, tokens: &[Token]) -> Vec<(TokenKind, &'a str)> {
+	tokens
+		.iter()
+		.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+		.map(|t| (t.kind, &i[t.span.clone()]))
+		.collect()
+}
+
+// Compares two lists of tokens, finding the longest common subsequence
+// of the tokens which differ, after the common prefix and suffix
+fn token_diff(a: &[(TokenKind, String)], b: &[(TokenKind, String)]) -> Vec<TokenDiff> {
+	let pre = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+	let (a_rest, b_rest) = (&a[pre..], &b[pre..]);
+	let suf = a_rest.iter().rev().zip(b_rest.iter().rev()).take_while(|(a, b)| a == b).count();
+	let (x, y) = (&a_rest[..a_rest.len() - suf], &b_rest[..b_rest.len() - suf]);
+	// The length of the longest common subsequence of each pair of suffixes
+	let w = y.len() + 1;
+	let mut lcs = vec![0u32; (x.len() + 1) * w];
+	for i in (0..x.len()).rev() {
+		for j in (0..y.len()).rev() {
+			lcs[i * w + j] = match x[i] == y[j] {
+				true => lcs[(i + 1) * w + j + 1] + 1,
+				false => lcs[(i + 1) * w + j].max(lcs[i * w + j + 1]),
+			};
+		}
+	}
+	let mut out = Vec::new();
+	for v in &a[..pre] {
+		TokenDiff::push(&mut out, TokenDiff::Equal, &v.1);
+	}
+	let (mut i, mut j) = (0, 0);
+	while i < x.len() || j < y.len() {
+		if i < x.len() && j < y.len() && x[i] == y[j] {
+			TokenDiff::push(&mut out, TokenDiff::Equal, &x[i].1);
+			i += 1;
+			j += 1;
+		} else if i < x.len() && (j == y.len() || lcs[(i + 1) * w + j] >= lcs[i * w + j + 1]) {
+			TokenDiff::push(&mut out, TokenDiff::Delete, &x[i].1);
+			i += 1;
+		} else {
+			TokenDiff::push(&mut out, TokenDiff::Insert, &y[j].1);
+			j += 1;
+		}
+	}
+	for v in &a_rest[a_rest.len() - suf..] {
+		TokenDiff::push(&mut out, TokenDiff::Equal, &v.1);
+	}
+	out
+}
+
+// Checks whether a pattern matches the tokens from the first token
+fn pattern_matches(i: &str, pattern: &[TokenKindPattern], tokens: &[Token]) -> bool {
+	match pattern.split_first() {
+		None => true,
+		Some((TokenKindPattern::Many, rest)) => {
+			(0..=tokens.len()).any(|n| pattern_matches(i, rest, &tokens[n..]))
+		}
+		Some((p, rest)) => match tokens.split_first() {
+			Some((t, tokens)) if p.matches(t.kind, &i[t.span.clone()]) => {
+				pattern_matches(i, rest, tokens)
+			}
+			_ => false,
+		},
+	}
+}
+
+// Finds the top-level statements of a script body from its scan. A
+// statement ends at a `;` or at the `}` which closes a block, where no
+// bracket is open, or at a line break where a `;` would be inserted,
+// which is where the token before the break can end a statement and the
+// token after it can only begin another. An `else`, or the `while` of a
+// `do`, continues the statement which ended before it.
+fn statements(i: &str, scanned: &Scanned) -> Vec<StatementSpan> {
+	let t: Vec<_> = scanned
+		.tokens
+		.iter()
+		.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+		.map(|t| (t.kind, &i[t.span.clone()], t.span.clone()))
+		.collect();
+	// Whether the token at `k` and the one after it are a `++` or a `--`
+	let step = |k: usize| match (t.get(k), t.get(k + 1)) {
+		(Some((TokenKind::Punct, a, x)), Some((TokenKind::Punct, b, y))) => {
+			matches!(*a, "+" | "-") && a == b && x.end == y.start
+		}
+		_ => false,
+	};
+	// Whether there is a line break before the token at `k`
+	let broken = |k: usize| k > 0 && i[t[k - 1].2.end..t[k].2.start].contains('\n');
+	let mut out = Vec::new();
+	let mut depth = 0usize;
+	// The span of the statement which has begun since the last one ended
+	let mut open: Option<Range<usize>> = None;
+	// Whether a top-level block has just been closed
+	let mut block = false;
+	// Whether a statement could end after the last token
+	let mut ends = false;
+	// Whether the open statement is a `do` which is waiting for its
+	// `while`, and whether the last token was that `while`
+	let mut repeat = false;
+	let mut until = false;
+	// The keyword before each open paren, where the paren holds the head
+	// of a statement such as an `if` or a loop
+	let mut parens: Vec<Option<&str>> = Vec::new();
+	// The code within a template interpolation is nested, but the
+	// braces around it are part of a template literal token
+	let mut marks = scanned.marks.iter().peekable();
+	for (k, (kind, v, at)) in t.iter().cloned().enumerate() {
+		let word = kind == TokenKind::Identifier;
+		let continues = word && (v == "else" || (v == "while" && repeat));
+		// A word after a top-level block begins another statement
+		if std::mem::take(&mut block) && word && !continues && !matches!(v, "catch" | "finally") {
+			out.extend(open.take().map(|span| StatementSpan {
+				span,
+			}));
+		}
+		// A line break ends a statement which could end before it, if the
+		// code after it could not continue the statement
+		let begins = match (kind, v) {
+			(TokenKind::Identifier, v) => {
+				!continues
+					&& !matches!(
+						v,
+						"case"
+							| "catch" | "default" | "else"
+							| "extends" | "finally"
+							| "in" | "instanceof" | "of"
+					)
+			}
+			// A template literal after a line break is tagged by the code
+			// before it, and other punctuation continues the expression
+			(TokenKind::TemplateLiteral, _) => false,
+			(TokenKind::Punct, _) => step(k),
+			_ => true,
+		};
+		if depth == 0 && ends && begins && broken(k) {
+			out.extend(open.take().map(|span| StatementSpan {
+				span,
+			}));
+		}
+		// A statement which has ended with a `;` is continued by an `else`
+		if depth == 0 && continues && open.is_none() {
+			open = out.pop().map(|s: StatementSpan| s.span);
+		}
+		if open.is_none() {
+			repeat = word && v == "do";
+		} else if continues && v == "while" {
+			repeat = false;
+		}
+		while let Some((_, opens)) = marks.next_if(|(m, _)| *m < at.end) {
+			match opens {
+				true => depth += 1,
+				false => depth = depth.saturating_sub(1),
+			}
+		}
+		let prev = k.checked_sub(1).map(|k| (t[k].0, t[k].1));
+		let before = k.checked_sub(2).map(|k| t[k].1);
+		ends = match (kind, v) {
+			// A keyword is always followed by more of the statement, but
+			// not a keyword which is used as a property name
+			(TokenKind::Identifier, v) => {
+				prev == Some((TokenKind::Punct, "."))
+					|| !matches!(
+						v,
+						"await"
+							| "case" | "catch" | "class"
+							| "const" | "default" | "delete"
+							| "do" | "else" | "export"
+							| "extends" | "finally"
+							| "for" | "function" | "if"
+							| "import" | "in" | "instanceof"
+							| "let" | "new" | "of"
+							| "switch" | "throw" | "try"
+							| "typeof" | "var" | "void"
+							| "while" | "with"
+					)
+			}
+			// A `++` or `--` ends a statement after an operand on its line
+			(TokenKind::Punct, "+" | "-") => k >= 2 && step(k - 1) && !broken(k - 1),
+			(TokenKind::Punct, ")" | "]" | "}") => true,
+			(TokenKind::Punct, _) => false,
+			_ => true,
+		};
+		match (kind, v) {
+			(TokenKind::Punct, "{" | "[") => depth += 1,
+			(TokenKind::Punct, "(") => {
+				depth += 1;
+				parens.push(match prev {
+					Some((TokenKind::Identifier, "while")) if until => Some("do"),
+					Some((TokenKind::Identifier, "await")) if before == Some("for") => Some("for"),
+					Some((TokenKind::Identifier, v)) if before != Some(".") => Some(v),
+					_ => None,
+				});
+			}
+			(TokenKind::Punct, "}") => {
+				depth = depth.saturating_sub(1);
+				block = depth == 0;
+			}
+			(TokenKind::Punct, "]") => depth = depth.saturating_sub(1),
+			// The head of an `if` or a loop is followed by its body, and the
+			// `while` of a `do` ends it, as a block does
+			(TokenKind::Punct, ")") => {
+				depth = depth.saturating_sub(1);
+				match parens.pop().flatten() {
+					Some("if" | "for" | "while" | "with") => ends = false,
+					Some("do") => block = depth == 0,
+					_ => (),
+				}
+			}
+			(TokenKind::Punct, ";") if depth == 0 => {
+				out.extend(open.take().map(|span| StatementSpan {
+					span: span.start..at.end,
+				}));
+				until = false;
+				continue;
+			}
+			_ => (),
+		}
+		until = continues && v == "while";
+		match &mut open {
+			Some(span) => span.end = at.end,
+			None => open = Some(at),
+		}
+	}
+	out.extend(open.map(|span| StatementSpan {
+		span,
+	}));
+	out
+}
+
+// Finds the deepest level of block nesting from the tokens of a body
+fn brace_depth_max(i: &str, tokens: &[Token]) -> usize {
+	let mut max = 0;
+	let mut depth = 0usize;
+	for t in tokens.iter().filter(|t| t.kind == TokenKind::Punct) {
+		match &i[t.span.clone()] {
+			"{" => {
+				depth += 1;
+				max = max.max(depth);
+			}
+			"}" => depth = depth.saturating_sub(1),
+			_ => (),
+		}
+	}
+	max
+}
+
+// Applies the escapes of each literal, with the line breaks of template
+// literals normalized as JavaScript does
+fn string_literals(i: &str, literals: &[(TokenKind, Range<usize>)]) -> Vec<LiteralSpan> {
+	literals
+		.iter()
+		.map(|(kind, span)| LiteralSpan {
+			kind: *kind,
+			span: span.clone(),
+			value: match kind {
+				TokenKind::TemplateLiteral => {
+					unescape(&i[span.clone()].replace("\r\n", "\n").replace('\r', "\n"))
+				}
+				_ => unescape(&i[span.clone()]),
+			},
+		})
+		.collect()
+}
+
+// Finds the features which a body may use from its significant tokens
+fn capabilities(tokens: &[(TokenKind, &str)]) -> ScriptCapabilities {
+	let names = decoded(tokens);
+	let tokens = &names.iter().map(|(k, v)| (*k, v.as_ref())).collect::<Vec<_>>();
+	let mut out = ScriptCapabilities::default();
+	let mut tokens = tokens.iter().copied().peekable();
+	while let Some((kind, v)) = tokens.next() {
+		if kind != TokenKind::Identifier {
+			continue;
+		}
+		match v {
+			"async" | "await" => out.uses_async = true,
+			"import" | "eval" | "Function" => out.uses_import = true,
+			"yield" => out.uses_generators = true,
+			"function" if tokens.peek() == Some(&(TokenKind::Punct, "*")) => {
+				out.uses_generators = true
+			}
+			"fetch" => out.uses_fetch = true,
+			_ => (),
+		}
+	}
+	out
+}
+
+// Checks whether the tokens of an expression are a single arrow function,
+// with either one parameter or a list of them in parentheses, where the
+// body of the function is everything after the `=>` up to any `,` which
+// would begin another expression of a sequence
+fn arrow(t: &[(TokenKind, &str)]) -> bool {
+	// An `async` which is not itself the parameter
+	let t = match t {
+		[(TokenKind::Identifier, "async"), (_, v), ..] if *v != "=" => &t[1..],
+		_ => t,
+	};
+	let params = match t.first() {
+		Some((TokenKind::Identifier, v)) if *v == "async" || !JS_KEYWORDS.contains(v) => 1,
+		Some((TokenKind::Punct, "(")) => {
+			let mut depth = 0usize;
+			let close = t.iter().position(|v| match v {
+				(TokenKind::Punct, "(" | "[" | "{") => {
+					depth += 1;
+					false
+				}
+				(TokenKind::Punct, ")" | "]" | "}") => {
+					depth = depth.saturating_sub(1);
+					depth == 0
+				}
+				_ => false,
+			});
+			match close {
+				Some(v) => v + 1,
+				None => return false,
+			}
+		}
+		_ => return false,
+	};
+	if t.get(params..params + 2) != Some(&[(TokenKind::Punct, "="), (TokenKind::Punct, ">")]) {
+		return false;
+	}
+	let body = &t[params + 2..];
+	let mut depth = 0usize;
+	!body.is_empty()
+		&& !body.iter().any(|v| match v {
+			(TokenKind::Punct, "(" | "[" | "{") => {
+				depth += 1;
+				false
+			}
+			(TokenKind::Punct, ")" | "]" | "}") => {
+				depth = depth.saturating_sub(1);
+				false
+			}
+			(TokenKind::Punct, ",") => depth == 0,
+			_ => false,
+		})
+}
+
+// Finds whether the body yields outside of any function nested within it,
+// where a brace opens the body of a function if it follows `=>`, or a `)`
+// which does not close the condition of a control statement
+fn generator(t: &[(TokenKind, &str)]) -> bool {
+	let names = decoded(t);
+	let t = &names.iter().map(|(k, v)| (*k, v.as_ref())).collect::<Vec<_>>();
+	let punct = |k: usize, p: &str| matches!(t.get(k), Some((TokenKind::Punct, v)) if *v == p);
+	let control = |k: usize| {
+		matches!(
+			t.get(k),
+			Some((
+				TokenKind::Identifier,
+				"if" | "for" | "await" | "while" | "switch" | "catch" | "with"
+			))
+		)
+	};
+	// Whether each open paren follows a control keyword
+	let mut parens: Vec<bool> = Vec::new();
+	// Whether each open brace is the body of a function
+	let mut braces: Vec<bool> = Vec::new();
+	// Whether the last paren which closed followed a control keyword
+	let mut closed = false;
+	for (k, (kind, v)) in t.iter().enumerate() {
+		match (kind, *v) {
+			(TokenKind::Punct, "(") => parens.push(control(k.wrapping_sub(1))),
+			(TokenKind::Punct, ")") => closed = parens.pop().unwrap_or(false),
+			(TokenKind::Punct, "{") => braces.push(
+				(punct(k.wrapping_sub(1), ")") && !closed)
+					|| (punct(k.wrapping_sub(1), ">") && punct(k.wrapping_sub(2), "=")),
+			),
+			(TokenKind::Punct, "}") => {
+				braces.pop();
+			}
+			(TokenKind::Identifier, "yield") if !braces.contains(&true) && !property(t, k) => {
+				return true
+			}
+			_ => (),
+		}
+	}
+	false
+}
+
+// Finds where a statement can be inserted before the first statement on
+// each line, with the line it is on. This is only done where it can not
+// change what the rest of the code means, so it is only done within the
+// blocks which hold statements, at the start of the body, after a `;` or
+// a block, or before a keyword which can only begin a statement.
+#[cfg(feature = "scripting")]
+fn instrument(i: &str, tokens: &[Token]) -> Vec<(usize, u32)> {
+	// A brace which is open, with whether it holds statements, whether it
+	// is the body of a `switch` or a `do`, and how many parens and brackets
+	// are open within it
+	struct Brace {
+		block: bool,
+		switch: bool,
+		repeat: bool,
+		nest: usize,
+		ternary: usize,
+	}
+	let lines = line_starts(i);
+	let line = |at: usize| lines.partition_point(|v| *v <= at) as u32;
+	let tokens: Vec<_> = tokens
+		.iter()
+		.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+		.map(|t| (t.kind, &i[t.span.clone()], t.span.clone()))
+		.collect();
+	let mut out = Vec::new();
+	let mut braces = vec![Brace {
+		block: true,
+		switch: false,
+		repeat: false,
+		nest: 0,
+		ternary: 0,
+	}];
+	// The keyword before each open paren, and before the last to close
+	let mut parens: Vec<Option<&str>> = Vec::new();
+	let mut closed: Option<&str> = None;
+	// Where the body of the last class to be declared will open
+	let mut class = None;
+	// Whether the last token ends a statement, and whether it closed a `do`
+	let mut ended = true;
+	let mut repeat = false;
+	let mut last: Option<(TokenKind, &str, usize)> = None;
+	let mut arrow = false;
+	for (kind, v, span) in tokens.iter().cloned() {
+		let top = braces.last().unwrap();
+		let first = match last {
+			None => true,
+			Some((_, _, end)) => line(span.start) > line(end.saturating_sub(1)),
+		};
+		if first && top.block && top.nest == 0 && kind == TokenKind::Identifier {
+			let keyword = matches!(
+				v,
+				"break"
+					| "const" | "continue"
+					| "debugger" | "for"
+					| "if" | "let" | "return"
+					| "switch" | "throw"
+					| "try" | "var" | "while"
+			);
+			let after = match last {
+				None => true,
+				Some((TokenKind::Identifier, "else" | "do", _)) => false,
+				Some((TokenKind::Identifier | TokenKind::Number, _, _)) => keyword,
+				Some((TokenKind::StringLiteral | TokenKind::TemplateLiteral, _, _)) => keyword,
+				Some((TokenKind::Regex | TokenKind::PrivateIdentifier, _, _)) => keyword,
+				Some((TokenKind::Punct, "]", _)) => keyword,
+				Some(_) => ended,
+			};
+			let continues = matches!(
+				v,
+				"else" | "catch" | "finally" | "case" | "default" | "in" | "instanceof" | "of"
+			) || (v == "while" && repeat);
+			if after && !continues {
+				out.push((span.start, line(span.start)));
+			}
+		}
+		let statement = std::mem::take(&mut ended);
+		repeat = false;
+		let depth = braces.len();
+		let top = braces.last_mut().unwrap();
+		match (kind, v) {
+			(TokenKind::Identifier, "class") => class = Some((depth, top.nest)),
+			(TokenKind::TemplateLiteral, v) => {
+				if v.starts_with('}') {
+					top.nest = top.nest.saturating_sub(1);
+				}
+				if v.ends_with(TEMPLATE_BEG) {
+					top.nest += 1;
+				}
+			}
+			(TokenKind::Punct, "(") => {
+				top.nest += 1;
+				parens.push(match last {
+					Some((TokenKind::Identifier, v, _)) => Some(v),
+					_ => None,
+				});
+			}
+			(TokenKind::Punct, "[") => top.nest += 1,
+			(TokenKind::Punct, ")") => {
+				top.nest = top.nest.saturating_sub(1);
+				closed = parens.pop().flatten();
+			}
+			(TokenKind::Punct, "]") => top.nest = top.nest.saturating_sub(1),
+			(TokenKind::Punct, ";") => ended = top.nest == 0,
+			(TokenKind::Punct, "?") if top.switch && top.nest == 0 => top.ternary += 1,
+			(TokenKind::Punct, ":") if top.switch && top.nest == 0 => match top.ternary {
+				0 => ended = true,
+				_ => top.ternary -= 1,
+			},
+			(TokenKind::Punct, "{") => {
+				let (block, switch, repeat) = match last {
+					// The body of a class holds its members
+					_ if class == Some((depth, top.nest)) => (false, false, false),
+					None => (true, false, false),
+					Some((TokenKind::Punct, ")", _)) => (true, closed == Some("switch"), false),
+					Some((TokenKind::Punct, ">", _)) => (arrow, false, false),
+					Some((TokenKind::Punct, ";" | "{" | "}" | ":", _)) => {
+						(top.block && statement, false, false)
+					}
+					Some((TokenKind::Identifier, v, _)) => match v {
+						"else" | "try" | "catch" | "finally" => (true, false, false),
+						"do" => (true, false, true),
+						_ => (false, false, false),
+					},
+					_ => (false, false, false),
+				};
+				if class == Some((depth, top.nest)) {
+					class = None;
+				}
+				braces.push(Brace {
+					block,
+					switch,
+					repeat,
+					nest: 0,
+					ternary: 0,
+				});
+				ended = block;
+			}
+			(TokenKind::Punct, "}") if braces.len() > 1 => {
+				let brace = braces.pop().unwrap();
+				ended = brace.block && !brace.switch;
+				repeat = brace.repeat;
+			}
+			_ => (),
+		}
+		arrow = v == ">" && matches!(last, Some((TokenKind::Punct, "=", end)) if end == span.start);
+		last = Some((kind, v, span.end));
+	}
+	out
+}
+
+// Finds the calls of `surrealdb.query()`, with the literal which is the
+// query of each call, or the span of the first argument where it is not
+// a literal. Any other use of `surrealdb`, other than `typeof surrealdb`
+// or the use of another of its properties, is reported as dynamic too.
+fn embedded_sql(i: &str, tokens: &[Token], literals: Vec<LiteralSpan>) -> EmbeddedSql {
+	let t: Vec<_> = tokens
+		.iter()
+		.filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+		.map(|t| (t.kind, &i[t.span.clone()], t.span.clone()))
+		.collect();
+	let text = |k: usize| t.get(k).map(|v| (v.0, v.1));
+	let punct = |k: usize, p: &str| text(k) == Some((TokenKind::Punct, p));
+	// The literal which is the whole of a token, if the token is a string,
+	// or a template literal with no interpolations
+	let literal = |k: usize| {
+		let (kind, v, span) = t.get(k)?;
+		let whole = match kind {
+			TokenKind::StringLiteral => true,
+			TokenKind::TemplateLiteral => v.len() > 1 && v.starts_with('`') && v.ends_with('`'),
+			_ => false,
+		};
+		if !whole {
+			return None;
+		}
+		literals.iter().find(|l| l.span.start == span.start + 1)
+	};
+	let mut out = EmbeddedSql::default();
+	for k in 0..t.len() {
+		if text(k) != Some((TokenKind::Identifier, "surrealdb")) {
+			continue;
+		}
+		// A property of another object is not the bridge, unless that
+		// object is the global object
+		if punct(k.wrapping_sub(1), ".")
+			&& text(k.wrapping_sub(2)) != Some((TokenKind::Identifier, "globalThis"))
+		{
+			continue;
+		}
+		// Find the `query` property, with or without optional chaining
+		let dot = match punct(k + 1, "?") && punct(k + 2, ".") {
+			true => k + 2,
+			false => k + 1,
+		};
+		let name = match (text(dot), text(dot + 1)) {
+			(Some((TokenKind::Punct, ".")), Some((TokenKind::Identifier, v))) => Some((v, dot + 2)),
+			(Some((TokenKind::Punct, "[")), _) if punct(dot + 2, "]") => {
+				literal(dot + 1).map(|v| (v.value.as_str(), dot + 3))
+			}
+			_ => None,
+		};
+		let call = match name {
+			Some(("query", next)) => match punct(next, "?") && punct(next + 1, ".") {
+				true => next + 2,
+				false => next,
+			},
+			// Checking for the bridge, or using another property, is allowed
+			Some(_) => continue,
+			None if text(k.wrapping_sub(1)) == Some((TokenKind::Identifier, "typeof")) => continue,
+			None => {
+				out.dynamic.push(t[k].2.clone());
+				continue;
+			}
+		};
+		if !punct(call, "(") {
+			out.dynamic.push(t[k].2.start..t[call - 1].2.end);
+			continue;
+		}
+		// Find the end of the first argument
+		let mut depth = 0usize;
+		let mut end = call + 1;
+		while let Some((kind, v)) = text(end) {
+			match (kind, v) {
+				(TokenKind::Punct, "(" | "[" | "{") => depth += 1,
+				(TokenKind::Punct, ")" | "]" | "}") if depth == 0 => break,
+				(TokenKind::Punct, ")" | "]" | "}") => depth -= 1,
+				(TokenKind::Punct, ",") if depth == 0 => break,
+				_ => (),
+			}
+			end += 1;
+		}
+		match literal(call + 1) {
+			Some(v) if end == call + 2 => out.queries.push(v.clone()),
+			_ if end > call + 1 => out.dynamic.push(t[call + 1].2.start..t[end - 1].2.end),
+			_ => (),
+		}
+	}
+	out
+}
+
+fn free_identifiers(t: &[(TokenKind, &str)]) -> BTreeSet<String> {
+	unbound(t, &[])
+}
+
+// Finds the names which are used but not declared, other than keywords and
+// those builtins which are not listed to be kept
+fn unbound(t: &[(TokenKind, &str)], keep: &[&str]) -> BTreeSet<String> {
+	let decoded = decoded(t);
+	let t = &decoded.iter().map(|(k, v)| (*k, v.as_ref())).collect::<Vec<_>>();
+	let punct = |k: usize, p: &str| matches!(t.get(k), Some((TokenKind::Punct, v)) if *v == p);
+	let ident = |k: usize| matches!(t.get(k), Some((TokenKind::Identifier, _)));
+	let property = |k: usize| property(t, k);
+	// The names declared in each scope
+	let mut names: Vec<BTreeSet<&str>> = vec![BTreeSet::new()];
+	// The scopes which are open, innermost last
+	let mut open = vec![Scope {
+		id: 0,
+		arrow: None,
+	}];
+	// Each use of a name, and the scopes which enclosed it
+	let mut uses: Vec<(usize, &str, Vec<usize>)> = Vec::new();
+	// Tokens which are declarations, rather than uses
+	let mut skip = vec![false; t.len()];
+	// Parameters to be declared in the next block
+	let mut params: Vec<&str> = Vec::new();
+	// The bracket depth of a declaration, and whether a name is expected
+	let mut decl: Option<(usize, bool)> = None;
+	let mut depth = 0usize;
+	// Collects the names in a parameter list which begins at `k`
+	let list = |k: usize, skip: &mut Vec<bool>| {
+		let mut out = Vec::new();
+		let mut level = 0usize;
+		for j in k..t.len() {
+			match t[j] {
+				(TokenKind::Punct, "(" | "[" | "{") => level += 1,
+				(TokenKind::Punct, ")" | "]" | "}") => {
+					level -= 1;
+					if level == 0 {
+						break;
+					}
+				}
+				(TokenKind::Identifier, v)
+					if level == 1 && (punct(j - 1, "(") || punct(j - 1, ",")) =>
+				{
+					skip[j] = true;
+					out.push(v);
+				}
+				_ => (),
+			}
+		}
+		out
+	};
+	// Collects the names in a destructuring pattern which begins at `k`,
+	// returning the names and the index at which the pattern ends
+	let pattern = |k: usize| {
+		let mut out = Vec::new();
+		let mut level = 0usize;
+		for (j, v) in t.iter().enumerate().skip(k) {
+			match *v {
+				(TokenKind::Punct, "[" | "{") => level += 1,
+				(TokenKind::Punct, "]" | "}") => {
+					level -= 1;
+					if level == 0 {
+						return (out, j);
+					}
+				}
+				(TokenKind::Identifier, v)
+					if !punct(j + 1, ":")
+						&& ["{", "[", ",", ":", "."].iter().any(|p| punct(j - 1, p)) =>
+				{
+					out.push(v)
+				}
+				_ => (),
+			}
+		}
+		(out, t.len())
+	};
+	let mut k = 0;
+	while k < t.len() {
+		match t[k] {
+			(TokenKind::Punct, "{" | "[") if matches!(decl, Some((_, true))) => {
+				let (v, end) = pattern(k);
+				let id = open.last().map_or(0, |s| s.id);
+				names[id].extend(v);
+				decl = decl.map(|(d, _)| (d, false));
+				k = end;
+			}
+			(TokenKind::Punct, "{") => {
+				names.push(params.drain(..).collect());
+				open.push(Scope {
+					id: names.len() - 1,
+					arrow: None,
+				});
+				decl = None;
+			}
+			(TokenKind::Punct, "}") => {
+				while open.len() > 1
+					&& matches!(
+						open.last(),
+						Some(Scope {
+							arrow: Some(_),
+							..
+						})
+					) {
+					open.pop();
+				}
+				if open.len() > 1 {
+					open.pop();
+				}
+			}
+			(TokenKind::Punct, "(" | "[") => depth += 1,
+			(TokenKind::Punct, v @ (")" | "]" | "," | ";")) => {
+				// An arrow function's expression body ends here
+				while matches!(open.last(), Some(Scope { arrow: Some(d), .. }) if *d >= depth) {
+					open.pop();
+				}
+				match v {
+					")" | "]" => depth = depth.saturating_sub(1),
+					";" => decl = None,
+					_ => {
+						if let Some((d, _)) = decl {
+							if d == depth {
+								decl = Some((d, true));
+							}
+						}
+					}
+				}
+			}
+			(TokenKind::Punct, "=") if punct(k + 1, ">") => {
+				// The parameters of an arrow function have already been seen
+				let mut args = Vec::new();
+				if ident(k.wrapping_sub(1)) {
+					skip[k - 1] = true;
+					args.push(t[k - 1].1);
+				} else if punct(k.wrapping_sub(1), ")") {
+					let mut level = 0usize;
+					let mut j = k - 1;
+					loop {
+						match t[j] {
+							(TokenKind::Punct, ")") => level += 1,
+							(TokenKind::Punct, "(") => {
+								level -= 1;
+								if level == 0 {
+									break;
+								}
+							}
+							_ => (),
+						}
+						if j == 0 {
+							break;
+						}
+						j -= 1;
+					}
+					args = list(j, &mut skip);
+				}
+				uses.retain(|(j, _, _)| !skip[*j]);
+				k += 1;
+				match punct(k + 1, "{") {
+					true => params = args,
+					false => {
+						names.push(args.into_iter().collect());
+						open.push(Scope {
+							id: names.len() - 1,
+							arrow: Some(depth),
+						});
+					}
+				}
+			}
+			(TokenKind::Identifier, _) if property(k) => (),
+			(TokenKind::Identifier, "function" | "class") => {
+				if ident(k + 1) {
+					skip[k + 1] = true;
+					let id = open.last().map_or(0, |s| s.id);
+					names[id].insert(t[k + 1].1);
+				}
+				if t[k].1 == "function" {
+					let j = if ident(k + 1) {
+						k + 2
+					} else {
+						k + 1
+					};
+					if punct(j, "(") {
+						params = list(j, &mut skip);
+					}
+				}
+			}
+			(TokenKind::Identifier, "catch") if punct(k + 1, "(") => {
+				params = list(k + 1, &mut skip);
+			}
+			(TokenKind::Identifier, "var" | "let" | "const") => {
+				decl = Some((depth, true));
+			}
+			(TokenKind::Identifier, v) if !skip[k] => {
+				if let Some((d, true)) = decl {
+					decl = Some((d, false));
+					let id = open.last().map_or(0, |s| s.id);
+					names[id].insert(v);
+				} else if JS_KEYWORDS.contains(&v)
+					|| (JS_BUILTINS.contains(&v) && !keep.contains(&v))
+				{
+					// Keywords and builtins are always bound
+				} else {
+					uses.push((k, v, open.iter().map(|s| s.id).collect()));
+				}
+			}
+			_ => (),
+		}
+		k += 1;
+	}
+	uses.into_iter()
+		.filter(|(_, v, scopes)| !scopes.iter().any(|id| names[*id].contains(v)))
+		.map(|(_, v, _)| v.to_owned())
+		.collect()
+}
+
+// Masks the contents of string and template literals in a script body
+fn redact(i: &str) -> String {
+	let mut out = String::with_capacity(i.len());
+	let mut s = i;
+	loop {
+		let mut pos = 0;
+		// Whether the last part written was masked template text
+		let mut masked = false;
+		let res = scan(s, cnf::MAX_SCRIPT_DEPTH, |part, v| {
+			pos += v.len();
+			match part {
+				ScriptPart::String if v.len() > 2 => {
+					out.push_str(&v[..1]);
+					out.push_str("***");
+					out.push_str(&v[v.len() - 1..]);
+				}
+				ScriptPart::Template if !matches!(v, "`" | TEMPLATE_BEG | "}") => {
+					if !std::mem::replace(&mut masked, true) {
+						out.push_str("***");
+					}
+					return;
+				}
+				_ => out.push_str(v),
+			}
+			masked = false;
+		});
+		match res {
+			// The whole of the input was scanned
+			Ok(_) if pos == s.len() => break,
+			// The scan stopped at an unmatched `}`
+			Ok(_) => {
+				out.push('}');
+				s = &s[pos + 1..];
+			}
+			// The rest of the input might be part of a literal
+			Err(_) => {
+				out.push_str("***");
+				break;
+			}
+		}
+	}
+	out
+}
+
+// Converts the line endings in a script body to `\n`, outside of literals
+fn normalize_newlines(i: &str) -> String {
+	let mut out = String::with_capacity(i.len());
+	let _ = scan(i, cnf::MAX_SCRIPT_DEPTH, |part, v| match part {
+		ScriptPart::Code | ScriptPart::Comment => {
+			out.push_str(&v.replace("\r\n", "\n").replace('\r', "\n"))
+		}
+		_ => out.push_str(v),
+	});
+	out
+}
+
+// Applies the escapes in the text of a string or template literal, as
+// JavaScript does. An escape which is not valid, such as `\x` without two
+// hex digits after it, is kept as it is, and an unpaired surrogate gives
+// the replacement character.
+fn unescape(v: &str) -> String {
+	let mut out = String::with_capacity(v.len());
+	// A high surrogate which is waiting for a low surrogate
+	let mut high: Option<u32> = None;
+	let mut i = 0;
+	while let Some(c) = v[i..].chars().next() {
+		i += c.len_utf8();
+		let code = match (c, v[i..].chars().next()) {
+			('\\', Some(e)) => {
+				i += e.len_utf8();
+				match e {
+					'n' => 0x0a,
+					'r' => 0x0d,
+					't' => 0x09,
+					'b' => 0x08,
+					'f' => 0x0c,
+					'v' => 0x0b,
+					'0' if !v[i..].starts_with(|c: char| c.is_ascii_digit()) => 0,
+					// A line continuation is not part of the value
+					'\r' => {
+						i += v[i..].starts_with('\n') as usize;
+						continue;
+					}
+					'\n' | '\u{2028}' | '\u{2029}' => continue,
+					'x' | 'u' => match hex_escape(e, &v[i..]) {
+						Some((code, len)) => {
+							i += len;
+							code
+						}
+						None => {
+							i -= 1;
+							'\\' as u32
+						}
+					},
+					e => e as u32,
+				}
+			}
+			(c, _) => c as u32,
+		};
+		// Pair up surrogates, replacing any which are unpaired
+		if let Some(h) = high.take() {
+			if (0xdc00..=0xdfff).contains(&code) {
+				let v = 0x10000 + ((h - 0xd800) << 10) + (code - 0xdc00);
+				out.push(char::from_u32(v).unwrap_or(char::REPLACEMENT_CHARACTER));
+				continue;
+			}
+			out.push(char::REPLACEMENT_CHARACTER);
+		}
+		match code {
+			0xd800..=0xdbff => high = Some(code),
+			_ => out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)),
+		}
+	}
+	if high.is_some() {
+		out.push(char::REPLACEMENT_CHARACTER);
+	}
+	out
+}
+
+// Finds the byte offset at which each line of a body begins, where `\r\n`
+// and a lone `\r` each end a line, as they do in JavaScript
+fn line_starts(i: &str) -> Vec<usize> {
+	let mut out = vec![0];
+	let mut it = i.char_indices().peekable();
+	while let Some((k, c)) = it.next() {
+		match c {
+			'\r' if matches!(it.peek(), Some((_, '\n'))) => (),
+			'\n' | '\r' => out.push(k + 1),
+			_ => (),
+		}
+	}
+	out
+}
+
+// Converts a byte offset within a body into a line, and a column in UTF-16
+// code units, so that a character outside the BMP counts as two
+fn editor_position(i: &str, lines: &[usize], offset: usize) -> EditorPosition {
+	let line = lines.partition_point(|v| *v <= offset).saturating_sub(1);
+	EditorPosition {
+		line: line as u32,
+		character: i[lines[line]..offset].encode_utf16().count() as u32,
+	}
+}
+
+// Writes the value of a string or template literal with the fewest escapes
+// which give the same value, within literals quoted with `quote`
+fn escape(v: &str, quote: char) -> String {
+	let mut out = String::with_capacity(v.len());
+	let mut chars = v.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			'\u{8}' => out.push_str("\\b"),
+			'\u{c}' => out.push_str("\\f"),
+			'\u{b}' => out.push_str("\\v"),
+			// A `\0` which is followed by a digit would be an octal escape
+			'\0' => match matches!(chars.peek(), Some(c) if c.is_ascii_digit()) {
+				true => out.push_str("\\x00"),
+				false => out.push_str("\\0"),
+			},
+			'\u{2028}' | '\u{2029}' => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+			c if c == quote => {
+				out.push('\\');
+				out.push(c);
+			}
+			// A `${` would begin an interpolation in a template literal
+			'$' if quote == '`' && chars.peek() == Some(&'{') => out.push_str("\\$"),
+			c => out.push(c),
+		}
+	}
+	out
+}
+
+// Checks that each escape in the text of a literal is valid in strict mode
+// code, where legacy octal escapes, such as `\1`, are not allowed
+fn valid_escapes(v: &str) -> bool {
+	let mut chars = v.char_indices();
+	while let Some((_, c)) = chars.next() {
+		if c != '\\' {
+			continue;
+		}
+		match chars.next() {
+			Some((i, e @ ('x' | 'u'))) => match hex_escape(e, &v[i + 1..]) {
+				Some((_, len)) => {
+					chars.nth(len - 1);
+				}
+				None => return false,
+			},
+			Some((_, '1'..='9')) => return false,
+			Some((i, '0')) if v[i + 1..].starts_with(|c: char| c.is_ascii_digit()) => return false,
+			_ => (),
+		}
+	}
+	true
+}
+
+// Parses the digits of a `\x` or `\u` escape at the start of `v`, returning
+// the code unit or code point, and the number of characters it spans
+fn hex_escape(e: char, v: &str) -> Option<(u32, usize)> {
+	let hex = |v: &str| match v.chars().all(|c| c.is_ascii_hexdigit()) {
+		true => u32::from_str_radix(v, 16).ok(),
+		false => None,
+	};
+	match e {
+		'x' => Some((hex(v.get(..2)?)?, 2)),
+		_ if v.starts_with('{') => {
+			let end = v.find('}')?;
+			let code = hex(&v[1..end]).filter(|c| *c <= 0x10ffff)?;
+			Some((code, end + 1))
+		}
+		_ => Some((hex(v.get(..4)?)?, 4)),
+	}
+}
+
+// Fails unrecoverably if a nested block would exceed the maximum depth
+fn nested(i: &str, depth: usize) -> Result<usize, Err<Error<&str>>> {
+	match depth.checked_sub(1) {
+		Some(depth) => Ok(depth),
+		None => Err(Err::Failure(Error::ScriptError {
+			input: i,
+			offset: 0,
+			kind: ScriptErrorKind::DepthExceeded,
+			message: "script exceeds the maximum nesting depth",
+		})),
+	}
+}
+
+// Decides whether a regex may follow a run of plain script text, based
+// on the last significant token before it. A `/` is division after an
+// operand, such as a name, a number, a literal, or a closing `)` or `]`,
+// and begins a regex after an operator, a keyword such as `return`, or
+// an opening bracket. This is a heuristic, rather than a full JavaScript
+// grammar, so it is wrong in some rare cases: a `/` after a `}` always
+// begins a regex, even if the `}` closed an object literal, and one at
+// the start of a line is taken to continue the line before it, even if
+// automatic semicolon insertion would end the statement there instead.
+// As parens are not tracked, a `/` after the `)` which closes the head of
+// an `if`, a `for` or a `while` is division too, so `if (x) /a}/.test(y)`
+// is rejected, while `if (x) { /a}/.test(y) }` is not.
+fn regex_allowed(v: &str, prev: bool) -> bool {
+	let v = v.trim_end();
+	match v.chars().last() {
+		None => prev,
+		Some(c) if is_ident_char(c) => {
+			let rest = v.trim_end_matches(is_ident_char);
+			// A keyword used as a property name is an operand
+			REGEX_KEYWORDS.contains(&&v[rest.len()..]) && !rest.trim_end().ends_with('.')
+		}
+		Some(')' | ']') => false,
+		// A `++` or `--` is postfix if it follows an operand, and so is
+		// followed by division, or is prefix and followed by an operand
+		Some('+' | '-') if v.ends_with("++") || v.ends_with("--") => {
+			regex_allowed(&v[..v.len() - 2], prev)
+		}
+		Some(_) => true,
+	}
+}
+
+fn is_ident_char(c: char) -> bool {
+	c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+// Checks if a name can be used as a JavaScript variable name
+pub(crate) fn is_identifier(v: &str) -> bool {
+	match v.chars().next() {
+		Some(c) if c.is_ascii_digit() => false,
+		Some(_) => v.chars().all(is_ident_char) && !JS_KEYWORDS.contains(&v),
+		None => false,
+	}
+}
+
+// Matches a run of script text up to the next structural character. The
+// input is only searched as far as the end of the run, as searching past
+// it would make a body of many short runs take quadratic time to scan.
+fn char_any(i: &str) -> IResult<&str, &str> {
+	let end = i
+		.bytes()
+		.position(|b| matches!(b, b'{' | b'}' | b'/' | b'\'' | b'"' | b'`'))
+		.unwrap_or(i.len());
+	match end {
+		0 => Err(Err::Error(ParserError(i))),
+		_ => Ok((&i[end..], &i[..end])),
+	}
+}
+
+fn regex_literal(i: &str) -> IResult<&str, &str> {
+	let (i, _) = tag(SLASH)(i)?;
+	let (i, v) = recognize(many0(regex_char))(i)?;
+	let (i, _) = tag(SLASH)(i)?;
+	let (i, _) = take_while(is_ident_char)(i)?;
+	Ok((i, v))
+}
+
+fn regex_char(i: &str) -> IResult<&str, &str> {
+	alt((is_not("/\\[\n"), regex_escape, regex_class))(i)
+}
+
+fn regex_class(i: &str) -> IResult<&str, &str> {
+	let (i, _) = tag(REGEX_CLASS_BEG)(i)?;
+	let (i, v) = recognize(many0(alt((is_not("]\\\n"), regex_escape))))(i)?;
+	let (i, _) = tag(REGEX_CLASS_END)(i)?;
+	Ok((i, v))
+}
+
+fn regex_escape(i: &str) -> IResult<&str, &str> {
+	let (s, _) = tag("\\")(i)?;
+	let (s, v) = anychar(s)?;
+	match v {
+		'\n' => Err(Err::Error(ParserError(i))),
+		_ => Ok((s, &i[..i.len() - s.len()])),
+	}
+}
+
+// Scans a string literal at the start of `s`, resuming after the first
+// `from` bytes, which are known not to end it. Returns the input after
+// the closing quote, or the offset up to which the literal was scanned.
+fn string_literal(s: &str, from: usize) -> Result<&str, usize> {
+	let v = s.as_bytes();
+	let mut i = from.max(1);
+	while let Some(p) = memchr2(v[0], b'\\', &v[i..]) {
+		let p = i + p;
+		match v[p] == v[0] {
+			true => return Ok(&s[p + 1..]),
+			// The escaped character can not be a quote or a backslash, so
+			// skipping only its first byte is enough
+			false if p + 1 < v.len() => i = p + 2,
+			false => return Err(p),
+		}
+	}
+	Err(v.len())
+}
+
+// Scans a line or block comment, or a hashbang, at the start of `s`,
+// resuming after the first `from` bytes, which are known not to end it.
+// Returns the input after the comment, or the offset up to which the
+// comment was scanned, if it does not end within `s`.
+fn comment(s: &str, from: usize) -> Result<&str, usize> {
+	let v = s.as_bytes();
+	match v[1] {
+		b'/' | b'!' => {
+			let i = from.max(2);
+			match memchr2(b'\n', b'\r', &v[i..]) {
+				Some(p) => Ok(&s[i + p..]),
+				None => Err(v.len()),
+			}
+		}
+		_ => {
+			// The `*` of the end may be the last byte which was scanned
+			let i = from.saturating_sub(1).max(2);
+			match memmem::find(&v[i..], COMMENT_END.as_bytes()) {
+				Some(p) => Ok(&s[i + p + 2..]),
+				None => Err(v.len()),
+			}
+		}
+	}
+}
+
+fn template_text(i: &str) -> IResult<&str, &str> {
+	alt((is_not(BACKTICK_ESC), template_escape, template_dollar))(i)
+}
+
+fn template_escape(i: &str) -> IResult<&str, &str> {
+	recognize(pair(tag("\\"), anychar))(i)
+}
+
+fn template_dollar(i: &str) -> IResult<&str, &str> {
+	let (i, v) = tag("$")(i)?;
+	let (i, _) = not(tag("{"))(i)?;
+	Ok((i, v))
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn script_basic() {
+		let sql = "return true;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return true;", format!("{}", out));
+		assert_eq!(out, Script::from("return true;"));
+	}
+
+	#[test]
+	fn script_object() {
+		let sql = "return { test: true, something: { other: true } };";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return { test: true, something: { other: true } };", format!("{}", out));
+		assert_eq!(out, Script::from("return { test: true, something: { other: true } };"));
+	}
+
+	#[test]
+	fn script_closure() {
+		let sql = "return this.values.map(v => `This value is ${Number(v * 3)}`);";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(
+			"return this.values.map(v => `This value is ${Number(v * 3)}`);",
+			format!("{}", out)
+		);
+		assert_eq!(
+			out,
+			Script::from("return this.values.map(v => `This value is ${Number(v * 3)}`);")
+		);
+	}
+
+	#[test]
+	fn script_complex() {
+		let sql = r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#;
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(
+			r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#,
+			format!("{}", out)
+		);
+		assert_eq!(
+			out,
+			Script::from(
+				r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#
+			)
+		);
+	}
+
+	#[test]
+	fn script_split_at_statement() {
+		let sql = r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#;
+		let out = Script::from(sql);
+		// The complex example is a single statement
+		let (head, tail) = out.split_at_statement(0).unwrap();
+		assert_eq!((head.as_str(), tail.as_str()), ("", sql));
+		let (head, tail) = out.split_at_statement(1).unwrap();
+		assert_eq!((head.as_str(), tail.as_str()), (sql, ""));
+		assert_eq!(out.split_at_statement(2), None);
+		// Code can be inserted before the last statement
+		let out = Script::from(format!(
+			"// setup\nlet a = {{ b: '}}; {{' }};\nif (a) {{ a.c = 1; }}\n{}",
+			sql
+		));
+		assert_eq!(out.statement_count(), 3);
+		let (head, tail) = out.split_at_statement(2).unwrap();
+		assert_eq!(head.as_str(), "// setup\nlet a = { b: '}; {' };\nif (a) { a.c = 1; }\n");
+		assert_eq!(tail.as_str(), sql);
+		let res = Script::new(format!("{}a.d = 2;\n{}", head, tail)).unwrap();
+		assert_eq!(res.statement_count(), 4);
+		let (head, tail) = out.split_at_statement(1).unwrap();
+		assert_eq!(tail.statement_count(), 2);
+		assert_eq!(format!("{}{}", head, tail), out.as_str());
+		// The head of a `for` and an `if` with its `else` are never split
+		let out = Script::from("for (let i = 0; i < 3; i++) { x(i); } return 1;");
+		let (head, tail) = out.split_at_statement(1).unwrap();
+		assert_eq!(
+			(head.as_str(), tail.as_str()),
+			("for (let i = 0; i < 3; i++) { x(i); } ", "return 1;")
+		);
+		assert_eq!(out.split_at_statement(2).unwrap().0, out);
+		assert_eq!(out.split_at_statement(3), None);
+		let out = Script::from("if (a) b; else c;\nd()");
+		let (head, tail) = out.split_at_statement(1).unwrap();
+		assert_eq!((head.as_str(), tail.as_str()), ("if (a) b; else c;\n", "d()"));
+		assert_eq!(out.split_at_statement(2).unwrap().0, out);
+		// A body which does not parse is not split
+		assert_eq!(Script::from("let a = {; return a;").split_at_statement(0), None);
+	}
+
+	#[test]
+	fn script_comment_line() {
+		let sql = "// don't forget }\nreturn { test: true }; // trailing ` \" {";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(
+			"// don't forget }\nreturn { test: true }; // trailing ` \" {",
+			format!("{}", out)
+		);
+		assert_eq!(
+			out,
+			Script::from("// don't forget }\nreturn { test: true }; // trailing ` \" {")
+		);
+	}
+
+	#[test]
+	fn script_division() {
+		let sql = "return 10 / 2;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return 10 / 2;", format!("{}", out));
+	}
+
+	#[test]
+	fn script_comment_block_braces() {
+		let sql = "/* }}} */ return { test: true };";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("/* }}} */ return { test: true };", format!("{}", out));
+		assert_eq!(out, Script::from("/* }}} */ return { test: true };"));
+	}
+
+	#[test]
+	fn script_comment_block_backtick() {
+		let sql = "return /* a stray ` and a ' */ true;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return /* a stray ` and a ' */ true;", format!("{}", out));
+		assert_eq!(out, Script::from("return /* a stray ` and a ' */ true;"));
+	}
+
+	#[test]
+	fn script_comment_block_unterminated() {
+		let sql = "return true; /* never closed { ";
+		let res = script(sql);
+		assert!(res.is_err());
+	}
+
+	#[test]
+	fn script_regex_division() {
+		let sql = "return a / b / c;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return a / b / c;", format!("{}", out));
+		assert_eq!(out, Script::from("return a / b / c;"));
+	}
+
+	#[test]
+	fn script_regex_braces() {
+		let sql = r#"return s.replace(/\}\{/g, "");"#;
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(r#"return s.replace(/\}\{/g, "");"#, format!("{}", out));
+		assert_eq!(out, Script::from(r#"return s.replace(/\}\{/g, "");"#));
+	}
+
+	#[test]
+	fn script_regex_class() {
+		let sql = "let m = s.match(/[}{/'\"]+/g); return m;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("let m = s.match(/[}{/'\"]+/g); return m;", format!("{}", out));
+		assert_eq!(out, Script::from("let m = s.match(/[}{/'\"]+/g); return m;"));
+	}
+
+	#[test]
+	fn script_regex_return() {
+		let sql = "return /ab}c/i.test(this.name);";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return /ab}c/i.test(this.name);", format!("{}", out));
+		assert_eq!(out, Script::from("return /ab}c/i.test(this.name);"));
+	}
+
+	#[test]
+	fn script_regex_mixed() {
+		let sql = "let re = /ab/; let q = a / b; return re.test(q) ? a++ / 2 / c : --b / 2 / c;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(out, Script::from(sql));
+		let res: Vec<_> = out.tokens().filter(|t| t.kind == TokenKind::Regex).collect();
+		assert_eq!(res.len(), 1);
+		assert_eq!(&out[res[0].span.clone()], "/ab/");
+		let res =
+			out.tokens().filter(|t| t.kind == TokenKind::Punct && &out[t.span.clone()] == "/");
+		assert_eq!(res.count(), 5);
+	}
+
+	#[test]
+	fn script_regex_arrow() {
+		let sql = "return this.tags.filter(v => /^a}b/i.test(v)).map(v => v / 2);";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(out, Script::from(sql));
+		let res: Vec<_> =
+			out.tokens().filter(|t| t.kind == TokenKind::Regex).map(|t| &out[t.span]).collect();
+		assert_eq!(res, vec!["/^a}b/i"]);
+	}
+
+	#[test]
+	fn script_regex_after_operators() {
+		for v in [
+			"=", "(", ",", "&&", "||", "!", "?", ":", "[", ";", "{", "=>", "return", "typeof", "++",
+		] {
+			let sql = format!("{} /}}/", v);
+			let out = Script::from(sql.as_str());
+			let res = out.tokens().any(|t| t.kind == TokenKind::Regex);
+			assert!(res, "{:?}", sql);
+		}
+		for v in ["a", "1", ")", "]", "'a'", "`a`", "/a/", "a++", "a--", "this"] {
+			let sql = format!("{} / b / c", v);
+			let out = Script::from(sql.as_str());
+			let res = out.tokens().filter(|t| t.kind == TokenKind::Regex).count();
+			assert_eq!(res, v.starts_with('/') as usize, "{:?}", sql);
+		}
+	}
+
+	#[test]
+	fn script_regex_after_head() {
+		// A `/` after the head of an `if` or a loop is taken to be division
+		let sql = "if (x) /a}/.test(y);";
+		let (i, out) = script(sql).unwrap();
+		assert_eq!((i, out.as_str()), ("}/.test(y);", "if (x) /a"));
+		// So a regex there is written within a block, or in parens
+		for sql in ["if (x) { /a}/.test(y); }", "while (x) (/a}/.test(y));"] {
+			let (i, out) = script(sql).unwrap();
+			assert_eq!((i, out.as_str()), ("", sql));
+		}
+	}
+
+	#[test]
+	fn script_template_nested() {
+		let sql = "return `a ${ `b ${ `c ${x} }` }` } d`;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return `a ${ `b ${ `c ${x} }` }` } d`;", format!("{}", out));
+		assert_eq!(out, Script::from("return `a ${ `b ${ `c ${x} }` }` } d`;"));
+	}
+
+	#[test]
+	fn script_template_object() {
+		let sql = "return `a ${ {x: { y: 1 }}.x.y } b {`;";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return `a ${ {x: { y: 1 }}.x.y } b {`;", format!("{}", out));
+		assert_eq!(out, Script::from("return `a ${ {x: { y: 1 }}.x.y } b {`;"));
+	}
+
+	#[test]
+	fn script_escape_backslash() {
+		let sql = r"return '\\' + 'it\'s a \n line';";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(r"return '\\' + 'it\'s a \n line';", format!("{}", out));
+		assert_eq!(out, Script::from(r"return '\\' + 'it\'s a \n line';"));
+	}
+
+	#[test]
+	fn script_escape_sequences() {
+		let sql = r#"return "line\nbreak \t \x41 \u0041 \u{1F600} {" + `\${ \\ \x41 }`;"#;
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(
+			r#"return "line\nbreak \t \x41 \u0041 \u{1F600} {" + `\${ \\ \x41 }`;"#,
+			format!("{}", out)
+		);
+		assert_eq!(
+			out,
+			Script::from(r#"return "line\nbreak \t \x41 \u0041 \u{1F600} {" + `\${ \\ \x41 }`;"#)
+		);
+	}
+
+	#[test]
+	fn script_escape_trailing() {
+		let sql = r#"return "C:\\path\\" + 'C:\\path\\';"#;
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(r#"return "C:\\path\\" + 'C:\\path\\';"#, format!("{}", out));
+		assert_eq!(out, Script::from(r#"return "C:\\path\\" + 'C:\\path\\';"#));
+	}
+
+	#[test]
+	fn script_unterminated_single() {
+		let sql = "return { a: 'oops };";
+		let res = script(sql);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 12,
+				kind: ScriptErrorKind::UnterminatedString,
+				message: "unterminated single-quoted string",
+				..
+			}))
+		));
+	}
+
+	#[test]
+	fn script_unterminated_double() {
+		let sql = r#"return "oops;"#;
+		let res = script(sql);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 7,
+				kind: ScriptErrorKind::UnterminatedString,
+				message: "unterminated double-quoted string",
+				..
+			}))
+		));
+	}
+
+	#[test]
+	fn script_unterminated_backtick() {
+		let sql = "let a = 1;\nreturn `value ${a} \\`;";
+		let res = script(sql);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 18,
+				kind: ScriptErrorKind::UnterminatedString,
+				message: "unterminated template literal",
+				..
+			}))
+		));
+	}
+
+	#[test]
+	fn script_empty() {
+		let sql = "";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let (i, out) = res.unwrap();
+		assert_eq!(i, "");
+		assert_eq!("", format!("{}", out));
+		assert_eq!(out, Script::from(""));
+	}
+
+	#[test]
+	fn script_empty_object() {
+		let sql = "return {};";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!("return {};", format!("{}", out));
+		assert_eq!(out, Script::from("return {};"));
+	}
+
+	#[test]
+	fn script_empty_statements() {
+		use crate::sql::function::function;
+		let tests = [";;;", "{ }", "{ ; }", " ;\n{ };; ", "{}{;}\t"];
+		for sql in tests {
+			let (i, out) = script(sql).unwrap();
+			assert_eq!(i, "", "{:?}", sql);
+			assert_eq!(format!("{}", out), sql);
+			assert_eq!(Script::from_str(sql), Ok(Script::from(sql)));
+			// The body is kept exactly within a function too
+			let res = format!("function() {{{}}}", sql);
+			let (i, out) = function(&res).unwrap();
+			assert_eq!(i, "", "{:?}", sql);
+			assert_eq!(format!("{}", out), res);
+		}
+		// Only minifying or formatting changes the body
+		assert_eq!(Script::from("{ ; }").minify(), "{;}");
+		assert_ne!(Script::from("{ ; }").format(4), "{ ; }");
+	}
+
+	#[test]
+	fn script_depth_nested() {
+		let sql = format!("return {}{};", "{ a: ".repeat(10), "}".repeat(10));
+		let res = script_ref_with(
+			&sql,
+			&ScriptOptions {
+				depth: 10,
+				..Default::default()
+			},
+		);
+		assert!(res.is_ok());
+		let res = script_ref_with(
+			&sql,
+			&ScriptOptions {
+				depth: 9,
+				..Default::default()
+			},
+		);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 52,
+				kind: ScriptErrorKind::DepthExceeded,
+				message: "script exceeds the maximum nesting depth",
+				..
+			}))
+		));
+	}
+
+	#[test]
+	fn script_depth_template() {
+		let sql = "return `${`${`${ 1 }`}`}`;";
+		let res = script_ref_with(
+			sql,
+			&ScriptOptions {
+				depth: 3,
+				..Default::default()
+			},
+		);
+		assert!(res.is_ok());
+		let res = script_ref_with(
+			sql,
+			&ScriptOptions {
+				depth: 2,
+				..Default::default()
+			},
+		);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 14,
+				..
+			}))
+		));
+	}
+
+	#[test]
+	fn script_depth_exceeded() {
+		let sql = format!("{}{}", "{".repeat(100_000), "}".repeat(100_000));
+		let res = script(&sql);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 256,
+				..
+			}))
+		));
+	}
+
+	#[test]
+	fn script_nested_linear() {
+		// Maximally nested blocks, repeated to make up a large body
+		let nested = format!("{}{}", "{".repeat(256), "}".repeat(256)).repeat(200);
+		// Flat sibling blocks, making up a body of the same length
+		let flat = "{}".repeat(256 * 200);
+		assert_eq!(nested.len(), flat.len());
+		let now = std::time::Instant::now();
+		let res = script(&nested);
+		let nested_time = now.elapsed();
+		assert_eq!(res.unwrap().1.len(), nested.len());
+		let now = std::time::Instant::now();
+		let res = script(&flat);
+		let flat_time = now.elapsed();
+		assert_eq!(res.unwrap().1.len(), flat.len());
+		// Deep nesting should cost no more than flat input of the same size
+		assert!(nested_time < flat_time * 10 + std::time::Duration::from_millis(50));
+	}
+
+	#[test]
+	fn script_adversarial_linear() {
+		let size = 200_000;
+		// A body of many short parts, to compare the time taken with
+		let flat = "{}".repeat(size / 2);
+		let now = std::time::Instant::now();
+		assert!(script(&flat).is_ok());
+		let flat_time = now.elapsed();
+		let tests = [
+			"`${",
+			"${`",
+			"{`",
+			"'\"`",
+			"\\'\"",
+			"'a' ",
+			"\"\\\"\" ",
+			"`a` ",
+			"=/[",
+			"=/\\",
+			"/[/]",
+			"/*/",
+			"//\n",
+			"{'",
+			"`${'`'}",
+			"$",
+			"`$`",
+			"é'é'",
+		];
+		for v in tests {
+			let sql = v.repeat(size / v.len());
+			let now = std::time::Instant::now();
+			let res = script(&sql);
+			let time = now.elapsed();
+			if let Ok((rest, out)) = res {
+				assert_eq!(rest.len() + out.len(), sql.len());
+			}
+			// Each body should scan in linear time
+			assert!(
+				time < flat_time * 10 + std::time::Duration::from_millis(100),
+				"{:?} took {:?}",
+				v,
+				time
+			);
+		}
+	}
+
+	// Returns the number of bytes which the scanner examines while running `f`
+	fn visits(f: impl FnOnce()) -> usize {
+		VISITED.with(|v| v.set(0));
+		f();
+		VISITED.with(|v| v.get())
+	}
+
+	#[test]
+	fn script_visits_linear() {
+		let opts = ScriptOptions {
+			depth: usize::MAX,
+			max_len: usize::MAX,
+			..Default::default()
+		};
+		// Most of these bodies do not settle until their end, so each byte
+		// must be examined a bounded number of times, whether the body is
+		// parsed all at once or fed a single byte at a time
+		let families: [fn(usize) -> String; 12] = [
+			|n| format!("{}{}", "{".repeat(n), "}".repeat(n)),
+			|n| format!("{}{}", "{".repeat(n), "a".repeat(n)),
+			|n| format!("{}1{}", "`${".repeat(n), "}`".repeat(n)),
+			|n| format!("{}{}", "`${{".repeat(n), "}}`".repeat(n)),
+			|n| format!("{}{}", "`${'".repeat(n), "'}`".repeat(n)),
+			|n| format!("`{}`", "a".repeat(n)),
+			|n| format!("'{}'", "a".repeat(n)),
+			|n| format!("'{}'", "\\'".repeat(n)),
+			|n| format!("// {}\n", "a".repeat(n)),
+			|n| format!("/* {} */", "*".repeat(n)),
+			|n| format!("return /{}/;", "a".repeat(n)),
+			|n| "=/[".repeat(n),
+		];
+		for family in families {
+			for n in [1_000, 2_000, 4_000, 8_000] {
+				let sql = family(n);
+				let batch = visits(|| {
+					let _ = Script::parse_with(&sql, &opts);
+				});
+				let fed = visits(|| {
+					let mut parser = ScriptParser::new(opts.clone());
+					for v in sql.as_bytes().chunks(1) {
+						let _ = parser.feed(v);
+					}
+					let _ = parser.finish();
+				});
+				assert!(batch <= sql.len() * 3, "{:?}... visited {} bytes", &sql[..12], batch);
+				assert!(fed <= sql.len() * 3, "{:?}... visited {} bytes when fed", &sql[..12], fed);
+			}
+		}
+	}
+
+	#[test]
+	fn script_char_any() {
+		let old = |i| is_not::<_, _, Error<&str>>("{}'`\"/")(i);
+		for sql in ["", "{", "return x", "return x;}", "a\"b", "é / ü", "ab`c", "x'y"] {
+			match (char_any(sql), old(sql)) {
+				(Ok(a), Ok(b)) => assert_eq!(a, b),
+				(Err(_), Err(_)) => (),
+				(a, b) => panic!("mismatch for {:?}: {:?} != {:?}", sql, a, b),
+			}
+		}
+	}
+
+	#[test]
+	fn script_char_any_large() {
+		let sql = format!("{}return {{ value: x }};", "let x = 1 + 2 * 3 - 4;\n".repeat(3_000));
+		assert!(sql.len() > 64 * 1024);
+		let now = std::time::Instant::now();
+		let res = script(&sql);
+		assert!(now.elapsed() < std::time::Duration::from_secs(1));
+		let (i, out) = res.unwrap();
+		assert_eq!(i, "");
+		assert_eq!(out.as_bytes(), sql.as_bytes());
+	}
+
+	#[test]
+	fn script_remaining() {
+		let sql = "return { test: `}` }; } AND more";
+		let res = script(sql);
+		assert!(res.is_ok());
+		let (i, out) = res.unwrap();
+		assert_eq!("return { test: `}` }; ", format!("{}", out));
+		assert_eq!(i, "} AND more");
+		assert_eq!(i.as_ptr(), sql[out.len()..].as_ptr());
+	}
+
+	#[test]
+	fn script_borrowed() {
+		let sql = "return { test: true };";
+		let res = script_ref(sql);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
+		assert_eq!(out.as_str().as_ptr(), sql.as_ptr());
+		assert_eq!("return { test: true };", format!("{}", out));
+		assert_eq!(out.to_owned(), script(sql).unwrap().1);
+		assert_eq!(Script::from(out), Script::from("return { test: true };"));
+	}
+
+	#[test]
+	fn script_new() {
+		// Checking is skipped, so anything is accepted
+		let out = Script::new_unchecked("return { ;");
+		assert_eq!(out.as_str(), "return { ;");
+		assert!(Script::validate(&out).is_err());
+		// Checking rejects a body which does not parse
+		let res = Script::new("return { ;");
+		assert_eq!(
+			res,
+			Err(ScriptError::UnmatchedOpen {
+				offset: 7
+			})
+		);
+		let res = Script::new(String::from("return 'a;"));
+		assert!(matches!(res, Err(ScriptError::UnterminatedString { .. })));
+		let res = Script::new("return { a: 1 };");
+		assert_eq!(res, Ok(Script::new_unchecked("return { a: 1 };")));
+	}
+
+	#[test]
+	fn script_from_cow() {
+		let owned = String::from("return { test: true };");
+		let (ptr, cap) = (owned.as_ptr(), owned.capacity());
+		let out = Script::from(Cow::Owned(owned));
+		match out.0 {
+			ScriptBody::Owned(ref v) => assert_eq!((v.as_ptr(), v.capacity()), (ptr, cap)),
+			ScriptBody::Interned(_) => panic!("expected an owned body"),
+		}
+		let borrowed = "return { test: true };";
+		let out = Script::from(Cow::Borrowed(borrowed));
+		assert_ne!(out.as_str().as_ptr(), borrowed.as_ptr());
+		assert_eq!(out, Script::from(borrowed));
+	}
+
+	#[test]
+	fn script_from_str() {
+		let out = "return { test: true };".parse::<Script>();
+		assert_eq!(out, Ok(Script::from("return { test: true };")));
+	}
+
+	#[test]
+	fn script_from_str_unbalanced() {
+		let out = "return { test: true;".parse::<Script>();
+		assert_eq!(
+			out,
+			Err(ScriptError::UnmatchedOpen {
+				offset: 7
+			})
+		);
+	}
+
+	#[test]
+	fn script_from_str_trailing() {
+		let out = "return true; } garbage".parse::<Script>();
+		assert_eq!(
+			out,
+			Err(ScriptError::TrailingInput {
+				offset: 13
+			})
+		);
+	}
+
+	#[test]
+	fn script_from_str_invalid() {
+		let out = "return 'oops;".parse::<Script>();
+		assert_eq!(
+			out,
+			Err(ScriptError::UnterminatedString {
+				offset: 7,
+			})
+		);
+	}
+
+	#[test]
+	fn script_from_string_invalid() {
+		let out = String::from("let a = 1;\nreturn \"oops;").parse::<Script>();
+		assert_eq!(
+			out,
+			Err(ScriptError::UnterminatedString {
+				offset: 18,
+			})
+		);
+	}
+
+	#[test]
+	fn script_error_variants() {
+		let opts = ScriptOptions {
+			depth: 2,
+			max_len: 32,
+			..Default::default()
+		};
+		let tests = [
+			(
+				"return { a: { b: 1 };",
+				ScriptError::UnmatchedOpen {
+					offset: 7,
+				},
+			),
+			(
+				"return 1; } garbage",
+				ScriptError::TrailingInput {
+					offset: 10,
+				},
+			),
+			(
+				"return 'a;",
+				ScriptError::UnterminatedString {
+					offset: 7,
+				},
+			),
+			(
+				"return \"a;",
+				ScriptError::UnterminatedString {
+					offset: 7,
+				},
+			),
+			(
+				"return `a ${1}",
+				ScriptError::UnterminatedString {
+					offset: 7,
+				},
+			),
+			(
+				"return 1; /* a",
+				ScriptError::UnterminatedComment {
+					offset: 10,
+				},
+			),
+			(
+				"if (a) { if (b) { if (c) { d; } } }",
+				ScriptError::DepthExceeded {
+					offset: 25,
+				},
+			),
+			(
+				"return 'a very long string value';",
+				ScriptError::TooLong {
+					offset: 32,
+				},
+			),
+			(
+				"return\x01 1;",
+				ScriptError::Parse {
+					offset: 6,
+					message: "invalid control character in script",
+				},
+			),
+		];
+		for (sql, err) in tests {
+			assert_eq!(Script::parse_with(sql, &opts), Err(err.clone()), "{:?}", sql);
+			let mut parser = ScriptParser::new(opts.clone());
+			let _ = parser.feed(sql.as_bytes());
+			assert_eq!(parser.finish(), Err(err), "{:?}", sql);
+		}
+	}
+
+	#[test]
+	fn script_hash_map() {
+		let mut map = std::collections::HashMap::new();
+		map.insert(Script::from("return 1;"), 1);
+		map.insert(Script::from("return 2;"), 2);
+		assert_eq!(map.len(), 2);
+		assert_eq!(map.get(&Script::from("return 1;")), Some(&1));
+		assert_eq!(map.get(&Script::from("return 2;")), Some(&2));
+		assert_eq!(map.get("return 1;"), Some(&1));
+		assert_eq!(map.get("return 3;"), None);
+		// A script hashes just as its body does
+		let hash = |v: &dyn Fn(&mut std::collections::hash_map::DefaultHasher)| {
+			let mut h = std::collections::hash_map::DefaultHasher::new();
+			v(&mut h);
+			h.finish()
+		};
+		let (script, _) = Script::parse_prefix("return 1;}").unwrap();
+		assert_eq!(hash(&|h| script.hash(h)), hash(&|h| "return 1;".hash(h)));
+		// A script is compared and ordered just as its body is
+		let perms = ScriptPermissions {
+			query: true,
+			..Default::default()
+		};
+		let other = Script::from("return 1;").with_permissions(perms);
+		assert_eq!(script, other);
+		assert_eq!(script.cmp(&other), "return 1;".cmp("return 1;"));
+		assert!(script < Script::from("return 2;").with_permissions(ScriptPermissions::default()));
+		map.insert(other, 3);
+		assert_eq!(map.len(), 2);
+		assert_eq!(map.get("return 1;"), Some(&3));
+	}
+
+	#[test]
+	fn script_interner() {
+		let interner = ScriptInterner::default();
+		let a = interner.intern(&Script::from("return this.price * 1.2;"));
+		let b = interner.intern(&Script::from(String::from("return this.price * 1.2;")));
+		// Equal bodies share a single allocation
+		assert!(a.is_interned() && b.is_interned());
+		assert_eq!(a.as_str().as_ptr(), b.as_str().as_ptr());
+		assert_eq!(interner.len(), 1);
+		// Interned scripts are equal to, and hash as, owned scripts
+		let owned = Script::from("return this.price * 1.2;");
+		assert!(!owned.is_interned());
+		assert_eq!(a, b);
+		assert_eq!(a, owned);
+		assert_eq!(a.cmp(&owned), Ordering::Equal);
+		let hash = |v: &Script| {
+			let mut h = std::collections::hash_map::DefaultHasher::new();
+			v.hash(&mut h);
+			h.finish()
+		};
+		assert_eq!(hash(&a), hash(&owned));
+		assert_eq!(serde_json::to_string(&a).unwrap(), serde_json::to_string(&owned).unwrap());
+		// A different body is not shared
+		let c = interner.intern(&Script::from("return 1;"));
+		assert_ne!(a, c);
+		assert_eq!(interner.len(), 2);
+		// Bodies which no script uses are purged
+		drop(c);
+		assert_eq!(interner.purge(), 1);
+		assert_eq!(interner.len(), 1);
+		// Short bodies are left owned
+		let interner = ScriptInterner::with_min_len(10);
+		assert!(!interner.intern(&Script::from("return;")).is_interned());
+		assert!(interner.intern(&Script::from("return true;")).is_interned());
+	}
+
+	#[test]
+	fn script_btree_map() {
+		let mut map = std::collections::BTreeMap::new();
+		map.insert(Script::from("return b;"), 2);
+		map.insert(Script::from("return a;"), 1);
+		assert_eq!(map.get(&Script::from("return a;")), Some(&1));
+		assert_eq!(map.get(&Script::from("return b;")), Some(&2));
+		assert_eq!(map.get("return a;"), Some(&1));
+		let keys: Vec<_> = map.keys().map(|k| k.as_str()).collect();
+		assert_eq!(keys, vec!["return a;", "return b;"]);
+	}
+
+	#[test]
+	fn script_eq_str() {
+		let out = Script::from("return true;");
+		assert!(out == *"return true;");
+		assert!(*"return true;" == out);
+		assert!(out == "return true;");
+		assert!("return true;" == out);
+		let val = String::from("return true;");
+		assert!(out == val);
+		assert!(val == out);
+		assert!(out != "return false;");
+		assert!("return false;" != out);
+	}
+
+	#[test]
+	fn script_as_ref() {
+		fn text(v: impl AsRef<str>) -> String {
+			v.as_ref().to_owned()
+		}
+		fn bytes(v: impl AsRef<[u8]>) -> Vec<u8> {
+			v.as_ref().to_vec()
+		}
+		let out = Script::from("return 'é';");
+		assert_eq!(text(&out), "return 'é';");
+		assert_eq!(bytes(&out), "return 'é';".as_bytes());
+		let mut buf = Vec::new();
+		std::io::Write::write_all(&mut buf, out.as_ref()).unwrap();
+		assert_eq!(buf, "return 'é';".as_bytes());
+	}
+
+	#[test]
+	fn script_serde_json() {
+		let out = Script::from("return 'a\"b';");
+		let enc = serde_json::to_string(&out).unwrap();
+		assert_eq!(enc, r#""return 'a\"b';""#);
+		let dec: Script = serde_json::from_str(&enc).unwrap();
+		assert_eq!(dec, out);
+	}
+
+	#[test]
+	fn script_serde_msgpack() {
+		let out = Script::from("return 'a\"b';");
+		let enc = msgpack::to_vec(&out).unwrap();
+		assert_eq!(enc, msgpack::to_vec("return 'a\"b';").unwrap());
+		let dec: Script = msgpack::from_slice(&enc).unwrap();
+		assert_eq!(dec, out);
+	}
+
+	#[test]
+	fn script_serde_permissions() {
+		let perms = ScriptPermissions {
+			query: true,
+			globals: ["Proxy".to_owned()].into(),
+			..Default::default()
+		};
+		let out = Script::from("return 1;").with_permissions(perms.clone());
+		let enc = serde_json::to_string(&out).unwrap();
+		assert_eq!(
+			enc,
+			r#"{"body":"return 1;","permissions":{"query":true,"http":false,"import":false,"globals":["Proxy"]}}"#
+		);
+		let dec: Script = serde_json::from_str(&enc).unwrap();
+		assert_eq!((dec.as_str(), dec.permissions()), ("return 1;", Some(&perms)));
+		let dec: Script = msgpack::from_slice(&msgpack::to_vec(&out).unwrap()).unwrap();
+		assert_eq!((dec.as_str(), dec.permissions()), ("return 1;", Some(&perms)));
+		// A script stored as its body alone has no permissions of its own
+		let dec: Script = msgpack::from_slice(&msgpack::to_vec("return 1;").unwrap()).unwrap();
+		assert_eq!((dec.as_str(), dec.permissions()), ("return 1;", None));
+		let dec: Script = serde_json::from_str(r#""return 1;""#).unwrap();
+		assert_eq!(dec.permissions(), None);
+	}
+
+	#[test]
+	fn script_lang() {
+		let out = Script::from("return 1;");
+		assert_eq!(out.lang(), ScriptLang::JavaScript);
+		assert_eq!(Script::from(String::from("return 1;")).lang(), ScriptLang::JavaScript);
+		assert_eq!(script("return 1;").unwrap().1.lang(), ScriptLang::JavaScript);
+		// A script with the default language is serialized as its body
+		let out = out.with_lang(ScriptLang::default());
+		assert_eq!(serde_json::to_string(&out).unwrap(), r#""return 1;""#);
+		assert_eq!(msgpack::to_vec(&out).unwrap(), msgpack::to_vec("return 1;").unwrap());
+		let dec: Script = msgpack::from_slice(&msgpack::to_vec("return 1;").unwrap()).unwrap();
+		assert_eq!(dec.lang(), ScriptLang::JavaScript);
+	}
+
+	#[test]
+	fn script_serde_checked() {
+		#[derive(Debug, Deserialize)]
+		struct Test {
+			#[serde(deserialize_with = "Script::deserialize_checked")]
+			script: Script,
+		}
+		let res = serde_json::from_str::<Test>(r#"{ "script": "return { a: 1 };" }"#);
+		assert_eq!(res.unwrap().script, "return { a: 1 };");
+		let res = serde_json::from_str::<Test>(r#"{ "script": "return 'a;" }"#);
+		assert!(res.is_err());
+	}
+
+	#[test]
+	fn script_minify() {
+		let one = Script::from(
+			"\n\tlet a = 'x  y';\n\t// Comment\n\tlet b = `a  ${ a  }`;\n\treturn { a , b };\n",
+		);
+		let two =
+			Script::from("let a = 'x  y'; /* Comment */\nlet   b = `a  ${a}`;\n  return {a,b};");
+		assert_eq!(one.minify(), two.minify());
+		assert_eq!("let a='x  y';let b=`a  ${a}`;return{a,b};", format!("{}", one.minify()));
+	}
+
+	#[test]
+	fn script_checksum() {
+		let one = Script::from(
+			"\n\tlet a = 'x  y';\n\t// Comment\n\tlet b = `a  ${ a  }`;\n\treturn { a , b };\n",
+		);
+		let two =
+			Script::from("let a = 'x  y'; /* Comment */\nlet   b = `a  ${a}`;\n  return {a,b};");
+		assert_eq!(one.checksum(), two.checksum());
+		assert_eq!(one.checksum(), one.minify().checksum());
+		// A change to a literal is a different script
+		let three = Script::from("let a = 'x y'; let b = `a  ${a}`; return {a,b};");
+		assert_ne!(one.checksum(), three.checksum());
+		let four = Script::from("let a = 'x  y'; let b = `a ${a}`; return {a,b};");
+		assert_ne!(one.checksum(), four.checksum());
+		assert_ne!(Script::from("return 1;").checksum(), Script::from("return 10;").checksum());
+		// The checksum is stable, so that it can be stored
+		assert_eq!(Script::from("").checksum(), 0xe3b0c44298fc1c14);
+	}
+
+	#[test]
+	fn script_canonicalize_literals() {
+		// The same character, however it is written, is written the same way
+		let res: Vec<_> = [r"return '\x41';", "return 'A';", r"return 'A';", r"return '\u{41}';"]
+			.into_iter()
+			.map(|v| Script::from(v).canonicalize_literals())
+			.collect();
+		assert!(res.iter().all(|v| v == "return 'A';"));
+		// The characters which need an escape are escaped minimally
+		let out = Script::from(
+			r#"return ['\'', "\"", '"', "'", '\\', 'a\
+b', '\x0a\t\x01\u2028', '\0\x001'];"#,
+		);
+		let res = out.canonicalize_literals();
+		assert_eq!(
+			res,
+			r#"return ['\'', "\"", '"', "'", '\\', 'ab', '\n\t\x01\u2028', '\0\x001'];"#
+		);
+		assert_eq!(
+			out.string_literals().into_iter().map(|v| v.value).collect::<Vec<_>>(),
+			res.string_literals().into_iter().map(|v| v.value).collect::<Vec<_>>(),
+		);
+		// Template literals are escaped, unless they are tagged
+		let out =
+			Script::from(r"return `\x41\${a}${`B`}\`` + String.raw`\x41${b}\x42` + f(`\x43`);");
+		let res = "return `A\\${a}${`B`}\\`` + String.raw`\\x41${b}\\x42` + f(`C`);";
+		assert_eq!(out.canonicalize_literals(), res);
+		// Code, and literals whose escapes are not valid, are left untouched
+		let out = Script::from(r"let a = '\x4'; return /\x41/.test(a) + '\u{d800}' + '\1';");
+		assert_eq!(out.canonicalize_literals(), out);
+		let out = Script::from("return 'a';}");
+		assert_eq!(out.canonicalize_literals(), out);
+	}
+
+	#[test]
+	fn script_replace_literal() {
+		// Each literal with the value is replaced, however it is written
+		let out = Script::from(
+			r#"const secret = 's3cr3t'; return fetch(url, { headers: { a: "s3cr\x33t", b: secret, c: 's3cr3t!' } });"#,
+		);
+		let res = out.replace_literal("s3cr3t", "n\"ew'\n").unwrap();
+		assert_eq!(
+			res,
+			r#"const secret = 'n"ew\'\n'; return fetch(url, { headers: { a: "n\"ew'\n", b: secret, c: 's3cr3t!' } });"#
+		);
+		assert_eq!(res.string_literals()[0].value, "n\"ew'\n");
+		// Identifiers, comments and templates with the value are untouched
+		let out = Script::from("let secret = 1; /* 'secret' */ return `secret` + secret.secret;");
+		assert_eq!(out.replace_literal("secret", "x").unwrap(), out);
+		// A script which does not parse can not be changed
+		assert!(Script::from("return 'a';}").replace_literal("a", "b").is_err());
+	}
+
+	#[test]
+	fn script_minify_tokens() {
+		let out = Script::from("return a - -b + + c / /x/ .test(d) + typeof e;");
+		assert_eq!(out.minify(), "return a- -b+ +c/ /x/.test(d)+typeof e;");
+		let out = Script::from("let a = 1\n\nlet b = a\n++b");
+		assert_eq!(out.minify(), "let a=1\nlet b=a\n++b");
+	}
+
+	#[test]
+	fn script_minify_newlines() {
+		let out = Script::from("let a = 1\r\rlet b = a /* \r */ ++b\r\n");
+		assert_eq!(out.minify(), "let a=1\nlet b=a\n++b");
+	}
+
+	#[test]
+	fn script_semantically_eq() {
+		let out = Script::from("return this.values.map(v => `This value is ${Number(v * 3)}`);");
+		let tests = [
+			"return this.values.map(v => `This value is ${Number(v * 3)}`);",
+			"return this.values.map(\n\tv => `This value is ${Number(v * 3)}`\n);",
+			"/* map */ return this.values.map(v   =>   `This value is ${Number(v * 3)}`); // done",
+		];
+		for v in tests {
+			assert!(out.semantically_eq(&Script::from(v)), "{:?}", v);
+		}
+		let tests = [
+			"return this.values.map(v => `This value is  ${Number(v * 3)}`);",
+			"return this.values.map(v => `This value is ${Number(v * 4)}`);",
+			"return this.values.map(v => `This value is ${Number(v / 3)}`);",
+			"return this.values.map(w => `This value is ${Number(w * 3)}`);",
+		];
+		for v in tests {
+			assert!(!out.semantically_eq(&Script::from(v)), "{:?}", v);
+		}
+		// Whitespace in strings is significant, and line breaks may be
+		let out = Script::from("let a = 'x y'; return a");
+		assert!(!out.semantically_eq(&Script::from("let a = 'x  y'; return a")));
+		assert!(!Script::from("a\n++b").semantically_eq(&Script::from("a++b")));
+		// Scripts which do not parse are compared as they are
+		assert!(Script::from("return 'a").semantically_eq(&Script::from("return 'a")));
+		assert!(!Script::from("return 'a").semantically_eq(&Script::from("return  'a")));
+	}
+
+	#[test]
+	fn script_minify_invalid() {
+		let out = Script::from("return 'a  ;");
+		assert_eq!(out.minify(), out);
+	}
+
+	#[test]
+	fn script_format() {
+		let out = Script::from("let a = { b: 1 }; for (let i = 0; i < 2; i++) { if (a) { a.b++; } else {} }\nreturn a;");
+		assert_eq!(
+			"let a={\n  b:1\n};\nfor(let i=0;i<2;i++){\n  if(a){\n    a.b++;\n  } else{}\n}\nreturn a;",
+			format!("{}", out.format(2)),
+		);
+	}
+
+	#[test]
+	fn script_format_minify() {
+		let tests = [
+			"let a = {}\n++b; return a;",
+			"let a = `x ${ {a: 1}.a }`; if (a) { return a; } else { return 'b  ;'; }",
+			"// Comment\nfunction a() { return /}/.test(b) } function c() {}\nreturn a() + c();",
+			"return {\n\ta: [1, 2, 3].map(v => { return v * 2 }),\n}",
+		];
+		for test in tests {
+			let out = Script::from(test);
+			assert_eq!(out.format(4).minify(), out.minify());
+		}
+	}
+
+	#[test]
+	fn script_parse_prefix() {
+		let (out, rest) = Script::parse_prefix("return { a: '}' };\n} AS f").unwrap();
+		assert_eq!(out, "return { a: '}' };\n");
+		assert_eq!(rest, "} AS f");
+		// Whitespace before the closing `}` is part of the body
+		let (out, rest) = Script::parse_prefix("return 1; \t}").unwrap();
+		assert_eq!(out, "return 1; \t");
+		assert_eq!(rest, "}");
+		let (out, rest) = Script::parse_prefix("return 1;").unwrap();
+		assert_eq!(out, "return 1;");
+		assert_eq!(rest, "");
+		assert_eq!(
+			Script::parse_prefix("return 'a;"),
+			Err(ScriptError::UnterminatedString {
+				offset: 7,
+			})
+		);
+	}
+
+	#[test]
+	fn script_parse_all() {
+		// Trailing whitespace is kept as part of the body
+		assert_eq!(Script::parse_all("return 1;\n\t ").unwrap(), "return 1;\n\t ");
+		assert_eq!(Script::parse_all("").unwrap(), "");
+		assert_eq!(
+			Script::parse_all("return 1; } "),
+			Err(ScriptError::TrailingInput {
+				offset: 10,
+			})
+		);
+		assert_eq!(
+			Script::parse_all("return 1; }}"),
+			Err(ScriptError::TrailingInput {
+				offset: 10,
+			})
+		);
+		assert!(Script::parse_all("return { a: 1;").is_err());
+	}
+
+	#[test]
+	fn script_unclosed() {
+		// The earliest block which is still open is reported
+		let tests = [
+			("return { a: 1;", 7),
+			("if (a) { return { b: { c: 1 };", 7),
+			("let a = {}; if (a) { if (b) { c(); }", 19),
+			("let a = `${ {} }`; { b: `${1}`, c: {", 19),
+		];
+		for (sql, offset) in tests {
+			let err = ScriptError::UnmatchedOpen {
+				offset,
+			};
+			assert_eq!(
+				Script::parse_with(sql, &ScriptOptions::default()),
+				Err(err.clone()),
+				"{:?}",
+				sql
+			);
+			assert_eq!(Script::parse_prefix(sql), Err(err.clone()), "{:?}", sql);
+			assert_eq!(streamed(&[sql.as_bytes()]), Err(err), "{:?}", sql);
+			assert!(matches!(
+				script(sql),
+				Err(Err::Failure(Error::ScriptError {
+					kind: ScriptErrorKind::UnclosedBlock,
+					message: "unclosed block in script",
+					..
+				}))
+			));
+		}
+		// A block which is cut off by the maximum length is too long
+		let opts = ScriptOptions {
+			max_len: 8,
+			..Default::default()
+		};
+		assert_eq!(
+			Script::parse_with("return { a: 1 };", &opts),
+			Err(ScriptError::TooLong {
+				offset: 8,
+			})
+		);
+	}
+
+	#[test]
+	fn script_validate() {
+		assert_eq!(Script::validate("return { a: '}' };"), Ok(()));
+		assert_eq!(Script::validate(""), Ok(()));
+	}
+
+	#[test]
+	fn script_validate_unmatched_open() {
+		let out = Script::validate("if (a) { return { b: 1 };");
+		assert_eq!(
+			out,
+			Err(ScriptError::UnmatchedOpen {
+				offset: 7
+			})
+		);
+	}
+
+	#[test]
+	fn script_validate_unmatched_close() {
+		let out = Script::validate("return { b: 1 }; }");
+		assert_eq!(
+			out,
+			Err(ScriptError::UnmatchedClose {
+				offset: 17
+			})
+		);
+	}
+
+	#[test]
+	fn script_validate_unterminated_string() {
+		let out = Script::validate("let a = 'b';\nreturn \"c;");
+		assert_eq!(
+			out,
+			Err(ScriptError::UnterminatedString {
+				offset: 20
+			})
+		);
+		let out = Script::validate("return `a ${b}");
+		assert_eq!(
+			out,
+			Err(ScriptError::UnterminatedString {
+				offset: 7
+			})
+		);
+	}
+
+	#[test]
+	fn script_validate_unterminated_comment() {
+		let out = Script::validate("let a = 1; /* comment");
+		assert_eq!(
+			out,
+			Err(ScriptError::UnterminatedComment {
+				offset: 11
+			})
+		);
+	}
+
+	#[test]
+	fn script_spans() {
+		let out = Script::from("let a = `x\ny`;\nreturn 'é';");
+		assert_eq!(
+			out.spans(),
+			vec![(0, 1, 0), (8, 1, 8), (9, 1, 9), (12, 2, 1), (13, 2, 2), (22, 3, 7), (26, 3, 10)]
+		);
+	}
+
+	#[test]
+	fn script_spans_string_newline() {
+		let out = Script::from("let a = 'x\\\ny';\nreturn a;");
+		assert_eq!(out.spans(), vec![(0, 1, 0), (8, 1, 8), (14, 2, 2)]);
+	}
+
+	#[test]
+	fn script_preview() {
+		let out = Script::from("return 'a long string' + b;");
+		assert_eq!(out.preview(27), "return 'a long string' + b;");
+		assert_eq!(out.preview(26), "return 'a long string' +…");
+		// A cut within a literal backs off to the token before it
+		for n in 8..=22 {
+			assert_eq!(out.preview(n), "return…", "{}", n);
+		}
+		assert_eq!(out.preview(3), "…");
+		assert_eq!(out.preview(0), "…");
+		// An escape, or a character of several bytes, is never split
+		let out = Script::from("a + '\\u{1F600}' + '😀😀' + c");
+		assert_eq!(out.preview(18), "a + '\\u{1F600}' +…");
+		assert_eq!(out.preview(22), "a + '\\u{1F600}' +…");
+		assert_eq!(out.preview(23), "a + '\\u{1F600}' + '😀😀'…");
+		// A template literal is never split at an interpolation
+		let out = Script::from("let a = `x${b}y${c}z`; return a;");
+		assert_eq!(out.preview(20), "let a =…");
+		assert_eq!(out.preview(23), "let a = `x${b}y${c}z`;…");
+	}
+
+	#[test]
+	fn script_editor_spans() {
+		let pos = |line, character| EditorPosition {
+			line,
+			character,
+		};
+		let range = |a, b, c, d| EditorRange {
+			start: pos(a, b),
+			end: pos(c, d),
+		};
+		// A character outside the BMP counts as two UTF-16 code units
+		let out = Script::from("let a = '😀é'; // 😀\r\nreturn a + 1;").editor_spans();
+		assert_eq!(out.literals, vec![range(0, 8, 0, 13), range(1, 11, 1, 12)]);
+		assert_eq!(out.comments, vec![range(0, 15, 0, 20)]);
+		assert_eq!(
+			out.identifiers,
+			vec![range(0, 0, 0, 3), range(0, 4, 0, 5), range(1, 0, 1, 6), range(1, 7, 1, 8)]
+		);
+		assert!(out.errors.is_empty());
+		// The point at which the body fails to parse is an error
+		let out = Script::from("let a = '😀';\rreturn 'b").editor_spans();
+		assert_eq!(out.errors, vec![range(1, 7, 1, 8)]);
+		let out = Script::from("a; }").editor_spans();
+		assert_eq!(out.errors, vec![range(0, 3, 0, 4)]);
+	}
+
+	#[test]
+	fn script_line_offset() {
+		let out = Script::from("a;\r\nb;\rc;\n'd\\\ne';");
+		assert_eq!(out.line_offset(0), None);
+		assert_eq!(out.line_offset(1), Some(0));
+		assert_eq!(out.line_offset(2), Some(4));
+		assert_eq!(out.line_offset(3), Some(7));
+		assert_eq!(out.line_offset(4), Some(10));
+		assert_eq!(out.line_offset(5), Some(14));
+		assert_eq!(out.line_offset(6), None);
+		let out = Script::from("a;\n");
+		assert_eq!(out.line_offset(2), Some(3));
 	}
-}
 
-impl From<&str> for Script {
-	fn from(s: &str) -> Self {
-		Self::from(String::from(s))
+	#[test]
+	fn script_offset() {
+		let out = Script::from("return 1;");
+		assert_eq!(out.offset(), None);
+		let res = script("return 1; }");
+		assert_eq!(res.unwrap().1.offset(), None);
 	}
-}
 
-impl Deref for Script {
-	type Target = String;
-	fn deref(&self) -> &Self::Target {
-		&self.0
+	#[test]
+	fn script_private_fields() {
+		use TokenKind::*;
+		let sql = "class C { #count = 0; inc() { return ++this.#count; } static has(o) { return #count in o; } }\nreturn new C().inc();";
+		let out = Script::from(sql);
+		let tokens: Vec<_> = out
+			.tokens()
+			.filter(|t| matches!(t.kind, PrivateIdentifier) || out[t.span.clone()].contains('#'))
+			.map(|t| (t.kind, &out[t.span]))
+			.collect();
+		assert_eq!(tokens, vec![(PrivateIdentifier, "#count"); 3]);
+		// A private name is not a free identifier, and is kept whole
+		assert!(!out.free_identifiers().iter().any(|v| v.contains("count")));
+		let res = "class C{#count=0;inc(){return++this.#count;}static has(o){return #count in o;}}\nreturn new C().inc();";
+		assert_eq!(out.minify(), res);
+		assert!(Script::validate(&out).is_ok());
+		// A `#` within the body is never a hashbang
+		let out = Script::from("a;\n#!b");
+		assert_eq!(out.tokens().filter(|t| t.kind == Comment).count(), 0);
+		let out = Script::from("class A { #\\u0061 = 1; #1 }");
+		let tokens: Vec<_> = out.tokens().map(|t| (t.kind, &out[t.span])).collect();
+		assert!(tokens.contains(&(PrivateIdentifier, "#\\u0061")));
+		assert!(tokens.contains(&(Punct, "#")));
 	}
-}
 
-impl Display for Script {
-	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-		Display::fmt(&self.0, f)
+	#[test]
+	fn script_tokens() {
+		use TokenKind::*;
+		let out = Script::from("return this.values.map(v => `This value is ${Number(v * 3)}`);");
+		let tokens: Vec<_> = out.tokens().map(|t| (t.kind, &out[t.span])).collect();
+		assert_eq!(
+			tokens,
+			vec![
+				(Identifier, "return"),
+				(Whitespace, " "),
+				(Identifier, "this"),
+				(Punct, "."),
+				(Identifier, "values"),
+				(Punct, "."),
+				(Identifier, "map"),
+				(Punct, "("),
+				(Identifier, "v"),
+				(Whitespace, " "),
+				(Punct, "="),
+				(Punct, ">"),
+				(Whitespace, " "),
+				(TemplateLiteral, "`This value is ${"),
+				(Identifier, "Number"),
+				(Punct, "("),
+				(Identifier, "v"),
+				(Whitespace, " "),
+				(Punct, "*"),
+				(Whitespace, " "),
+				(Number, "3"),
+				(Punct, ")"),
+				(TemplateLiteral, "}`"),
+				(Punct, ")"),
+				(Punct, ";"),
+			]
+		);
 	}
-}
 
-pub fn script(i: &str) -> IResult<&str, Script> {
-	let (i, v) = recognize(script_raw)(i)?;
-	Ok((i, Script(String::from(v))))
-}
+	#[test]
+	fn script_tokens_literals() {
+		use TokenKind::*;
+		let out = Script::from("// Test\nlet a = /[/]+/g.test('a b') ? 1e+3 : 0x1F - .5; /* } */");
+		let tokens: Vec<_> =
+			out.tokens().filter(|t| t.kind != Whitespace).map(|t| (t.kind, &out[t.span])).collect();
+		assert_eq!(
+			tokens,
+			vec![
+				(Comment, "// Test"),
+				(Identifier, "let"),
+				(Identifier, "a"),
+				(Punct, "="),
+				(Regex, "/[/]+/g"),
+				(Punct, "."),
+				(Identifier, "test"),
+				(Punct, "("),
+				(StringLiteral, "'a b'"),
+				(Punct, ")"),
+				(Punct, "?"),
+				(Number, "1e+3"),
+				(Punct, ":"),
+				(Number, "0x1F"),
+				(Punct, "-"),
+				(Number, ".5"),
+				(Punct, ";"),
+				(Comment, "/* } */"),
+			]
+		);
+	}
 
-fn script_raw(i: &str) -> IResult<&str, &str> {
-	recognize(many1(alt((char_any, char_object, string_single, string_double, string_backtick))))(i)
-}
+	#[test]
+	fn script_tokens_numbers() {
+		let tests = [
+			"0xFF", "0XAB_CD", "0b10", "0B1_0", "0o17", "1_000", "1_000.5", "1.5", "1.", ".5",
+			"1e9", "1E+9", "1.5e-9", "2e-1_0", "10n", "0x1Fn",
+		];
+		for v in tests {
+			let out = Script::from(format!("{{{}}}", v));
+			let res: Vec<_> = out.tokens().map(|t| (t.kind, &out[t.span])).collect();
+			assert_eq!(
+				res,
+				vec![(TokenKind::Punct, "{"), (TokenKind::Number, v), (TokenKind::Punct, "}")],
+				"{}",
+				v
+			);
+		}
+		// A method call on a number is not part of the number
+		let out = Script::from("1.5.toFixed(1) + 1..toString() + 1e-+2");
+		let res: Vec<_> = out
+			.tokens()
+			.filter(|t| t.kind != TokenKind::Whitespace)
+			.map(|t| (t.kind, &out[t.span]))
+			.take(9)
+			.collect();
+		assert_eq!(
+			res,
+			vec![
+				(TokenKind::Number, "1.5"),
+				(TokenKind::Punct, "."),
+				(TokenKind::Identifier, "toFixed"),
+				(TokenKind::Punct, "("),
+				(TokenKind::Number, "1"),
+				(TokenKind::Punct, ")"),
+				(TokenKind::Punct, "+"),
+				(TokenKind::Number, "1."),
+				(TokenKind::Punct, "."),
+			]
+		);
+		let res: Vec<_> = out.tokens().map(|t| &out[t.span]).skip_while(|v| *v != "1e").collect();
+		assert_eq!(res, vec!["1e", "-", "+", "2"]);
+		// The body is still accepted and rejected as it was
+		assert!(Script::parse_all("return 0x1F + 1_000 / 1e3;").is_ok());
+	}
 
-fn char_any(i: &str) -> IResult<&str, &str> {
-	is_not("{}'`\"")(i)
-}
+	#[test]
+	fn script_free_identifiers() {
+		let out = Script::from("return $arg + arguments[0] + Math.max(a, 1);");
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["$arg", "a"]);
+	}
 
-fn char_object(i: &str) -> IResult<&str, &str> {
-	let (i, _) = tag(OBJECT_BEG)(i)?;
-	let (i, v) = script_raw(i)?;
-	let (i, _) = tag(OBJECT_END)(i)?;
-	Ok((i, v))
-}
+	#[test]
+	fn script_free_identifiers_shadowed() {
+		let out = Script::from(
+			"function add(a, b) { let c = a + b; return c; } return [1, 2].map(a => add(a, c)).map((x, y) => { return x + y + z; });",
+		);
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["c", "z"]);
+	}
 
-fn string_single(i: &str) -> IResult<&str, &str> {
-	let (i, _) = tag(SINGLE)(i)?;
-	let (i, v) = alt((escaped(is_not(SINGLE_ESC), '\\', one_of(SINGLE)), tag("")))(i)?;
-	let (i, _) = tag(SINGLE)(i)?;
-	Ok((i, v))
-}
+	#[test]
+	fn script_free_identifiers_this() {
+		let out = Script::from(
+			"const { a, b: [c] } = this; for (let i = 0; i < this.values.length; i++) { try { total += i; } catch (e) { throw e; } } return { total: this.total };",
+		);
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["total"]);
+	}
 
-fn string_double(i: &str) -> IResult<&str, &str> {
-	let (i, _) = tag(DOUBLE)(i)?;
-	let (i, v) = alt((escaped(is_not(DOUBLE_ESC), '\\', one_of(DOUBLE)), tag("")))(i)?;
-	let (i, _) = tag(DOUBLE)(i)?;
-	Ok((i, v))
-}
+	#[test]
+	fn script_free_identifiers_reserved_keys() {
+		let out = Script::from(
+			"let o = { return: a, function: b, let: c, class: d, const: e, var: f, catch: (x) => x };",
+		);
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["a", "b", "c", "d", "e", "f"]);
+		// Reserved words are still keywords in statement position
+		let out =
+			Script::from("let a = o.let + o.class; function g(v) { return v; } return g(a) + h;");
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["h", "o"]);
+		let out = Script::from("const { return: r, new: n } = o; return r + n;");
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["o"]);
+	}
 
-fn string_backtick(i: &str) -> IResult<&str, &str> {
-	let (i, _) = tag(BACKTICK)(i)?;
-	let (i, v) = alt((escaped(is_not(BACKTICK_ESC), '\\', one_of(BACKTICK)), tag("")))(i)?;
-	let (i, _) = tag(BACKTICK)(i)?;
-	Ok((i, v))
-}
+	#[test]
+	fn script_free_identifiers_escaped() {
+		// An escape is part of the identifier which it is written in
+		let out = Script::from("return \\u0061bc + \\u{62}1 + \\x61;");
+		let res: Vec<_> = out.tokens().map(|t| (t.kind, out[t.span].to_owned())).collect();
+		assert_eq!(res[2], (TokenKind::Identifier, "\\u0061bc".to_owned()));
+		assert_eq!(res[6], (TokenKind::Identifier, "\\u{62}1".to_owned()));
+		assert_eq!(res[10], (TokenKind::Punct, "\\".to_owned()));
+		assert_eq!(res[11], (TokenKind::Identifier, "x61".to_owned()));
+		// An escaped identifier is the same as its decoded name
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["abc", "b1", "x61"]);
+		let out = Script::from("let \\u0061 = 1; return a + \\u{62};");
+		let res: Vec<_> = out.free_identifiers().into_iter().collect();
+		assert_eq!(res, vec!["b"]);
+		assert!(Script::from("return f\\u0065tch('x');").capabilities().uses_fetch);
+	}
 
-#[cfg(test)]
-mod tests {
+	#[test]
+	fn script_referenced_globals() {
+		let out = Script::from("const res = await fetch('https://example.com'); return res;");
+		let res: Vec<_> = out.referenced_globals().into_iter().collect();
+		assert_eq!(res, vec!["fetch"]);
+		let out =
+			Script::from("setInterval(f, 10); new WebAssembly.Module(b); return Math.max(a, 1);");
+		let res: Vec<_> = out.referenced_globals().into_iter().collect();
+		assert_eq!(res, vec!["WebAssembly", "setInterval"]);
+		// A local of the same name is not a use of the global
+		let out = Script::from("const fetch = (u) => u; return fetch('x');");
+		assert!(out.referenced_globals().is_empty());
+		let out = Script::from("function run(fetch) { return fetch('x'); } return run(g);");
+		assert!(out.referenced_globals().is_empty());
+		// Neither is a property, nor the text of a string
+		let out = Script::from("return this.fetch + 'setTimeout';");
+		assert!(out.referenced_globals().is_empty());
+		// The free identifiers are unchanged
+		let out = Script::from("return fetch(u);");
+		assert_eq!(out.free_identifiers().into_iter().collect::<Vec<_>>(), vec!["u"]);
+	}
 
-	use super::*;
+	#[test]
+	fn script_regex_reserved_keys() {
+		let out = Script::from("return { in: a, typeof: b }.typeof / c / d + o.return / 2 / e;");
+		assert_eq!(out.tokens().filter(|t| t.kind == TokenKind::Regex).count(), 0);
+		let out = Script::from("let o = { return: /a/ }; return /b/;");
+		assert_eq!(out.tokens().filter(|t| t.kind == TokenKind::Regex).count(), 2);
+	}
 
 	#[test]
-	fn script_basic() {
-		let sql = "return true;";
+	fn script_completion() {
+		let tests = [
+			("a + 1", Some("a + 1")),
+			("let a = 1; a + 1;", Some("a + 1;")),
+			("x = f(function() { return 1; })", Some("x = f(function() { return 1; })")),
+			("if (a) { b(); } await c", Some("await c")),
+			("const f = () => 1;\nf()", Some("f()")),
+			("v => v * 10", Some("v => v * 10")),
+			("async (v) => { return v; }", Some("async (v) => { return v; }")),
+			("`${ (() => { return 1 })() }`", Some("`${ (() => { return 1 })() }`")),
+			("", None),
+			(";;", None),
+			("return 1;", None),
+			("let a = 1;", None),
+			("{ a: 1 }", None),
+			("if (a) { b(); }", None),
+			("function a() {}", None),
+			("async function a() {}", None),
+			("label: a", None),
+			("for (let i = 0; i < 3; i++) a(i);", None),
+			("while (a) a--", None),
+			("a()\nreturn b", None),
+			("if (true) 1; else 2;", None),
+			("if (a)\n\tb\nelse\n\tc", None),
+			("do a++; while (a < 3)", None),
+			("globalThis.a = []; a.push(1)\na.push(2)\na", Some("a")),
+			("let a = b\n\t.c()\na\n\t.d()", Some("a\n\t.d()")),
+		];
+		for (sql, res) in tests {
+			let out = Script::from(sql);
+			assert_eq!(out.completion().map(|v| &sql[v.span]), res, "{:?}", sql);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "scripting")]
+	fn script_instrument() {
+		let tests = [
+			("return 1;", "c(1); return 1;", vec![1]),
+			("let a = 1;\nif (a) {\n\treturn 2;\n} else {\n\treturn 3;\n}", "c(1); let a = 1;\nc(2); if (a) {\n\tc(3); return 2;\n} else {\n\tc(5); return 3;\n}", vec![1, 2, 3, 5]),
+			// Expressions which continue onto the next line are left whole
+			("let a = b\n\t.c();\nlet d = {\n\te: 1,\n};\nreturn a +\n\td", "c(1); let a = b\n\t.c();\nc(3); let d = {\n\te: 1,\n};\nc(6); return a +\n\td", vec![1, 3, 6]),
+			// A keyword which begins a statement is recorded without a `;`
+			("let a = 1\nreturn a", "c(1); let a = 1\nc(2); return a", vec![1, 2]),
+			("if (a)\n\treturn 1\nelse\n\tif (b) c()", "c(1); if (a)\n\treturn 1\nelse\n\tif (b) c()", vec![1]),
+			// Nothing is inserted where it would change what the code means
+			("do {\n\ta++;\n}\nwhile (a < 3);", "c(1); do {\n\tc(2); a++;\n}\nwhile (a < 3);", vec![1, 2]),
+			("switch (a) {\n\tcase 1:\n\t\tb();\n\tdefault:\n}", "c(1); switch (a) {\n\tcase 1:\n\t\tc(3); b();\n\tdefault:\n}", vec![1, 3]),
+			("class A extends f() {\n\tm() {\n\t\treturn 1;\n\t}\n}", "c(1); class A extends f() {\n\tm() {\n\t\tc(3); return 1;\n\t}\n}", vec![1, 3]),
+			("f(() => {\n\tg();\n}, `${\nh\n}`)\n;", "c(1); f(() => {\n\tc(2); g();\n}, `${\nh\n}`)\n;", vec![1, 2]),
+			("for (let i = 0;\n\ti < 3;\n\ti++) {}", "c(1); for (let i = 0;\n\ti < 3;\n\ti++) {}", vec![1]),
+			("return 1;}", "return 1;}", vec![]),
+		];
+		for (sql, res, lines) in tests {
+			let (out, set) = Script::from(sql).instrument("c");
+			assert_eq!(out, res, "{:?}", sql);
+			assert_eq!(set.into_iter().collect::<Vec<_>>(), lines, "{:?}", sql);
+		}
+	}
+
+	#[test]
+	fn script_is_generator() {
+		let tests = [
+			("yield 1; yield 2;", true),
+			("for (const v of a) { yield v; }", true),
+			("if (a) { yield* b; }", true),
+			("for await (const v of a) yield v;", true),
+			("try { yield 1 } catch (e) { yield 2 }", true),
+			("return 1;", false),
+			("return a.yield;", false),
+			("return { yield: 1 };", false),
+			("function* a() { yield 1; } return [...a()];", false),
+			("const a = { *b() { yield 1; } }; return [...a.b()];", false),
+			("return 'yield';", false),
+			("// yield", false),
+		];
+		for (sql, res) in tests {
+			assert_eq!(Script::from(sql).is_generator(), res, "{:?}", sql);
+		}
+	}
+
+	#[test]
+	fn script_is_pure_expression() {
+		let tests = [
+			("return a+1;", true),
+			("return a + 1", true),
+			("a + 1", true),
+			("return { a: 1 };", true),
+			("return a === b && c >= d ? (x => x) : a !== 1;", true),
+			("return a + +b - -c;", true),
+			("return [1, 2].map(function(v) { return v * 2; });", true),
+			("// the price\nreturn this.price * 1.2; /* done */", true),
+			("", false),
+			("return;", false),
+			("let a = 1; return a;", false),
+			("a + 1; return a;", false),
+			("{ a: 1 }", false),
+			("if (a) { return 1; }", false),
+			("function a() {}", false),
+			("return a = 1;", false),
+			("return a += 1;", false),
+			("return a++;", false),
+			("return --a;", false),
+			("return a <<= 1", false),
+			("a >>= 1", false),
+			("a >>>= 1", false),
+			("return a **= 2;", false),
+			("a &&= b", false),
+			("a ||= b", false),
+			("a ??= b", false),
+			("a[0]=-1", false),
+			("return a <= b && a >= c && a >>> 1 < b ** 2 && a?.b != c", true),
+			("return a <<b>>= c", false),
+			("return (a)=>a", true),
+			("return delete a.b;", false),
+			("return await a;", false),
+			// A bare arrow function is not run when it is evaluated
+			("v => v * 10", true),
+			("(v) => v * 10;", true),
+			("return async (a, b) => { a = await b; };", true),
+			("v => v++, w = 1", false),
+		];
+		for (sql, res) in tests {
+			assert_eq!(Script::from(sql).is_pure_expression(), res, "{:?}", sql);
+		}
+	}
+
+	#[test]
+	fn script_is_arrow_function() {
+		let tests = [
+			("v => v * 10", true),
+			("(v) => v * 10;", true),
+			("(a, { b = 1 }) => a + b", true),
+			("async v => await v", true),
+			("async => 1", true),
+			("return v => ({ v });", true),
+			("v => {\n\treturn v * 10;\n}", true),
+			("v => v * 10, 2", false),
+			("(v => v * 10)(...arguments)", false),
+			("[6.6, 8.4, 7.3].map(v => v * 10)", false),
+			("const f = v => v * 10;", false),
+			("(a) + b", false),
+			("v", false),
+			("", false),
+		];
+		for (sql, res) in tests {
+			assert_eq!(Script::from(sql).is_arrow_function(), res, "{:?}", sql);
+		}
+	}
+
+	#[test]
+	fn script_statement_count() {
+		let out = Script::from(
+			r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#,
+		);
+		assert_eq!(out.statement_count(), 1);
+		assert_eq!(out.brace_depth_max(), 2);
+		let out = Script::from(
+			"function a() { return 1; }\nlet b = a(); ; // c;\ntry { b++ } catch (e) {} return b",
+		);
+		assert_eq!(out.statement_count(), 4);
+		assert_eq!(out.brace_depth_max(), 1);
+		let out = Script::from("for (let i = 0; i < n; i++) { a(i); } return a;");
+		assert_eq!(out.statement_count(), 2);
+		let out = Script::from("for (;;)\n\tif (a()) break\nreturn a");
+		assert_eq!(out.statement_count(), 2);
+		let out = Script::from("");
+		assert_eq!(out.statement_count(), 0);
+		assert_eq!(out.brace_depth_max(), 0);
+	}
+
+	#[test]
+	fn script_statements() {
+		let spans = |sql: &str| -> Vec<String> {
+			let out = Script::from(sql);
+			out.statements().into_iter().map(|s| out[s.span].to_owned()).collect()
+		};
+		let sql = r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#;
+		assert_eq!(spans(sql), vec![sql]);
+		assert_eq!(
+			spans("function a() { return 1; }\nlet b = a(); ; // c;\ntry { b++ } catch (e) {} return b"),
+			vec!["function a() { return 1; }", "let b = a();", "try { b++ } catch (e) {}", "return b"]
+		);
+		assert_eq!(
+			spans("  var a = ';';\n/* ; */ debugger\n\tif (a) { b; } else { c; };\ndo { a-- } while (a)"),
+			vec!["var a = ';';", "debugger", "if (a) { b; } else { c; };", "do { a-- } while (a)"]
+		);
+		// A `;` within parens does not end a statement
+		assert_eq!(
+			spans("for (let i = 0; i < n; i++) {} for (;;) break; return [a; b]"),
+			vec!["for (let i = 0; i < n; i++) {}", "for (;;) break;", "return [a; b]"]
+		);
+		// An `else` continues an `if` without braces, as a `while` does a `do`
+		assert_eq!(
+			spans("if (a) b; else c; d;\nif (a) b\nelse if (c) d; else e\ndo a(); while (b) c()"),
+			vec![
+				"if (a) b; else c;",
+				"d;",
+				"if (a) b\nelse if (c) d; else e",
+				"do a(); while (b)",
+				"c()"
+			]
+		);
+		assert_eq!(spans("if (a) {} while (b) {}"), vec!["if (a) {}", "while (b) {}"]);
+		// A line break ends a statement where a `;` would be inserted
+		assert_eq!(
+			spans("a.push(1)\na.push(2)\nlet b = a\n\t.map(f)\n\t[0]\nb++\n++b\nreturn `${b}`"),
+			vec![
+				"a.push(1)",
+				"a.push(2)",
+				"let b = a\n\t.map(f)\n\t[0]",
+				"b++",
+				"++b",
+				"return `${b}`"
+			]
+		);
+		assert_eq!(
+			spans("if (a)\n\tb()\nfor (const v\nof a)\n\tc(v)\nlet d = e\n`f`\nlet g = h\n\t+ i"),
+			vec![
+				"if (a)\n\tb()",
+				"for (const v\nof a)\n\tc(v)",
+				"let d = e\n`f`",
+				"let g = h\n\t+ i"
+			]
+		);
+		assert_eq!(spans("a\n.if\nb\nlet\nc = 1"), vec!["a\n.if", "b", "let\nc = 1"]);
+		assert_eq!(spans("let a = `${ 1; 2 }`; a"), vec!["let a = `${ 1; 2 }`;", "a"]);
+		assert_eq!(spans("`a${ `b${c;}` }d`; e"), vec!["`a${ `b${c;}` }d`;", "e"]);
+		assert!(spans("").is_empty());
+		assert!(spans(" ;; // a").is_empty());
+	}
+
+	#[test]
+	fn script_analyze() {
+		let sql = r#"
+			'use strict';
+			const { run } = await import('lib');
+			function* gen(n) { for (let i = 0; i < n; i++) { yield `${prefix}:${i}`; } }
+			let res = { a: { b: [...gen(limit)] }, c: "text with uneven {{{ brackets" };
+			if (res.a) { await fetch(url, { body: `${JSON.stringify(res)}\r\n` }); } else { return; }
+			return run(res, /[{]/.test(other));
+		"#;
+		let out = Script::from(sql);
+		let res = out.analyze();
+		assert_eq!(res.free_identifiers, out.free_identifiers());
+		assert_eq!(res.string_literals, out.string_literals());
+		assert_eq!(res.capabilities, out.capabilities());
+		assert_eq!(res.statement_count, out.statement_count());
+		assert_eq!(res.max_depth, out.brace_depth_max());
+		for name in ["limit", "other", "prefix", "url"] {
+			assert!(res.free_identifiers.contains(name));
+		}
+		assert!(!res.free_identifiers.contains("res") && !res.free_identifiers.contains("run"));
+		assert_eq!(
+			res.capabilities,
+			ScriptCapabilities {
+				uses_async: true,
+				uses_import: true,
+				uses_generators: true,
+				uses_fetch: true,
+			}
+		);
+		assert_eq!((res.statement_count, res.max_depth), (6, 2));
+		let values: Vec<_> = res.string_literals.iter().map(|v| v.value.as_str()).collect();
+		assert_eq!(
+			values,
+			vec!["use strict", "lib", "", ":", "", "text with uneven {{{ brackets", "", "\r\n"]
+		);
+		// A script which does not parse is analyzed up to the failure
+		let out = Script::from("let a = b; { c");
+		assert_eq!(out.analyze().free_identifiers, out.free_identifiers());
+		assert_eq!(out.analyze().statement_count, 2);
+	}
+
+	#[test]
+	fn script_describe() {
+		let out = Script::from(
+			r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#,
+		);
+		let res = out.describe();
+		assert_eq!(
+			res,
+			ScriptInfo {
+				valid: true,
+				length: 97,
+				lines: 1,
+				statements: 1,
+				depth: 2,
+			}
+		);
+		assert_eq!(
+			serde_json::to_string(&res).unwrap(),
+			r#"{"valid":true,"length":97,"lines":1,"statements":1,"depth":2}"#
+		);
+		let res = Script::from("return { a: 1;").describe();
+		assert!(!res.valid);
+	}
+
+	// Parses a script body by feeding it to a parser in the given chunks
+	fn streamed(chunks: &[&[u8]]) -> Result<Script, ScriptError> {
+		let mut parser = ScriptParser::default();
+		for v in chunks {
+			parser.feed(v)?;
+		}
+		parser.finish()
+	}
+
+	#[test]
+	fn script_parser_complex() {
+		let sql = r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#;
+		let res = Script::parse_with(sql, &ScriptOptions::default());
+		assert_eq!(res, Ok(Script::from(sql)));
+		// Split in two at every byte, including mid-string and mid-escape
+		for n in 0..=sql.len() {
+			let (a, b) = sql.as_bytes().split_at(n);
+			assert_eq!(streamed(&[a, b]), res, "split at byte {}", n);
+		}
+		// Fed one byte at a time
+		let bytes: Vec<_> = sql.as_bytes().chunks(1).collect();
+		assert_eq!(streamed(&bytes), res);
+		// The state within the string, and just after its escape
+		let mut parser = ScriptParser::default();
+		let n = sql.find("uneven").unwrap();
+		let state = parser.feed(&sql.as_bytes()[..n]).unwrap();
+		assert_eq!(
+			state,
+			ScriptState {
+				length: n,
+				depth: 2,
+				part: ScriptPart::String,
+			}
+		);
+		let n = sql.find(r#"\""#).unwrap() + 1;
+		let state = parser.feed(&sql.as_bytes()[state.length..n]).unwrap();
+		assert_eq!((state.length, state.depth, state.part), (n, 2, ScriptPart::String));
+		let state = parser.feed(&sql.as_bytes()[n..]).unwrap();
+		assert_eq!((state.depth, state.part), (0, ScriptPart::Code));
+		assert_eq!(parser.finish(), res);
+	}
+
+	#[test]
+	fn script_parser_matches_batch() {
+		let tests = [
+			"let a = 1 / 2; // half\nreturn a / 2;",
+			"let r = /a[/]b\\//gi;\nreturn r.test('x');",
+			"return /* a } */ `x ${ { a: `y${1}` }.a } $ z`;",
+			"return 'é' + \"\\u00e9\";",
+			"if (a) { return b } else { return c }",
+			"return 'unterminated;",
+			"return `unterminated ${1}",
+			"return { a: 1 /* unterminated",
+			"return { a: 1 };}",
+			"return { a: 1;",
+			"let a = 1;\x01",
+			"",
+		];
+		for sql in tests {
+			let res = Script::parse_with(sql, &ScriptOptions::default());
+			for n in 0..=sql.len() {
+				let (a, b) = sql.as_bytes().split_at(n);
+				assert_eq!(streamed(&[a, b]), res, "{:?} split at byte {}", sql, n);
+			}
+			let bytes: Vec<_> = sql.as_bytes().chunks(1).collect();
+			assert_eq!(streamed(&bytes), res, "{:?} fed bytewise", sql);
+		}
+	}
+
+	#[test]
+	fn script_parser_options() {
+		let opts = ScriptOptions {
+			depth: 2,
+			max_len: 16,
+			normalize_newlines: true,
+			..Default::default()
+		};
+		let tests = ["{ a;\r\n}", "{ { { } } }", "return 'a long string';", "{ '", "return 1;}}"];
+		for sql in tests {
+			let res = Script::parse_with(sql, &opts);
+			for n in 0..=sql.len() {
+				let mut parser = ScriptParser::new(opts.clone());
+				let (a, b) = sql.as_bytes().split_at(n);
+				let out = parser.feed(a).and_then(|_| parser.feed(b)).and_then(|_| parser.finish());
+				assert_eq!(out, res, "{:?} split at byte {}", sql, n);
+			}
+		}
+	}
+
+	#[test]
+	fn script_parser_early_error() {
+		let opts = ScriptOptions {
+			depth: 1,
+			..Default::default()
+		};
+		let mut parser = ScriptParser::new(opts);
+		assert!(parser.feed(b"if (a) { ").is_ok());
+		let err = ScriptError::DepthExceeded {
+			offset: 9,
+		};
+		assert_eq!(parser.feed(b"{ 'a"), Err(err.clone()));
+		assert_eq!(parser.feed(b"' } }"), Err(err.clone()));
+		assert_eq!(parser.finish(), Err(err));
+	}
+
+	#[test]
+	fn script_parser_utf8() {
+		let sql = "return 'é';".as_bytes();
+		let n = sql.iter().position(|&b| b >= 0x80).unwrap() + 1;
+		let mut parser = ScriptParser::default();
+		let state = parser.feed(&sql[..n]).unwrap();
+		assert_eq!((state.length, state.part), (n, ScriptPart::String));
+		parser.feed(&sql[n..]).unwrap();
+		assert_eq!(parser.finish(), Ok(Script::from("return 'é';")));
+		// An incomplete character at the end of the body
+		assert!(streamed(&[&sql[..n]]).is_err());
+		// A byte which can not begin a character
+		let mut parser = ScriptParser::default();
+		assert_eq!(
+			parser.feed(b"return \xff;"),
+			Err(ScriptError::Parse {
+				offset: 7,
+				message: "invalid utf-8 in script",
+			})
+		);
+	}
+
+	#[test]
+	fn script_fuzz() {
+		use rand::{Rng, SeedableRng};
+		// Fragments which are significant when scanning a body
+		let parts = [
+			"{", "}", "`", "${", "$", "'", "\"", "\\", "/", "*", "//", "/*", "*/", "[", "]", "(",
+			")", "=", "a", " ", "\n", "\r", "\x01", "é",
+		];
+		let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+		for _ in 0..10_000 {
+			let len = rng.gen_range(0..64);
+			let sql = match rng.gen_bool(0.5) {
+				true => (0..len).map(|_| parts[rng.gen_range(0..parts.len())]).collect(),
+				false => {
+					let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+					String::from_utf8_lossy(&bytes).into_owned()
+				}
+			};
+			// The body is either parsed or rejected, without panicking
+			if let Ok((rest, out)) = script(&sql) {
+				assert_eq!(out.len() + rest.len(), sql.len());
+			}
+			// The incremental parser makes the same decision
+			let res = Script::parse_with(&sql, &ScriptOptions::default());
+			let (a, b) = sql.as_bytes().split_at(rng.gen_range(0..=sql.len()));
+			assert_eq!(streamed(&[a, b]), res, "{:?} split at byte {}", sql, a.len());
+			// Any other analysis of the body does not panic either
+			let v = Script::from(sql.as_str());
+			let _ = (v.minify(), v.format(2), v.redact(), v.spans(), v.string_literals());
+			let _ = (v.tokens().count(), v.statement_count(), v.free_identifiers());
+		}
+	}
+
+	#[test]
+	fn script_capabilities() {
+		let none = ScriptCapabilities::default();
+		let out = Script::from(
+			r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#,
+		);
+		assert_eq!(out.capabilities(), none);
+		let out = Script::from("return this.values.map(v => `This value is ${Number(v * 3)}`);");
+		assert_eq!(out.capabilities(), none);
+		// Words in strings, comments and regexes are not uses
+		let out = Script::from("// await import\nreturn ['async', /fetch/, `yield`];");
+		assert_eq!(out.capabilities(), none);
+		let out =
+			Script::from("const res = await fetch('https://example.com'); return res.json();");
+		let res = out.capabilities();
+		assert!(res.uses_async && res.uses_fetch);
+		assert!(!res.uses_import && !res.uses_generators);
+		let out = Script::from("const { run } = await import(`./${name}.js`); return run();");
+		let res = out.capabilities();
+		assert!(res.uses_async && res.uses_import);
+		let out = Script::from("return eval('imp' + 'ort(\"lib\")');");
+		assert!(out.capabilities().uses_import);
+		let out = Script::from("function /* gen */ * g() { yield 1; } return [...g()];");
+		assert!(out.capabilities().uses_generators);
+		let out = Script::from("function g() { return 2 * 3; } return g();");
+		assert!(!out.capabilities().uses_generators);
+		// A script which does not parse is scanned as far as it parses
+		let out = Script::from("import('a'); return 'b");
+		assert!(out.capabilities().uses_import);
+	}
+
+	#[test]
+	fn script_embedded_sql() {
+		let queries = |v: &str| {
+			let out = Script::from(v).embedded_sql();
+			out.queries.into_iter().map(|v| v.value).collect::<Vec<_>>()
+		};
+		let dynamic = |v: &str| {
+			let out = Script::from(v).embedded_sql();
+			out.dynamic.into_iter().map(|r| v[r].to_owned()).collect::<Vec<_>>()
+		};
+		// Literal queries are extracted, with their escapes applied
+		let out = r#"
+			const a = await surrealdb.query('SELECT * FROM \'a\'', { id: 1 });
+			const b = await surrealdb?.query?.(`UPDATE b SET c = 1`);
+			const c = await globalThis.surrealdb['query']("DELETE c");
+			return [a, b, c];
+		"#;
+		assert_eq!(queries(out), vec!["SELECT * FROM 'a'", "UPDATE b SET c = 1", "DELETE c"]);
+		assert!(dynamic(out).is_empty());
+		// Queries which are built at runtime are flagged
+		let out = "await surrealdb.query('SELECT * FROM ' + table); await surrealdb.query(`DELETE ${id}`, {});";
+		assert!(queries(out).is_empty());
+		assert_eq!(dynamic(out), vec!["'SELECT * FROM ' + table", "`DELETE ${id}`"]);
+		// Uses of the bridge which can not be followed are flagged
+		let out = "const q = surrealdb.query; const s = surrealdb; return typeof surrealdb;";
+		assert_eq!(dynamic(out), vec!["surrealdb.query", "surrealdb"]);
+		// Words in strings and comments, and other objects, are not uses
+		let out =
+			"// surrealdb.query('a')\nreturn [db.surrealdb.query('b'), 'surrealdb.query(c)'];";
+		assert!(!Script::from(out).embeds_sql());
+		assert!(Script::from("surrealdb.query(q)").embeds_sql());
+	}
+
+	#[test]
+	fn script_writer() {
+		use std::fmt::Write;
+		let mut out = ScriptWriter::default();
+		let field = "values";
+		write!(out, "return this.{}.map(", field).unwrap();
+		write!(out, "v => `This value is ${{").unwrap();
+		write!(out, "Number(v * {})", 3).unwrap();
+		out.write_str("}`);").unwrap();
+		assert_eq!(
+			out.finish(),
+			Ok(Script::from("return this.values.map(v => `This value is ${Number(v * 3)}`);"))
+		);
+		// An error is only returned once the body is finished
+		let mut out = ScriptWriter::default();
+		assert!(write!(out, "return {{ a: ").is_ok());
+		assert!(write!(out, "'{}", 1).is_ok());
+		assert_eq!(
+			out.finish(),
+			Err(ScriptError::UnterminatedString {
+				offset: 12,
+			})
+		);
+		// The options are applied to the whole body
+		let mut out = ScriptWriter::new(ScriptOptions {
+			max_len: 8,
+			..Default::default()
+		});
+		write!(out, "return {};", 1).unwrap();
+		assert!(out.finish().is_err());
+	}
+
+	#[test]
+	fn script_string_literals() {
+		let out = Script::from(
+			"let a = 'it\\'s', b = \"say \\\"hi\\\"\\n\", c = `x ${`y${a}`} \\u{1F600}\\${z}`; return '';",
+		);
+		let res: Vec<_> =
+			out.string_literals().into_iter().map(|v| (v.kind, &out[v.span], v.value)).collect();
+		assert_eq!(
+			res,
+			vec![
+				(TokenKind::StringLiteral, "it\\'s", "it's".to_owned()),
+				(TokenKind::StringLiteral, "say \\\"hi\\\"\\n", "say \"hi\"\n".to_owned()),
+				(TokenKind::TemplateLiteral, "x ", "x ".to_owned()),
+				(TokenKind::TemplateLiteral, "y", "y".to_owned()),
+				(TokenKind::TemplateLiteral, "", "".to_owned()),
+				(TokenKind::TemplateLiteral, " \\u{1F600}\\${z}", " \u{1F600}${z}".to_owned()),
+				(TokenKind::StringLiteral, "", "".to_owned()),
+			]
+		);
+		// A `}` in the text of a template literal is not a delimiter
+		let out = Script::from("return `{a}` + `}`;");
+		let res: Vec<_> = out.string_literals().into_iter().map(|v| v.value).collect();
+		assert_eq!(res, vec!["{a}", "}"]);
+	}
+
+	#[test]
+	fn script_unescape() {
+		let tests = [
+			(r"a\nb\tc\rd", "a\nb\tc\rd"),
+			(r"\b\f\v\0", "\u{8}\u{c}\u{b}\0"),
+			(r"\x41\u0042\u{43}\u{1F600}", "ABC\u{1F600}"),
+			(r"\uD83D\uDE00", "\u{1F600}"),
+			(r"\uD83D!", "\u{FFFD}!"),
+			(r"\q\'\\", "q'\\"),
+			("a\\\nb\\\r\nc", "abc"),
+			(r"\x4\u12\u{110000}", r"\x4\u12\u{110000}"),
+			("trailing\\", "trailing\\"),
+		];
+		for (v, res) in tests {
+			assert_eq!(unescape(v), res, "{:?}", v);
+		}
+	}
+
+	#[test]
+	fn script_with_params() {
+		let params = BTreeMap::from([
+			("a".to_owned(), "1 + 1".to_owned()),
+			("name".to_owned(), "'tobie'".to_owned()),
+		]);
+		let tests = [
+			("return $a * 2;", "return (1 + 1) * 2;"),
+			("return [$a, $name, $b];", "return [(1 + 1), ('tobie'), $b];"),
+			("return `${$a}`;", "return `${(1 + 1)}`;"),
+			("return $name.length + $names;", "return ('tobie').length + $names;"),
+			// Literals, comments and property names are untouched
+			("return '$a' + \"$name\";", "return '$a' + \"$name\";"),
+			("return `$a ${'$a'} $name`;", "return `$a ${'$a'} $name`;"),
+			("return /$a/.test(x) // $a", "return /$a/.test(x) // $a"),
+			("return { $a: x.$a, b: $a };", "return { $a: x.$a, b: (1 + 1) };"),
+		];
+		for (sql, res) in tests {
+			assert_eq!(Script::from(sql).with_params(&params), Ok(Script::from(res)), "{:?}", sql);
+		}
+		// The result is checked once the expressions are substituted
+		let params = BTreeMap::from([("a".to_owned(), "'oops".to_owned())]);
+		assert_eq!(
+			Script::from("return $a;").with_params(&params),
+			Err(ScriptError::UnterminatedString {
+				offset: 8,
+			})
+		);
+	}
+
+	#[test]
+	fn script_map_lines() {
+		let script = Script::from("// header\nlet a = 1;\r\n\nreturn a;");
+		// Prepend a directive, keeping the rest of the script
+		let out = Script::from_lines(std::iter::once("\"use strict\";").chain(script.lines()));
+		assert_eq!(out, Ok(Script::from("\"use strict\";\n// header\nlet a = 1;\n\nreturn a;")));
+		assert_eq!(out.unwrap().directives(), vec!["use strict"]);
+		// Strip the header line, keeping every line break
+		let mut first = true;
+		let out = script.map_lines(|l| match std::mem::take(&mut first) {
+			true => String::new(),
+			false => l.to_owned(),
+		});
+		assert_eq!(out, Ok(Script::from("\nlet a = 1;\r\n\nreturn a;")));
+		// A result which no longer parses is rejected
+		let out = script.map_lines(|l| l.replace("1;", "'1;"));
+		assert_eq!(
+			out,
+			Err(ScriptError::UnterminatedString {
+				offset: 18,
+			})
+		);
+	}
+
+	#[test]
+	fn script_contains_tokens() {
+		let pattern = [
+			TokenKindPattern::Text(TokenKind::Identifier, "eval".to_owned()),
+			TokenKindPattern::Text(TokenKind::Punct, "(".to_owned()),
+			TokenKindPattern::Many,
+			TokenKindPattern::Text(TokenKind::Punct, ")".to_owned()),
+		];
+		let tests: [(&str, Vec<usize>); 7] = [
+			("eval(x)", vec![0]),
+			("return eval ( 'a' + b );", vec![7]),
+			("eval\n/* hidden */\n(\n\tx\n)", vec![0]),
+			("let a = `${eval(x)}`;", vec![11]),
+			("eval(a); window.eval(b);", vec![0, 16]),
+			// Text within literals and comments is never matched
+			("return 'eval(x)' + \"eval(y)\" + `eval(z)`; // eval(w)", vec![]),
+			("let evaluate = 1; return eval;", vec![]),
+		];
+		for (sql, res) in tests {
+			assert_eq!(Script::from(sql).contains_tokens(&pattern), res, "{:?}", sql);
+		}
+		assert_eq!(
+			Script::from("a(b)").contains_tokens(&[
+				TokenKindPattern::Kind(TokenKind::Identifier),
+				TokenKindPattern::Any
+			]),
+			vec![0, 2]
+		);
+		assert!(Script::from("eval(x)").contains_tokens(&[]).is_empty());
+	}
+
+	#[test]
+	fn script_token_diff() {
+		let one = Script::from("function add(a, b) {\n\treturn a + b;\n}\nreturn add(1, 2);");
+		// A script which has only been reindented is equal throughout
+		let two = Script::from("function add(a,b){\n    return a+b;\n}\n\nreturn add(1,2);");
+		let res = one.token_diff(&two);
+		assert_eq!(res.len(), 1);
+		assert!(matches!(&res[0], TokenDiff::Equal(v) if v.len() == 22));
+		// A real edit is shown as runs of deleted and inserted tokens
+		let two = Script::from("function add(a, b) {\n\treturn a * b + 1;\n}\nreturn add(1, 2);");
+		let res = one.token_diff(&two);
+		assert_eq!(res.len(), 6);
+		assert!(matches!(&res[0], TokenDiff::Equal(v) if v.len() == 10));
+		assert_eq!(res[1], TokenDiff::Delete(vec!["+".to_owned()]));
+		assert_eq!(res[2], TokenDiff::Insert(vec!["*".to_owned()]));
+		assert_eq!(res[3], TokenDiff::Equal(vec!["b".to_owned()]));
+		assert_eq!(res[4], TokenDiff::Insert(vec!["+".to_owned(), "1".to_owned()]));
+		assert!(matches!(&res[5], TokenDiff::Equal(v) if v.len() == 10));
+		// Comments and literals are compared as tokens
+		let one = Script::from("return 'a'; // one");
+		let two = Script::from("return \"a\";");
+		assert_eq!(
+			one.token_diff(&two),
+			vec![
+				TokenDiff::Equal(vec!["return".to_owned()]),
+				TokenDiff::Delete(vec!["'a'".to_owned()]),
+				TokenDiff::Insert(vec!["\"a\"".to_owned()]),
+				TokenDiff::Equal(vec![";".to_owned()]),
+				TokenDiff::Delete(vec!["// one".to_owned()]),
+			]
+		);
+		assert!(Script::from("").token_diff(&Script::from("")).is_empty());
+	}
+
+	#[test]
+	fn script_redact() {
+		let out = Script::from(
+			"// it's a 'comment'\nlet a = \"pass\\\"word\", b = '', c = /'x'/;\nreturn `${a}:${b} ${'c'}`;",
+		);
+		assert_eq!(
+			"// it's a 'comment'\nlet a = \"***\", b = '', c = /'x'/;\nreturn `${a}***${b}***${'***'}`;",
+			format!("{}", out.redact())
+		);
+	}
+
+	#[test]
+	fn script_redact_invalid() {
+		let out = Script::from("return 'a'; } let b = 'c'; let d = 'e");
+		assert_eq!("return '***'; } let b = '***'; let d = ***", format!("{}", out.redact()));
+	}
+
+	#[test]
+	fn script_to_sql() {
+		use crate::sql::function::{function, Function};
+		let tests =
+			["return { a: '}' };", "return `${ { b: 1 }.b }`; /* } */", "return 1; // }", ""];
+		for test in tests {
+			let out = Script::from(test);
+			let sql = out.to_sql();
+			let res = function(&sql);
+			assert!(res.is_ok());
+			let (i, v) = res.unwrap();
+			assert_eq!(i, "");
+			match v {
+				Function::Script(v, _) => assert_eq!(v.trim_end(), out.as_str()),
+				_ => unreachable!(),
+			}
+		}
+	}
+
+	#[test]
+	fn script_builder() {
+		use crate::sql::function::function;
+		let out = ScriptBuilder::new(Script::from("return arguments[0] + arguments[1];"))
+			.typed_param("a", "number")
+			.param("b")
+			.build()
+			.unwrap();
+		let sql = out.to_string();
+		assert_eq!("function(<number> $a, $b) {return arguments[0] + arguments[1];}", sql);
+		let res = function(&sql);
+		assert!(res.is_ok());
+		assert_eq!(res.unwrap().1, out);
+	}
+
+	#[test]
+	fn script_builder_invalid() {
+		let body = Script::from("return 1;");
+		let res = ScriptBuilder::new(body.clone()).param("a").param("a").build();
+		assert_eq!(res, Err(ScriptBuilderError::DuplicateParam(String::from("a"))));
+		let res = ScriptBuilder::new(body.clone()).param("a-b").build();
+		assert_eq!(res, Err(ScriptBuilderError::InvalidParam(String::from("a-b"))));
+		let res = ScriptBuilder::new(body).typed_param("a", "integer").build();
+		assert_eq!(res, Err(ScriptBuilderError::InvalidKind(String::from("integer"))));
+		let res = ScriptBuilder::new(Script::from("return {")).build();
+		assert_eq!(
+			res,
+			Err(ScriptBuilderError::Body(ScriptError::UnmatchedOpen {
+				offset: 7
+			}))
+		);
+	}
+
+	#[test]
+	fn script_control_code() {
+		let sql = "let a = 1;\0return a;";
 		let res = script(sql);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 10,
+				kind: ScriptErrorKind::Invalid,
+				message: "invalid control character in script",
+				..
+			}))
+		));
+		let opts = ScriptOptions {
+			reject_control: false,
+			..Default::default()
+		};
+		let res = script_with(sql, &opts);
 		assert!(res.is_ok());
-		let out = res.unwrap().1;
-		assert_eq!("return true;", format!("{}", out));
-		assert_eq!(out, Script::from("return true;"));
 	}
 
 	#[test]
-	fn script_object() {
-		let sql = "return { test: true, something: { other: true } };";
+	fn script_control_literal() {
+		let sql = "let a = '\0\x07';\r\n\treturn `\x01${a}`;";
 		let res = script(sql);
 		assert!(res.is_ok());
 		let out = res.unwrap().1;
-		assert_eq!("return { test: true, something: { other: true } };", format!("{}", out));
-		assert_eq!(out, Script::from("return { test: true, something: { other: true } };"));
+		assert_eq!(sql, format!("{}", out));
 	}
 
 	#[test]
-	fn script_closure() {
-		let sql = "return this.values.map(v => `This value is ${Number(v * 3)}`);";
+	fn script_normalize_newlines() {
+		let sql = "let a = 1; // one\r\nlet b = 'x\\\r\ny';\rreturn `\r\n${a}`;\n";
 		let res = script(sql);
 		assert!(res.is_ok());
 		let out = res.unwrap().1;
+		assert_eq!(sql, format!("{}", out));
+		let opts = ScriptOptions {
+			normalize_newlines: true,
+			..Default::default()
+		};
+		let res = script_with(sql, &opts);
+		assert!(res.is_ok());
+		let out = res.unwrap().1;
 		assert_eq!(
-			"return this.values.map(v => `This value is ${Number(v * 3)}`);",
+			"let a = 1; // one\nlet b = 'x\\\r\ny';\nreturn `\r\n${a}`;\n",
 			format!("{}", out)
 		);
-		assert_eq!(
-			out,
-			Script::from("return this.values.map(v => `This value is ${Number(v * 3)}`);")
-		);
+		let lines: Vec<_> = out.spans().into_iter().map(|(_, l, _)| l).collect();
+		assert_eq!(lines, vec![1, 1, 1, 2, 3, 4, 4, 5, 5, 5, 5, 5]);
 	}
 
 	#[test]
-	fn script_complex() {
-		let sql = r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#;
-		let res = script(sql);
-		assert!(res.is_ok());
-		let out = res.unwrap().1;
+	fn script_spans_mixed_newlines() {
+		let out = Script::from("a;\r\nb;\rc;\nd;");
+		let spans: Vec<_> = out.spans();
+		assert_eq!(spans, vec![(0, 1, 0)]);
+		let out = Script::from("a;\r\n'b';\r'c';\n'd';");
+		let lines: Vec<_> = out.spans().into_iter().map(|(_, l, _)| l).collect();
+		assert_eq!(lines, vec![1, 2, 2, 3, 3, 4, 4]);
+	}
+
+	#[test]
+	fn script_quotes_disabled() {
+		let opts = ScriptOptions {
+			string_backtick: false,
+			..Default::default()
+		};
+		// A backtick is plain input, so braces after it are still counted
+		let sql = "return `{ a: 1 }`;";
+		let res = script_with(sql, &opts);
+		assert_eq!(res.unwrap(), ("", Script::from(sql)));
+		let res = script_with("return `}` + 1; -- more", &opts);
+		assert_eq!(res.unwrap(), ("}` + 1; -- more", Script::from("return `")));
+		// A lone backtick is no longer an unterminated template literal
+		assert!(Script::parse_with("return `a;", &ScriptOptions::default()).is_err());
+		assert_eq!(Script::parse_with("return `a;", &opts), Ok(Script::from("return `a;")));
+		// Other quote styles are unaffected
+		assert_eq!(Script::parse_with("return '`';", &opts), Ok(Script::from("return '`';")));
 		assert_eq!(
-			r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#,
-			format!("{}", out)
+			Script::parse_with("return \"a;", &opts),
+			Err(ScriptError::UnterminatedString {
+				offset: 7,
+			})
 		);
+		// A disabled quote can be rejected instead
+		let opts = ScriptOptions {
+			reject_disabled_quotes: true,
+			..opts
+		};
+		assert_eq!(Script::parse_with("return 'a`';", &opts), Ok(Script::from("return 'a`';")));
 		assert_eq!(
-			out,
-			Script::from(
-				r#"return { test: true, some: { object: "some text with uneven {{{ {} \" brackets", else: false } };"#
-			)
+			Script::parse_with("return `a`;", &opts),
+			Err(ScriptError::Parse {
+				offset: 7,
+				message: "disabled quote style in script",
+			})
 		);
+		// The incremental parser makes the same decisions
+		for sql in ["return `{ a: 1 }`;", "return `a;", "return `a`;", "return '`';"] {
+			let res = Script::parse_with(sql, &opts);
+			for n in 0..=sql.len() {
+				let (a, b) = sql.as_bytes().split_at(n);
+				let mut parser = ScriptParser::new(opts.clone());
+				let _ = parser.feed(a).and_then(|_| parser.feed(b));
+				assert_eq!(parser.finish(), res, "{:?} split at byte {}", sql, n);
+			}
+		}
+		let opts = ScriptOptions {
+			string_single: false,
+			string_double: false,
+			..Default::default()
+		};
+		assert_eq!(
+			Script::parse_with("a = \"'{\"';", &opts).unwrap_err(),
+			ScriptError::UnmatchedOpen {
+				offset: 6,
+			}
+		);
+	}
+
+	#[test]
+	fn script_hashbang() {
+		// A hashbang line is skipped, so its quotes and braces are not code
+		let sql = "#!/usr/bin/env node --eval '{\nreturn 1; } rest";
+		let res = script(sql);
+		assert_eq!(res.unwrap(), ("} rest", Script::from("\nreturn 1; ")));
+		let res = Script::parse_with("#!node", &ScriptOptions::default());
+		assert_eq!(res, Ok(Script::from("")));
+		// It is kept where the body must round-trip exactly
+		let opts = ScriptOptions {
+			keep_hashbang: true,
+			..Default::default()
+		};
+		let sql = "#!/usr/bin/env node\r\nreturn `{`;";
+		assert_eq!(Script::parse_with(sql, &opts), Ok(Script::from(sql)));
+		assert_eq!(Script::from(sql).tokens().next().unwrap().kind, TokenKind::Comment);
+		// A `#` anywhere else is treated as it always was
+		for sql in [" #!'a", "return 1; #!'a", "# !'a", "a #!{"] {
+			assert!(Script::parse_with(sql, &opts).is_err(), "{:?}", sql);
+		}
+		let res = script("return a.#b; #! }");
+		assert_eq!(res.unwrap(), ("}", Script::from("return a.#b; #! ")));
+		// The incremental parser makes the same decisions
+		for sql in ["#!a '{\nreturn 1;", "#!a", "#", "# !a '{", " #!a '{\n"] {
+			for opts in [ScriptOptions::default(), opts.clone()] {
+				let res = Script::parse_with(sql, &opts);
+				for n in 0..=sql.len() {
+					let (a, b) = sql.as_bytes().split_at(n);
+					let mut parser = ScriptParser::new(opts.clone());
+					let _ = parser.feed(a).and_then(|_| parser.feed(b));
+					assert_eq!(parser.finish(), res, "{:?} split at byte {}", sql, n);
+				}
+			}
+		}
+	}
+	#[test]
+	fn script_byte_order_mark() {
+		// A byte order mark which begins the body is removed
+		let res = script("\u{feff}return 1; } rest");
+		assert_eq!(res.unwrap(), ("} rest", Script::from("return 1; ")));
+		let res = Script::parse_all("\u{feff}#!node\nreturn 1;");
+		assert_eq!(res, Ok(Script::from("\nreturn 1;")));
+		// Anywhere else, it is rejected as a control character is
+		for (sql, offset) in
+			[("return 1;\u{feff}", 9), (" \u{feff}return 1;", 1), ("\u{feff}\u{feff}", 3)]
+		{
+			let res = Script::parse_with(sql, &ScriptOptions::default());
+			assert_eq!(res.map_err(|e| e.offset()), Err(offset), "{:?}", sql);
+		}
+		// Unless it is within a literal, or control characters are allowed
+		let res = Script::parse_all("return '\u{feff}';");
+		assert_eq!(res, Ok(Script::from("return '\u{feff}';")));
+		let opts = ScriptOptions {
+			reject_control: false,
+			..Default::default()
+		};
+		let res = Script::parse_with("\u{feff}a\u{feff}", &opts);
+		assert_eq!(res, Ok(Script::from("a\u{feff}")));
+		// Whitespace around the body is kept by each of the parsers
+		let sql = " \t\nreturn 1;\r\n ";
+		assert_eq!(Script::parse_all(sql), Ok(Script::from(sql)));
+		assert_eq!(Script::parse_prefix(sql), Ok((Script::from(sql), "")));
+		assert_eq!(Script::parse_with(sql, &ScriptOptions::default()), Ok(Script::from(sql)));
+		// The incremental parser makes the same decisions
+		for sql in ["\u{feff}return 1;", "return\u{feff}1;", "\u{feff}", sql] {
+			let res = Script::parse_with(sql, &ScriptOptions::default());
+			for n in 0..=sql.len() {
+				let (a, b) = sql.as_bytes().split_at(n);
+				let mut parser = ScriptParser::default();
+				let _ = parser.feed(a).and_then(|_| parser.feed(b));
+				assert_eq!(parser.finish(), res, "{:?} split at byte {}", sql, n);
+			}
+		}
+	}
+
+	#[test]
+	fn script_max_len() {
+		let opts = ScriptOptions {
+			max_len: 16,
+			..Default::default()
+		};
+		let res = script_with("return '1234567';", &opts);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 16,
+				kind: ScriptErrorKind::TooLong,
+				message: "script exceeds the maximum length",
+				..
+			}))
+		));
+		let res = script_with("return '123456';", &opts);
+		assert_eq!(res.unwrap(), ("", Script::from("return '123456';")));
+		let res = script_with("return '123456';} + 1; -- more query text", &opts);
+		assert_eq!(res.unwrap(), ("} + 1; -- more query text", Script::from("return '123456';")));
+		let res = script_with("return '1234567';} + 1; -- more query text", &opts);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 16,
+				kind: ScriptErrorKind::TooLong,
+				message: "script exceeds the maximum length",
+				..
+			}))
+		));
+		let res = script_with("return '12345;} + 1; -- more query text", &opts);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 16,
+				kind: ScriptErrorKind::TooLong,
+				message: "script exceeds the maximum length",
+				..
+			}))
+		));
+		let res = script_with("return 1;\x01} + 1; -- more query text", &opts);
+		assert!(matches!(
+			res,
+			Err(Err::Failure(Error::ScriptError {
+				offset: 9,
+				kind: ScriptErrorKind::Invalid,
+				message: "invalid control character in script",
+				..
+			}))
+		));
+	}
+
+	#[test]
+	fn script_directives() {
+		let out = Script::from("// Strict\n\"use strict\";\n'another'\nreturn 1;");
+		assert_eq!(out.directives(), vec!["use strict", "another"]);
+		let out = Script::from("return 1;");
+		assert!(out.directives().is_empty());
+	}
+
+	#[test]
+	fn script_directives_not_leading() {
+		let out = Script::from("let a = 1; 'use strict'; return a;");
+		assert!(out.directives().is_empty());
+		let out = Script::from("'use strict'.length; return 1;");
+		assert!(out.directives().is_empty());
+		let out = Script::from("'use strict' + 1;");
+		assert!(out.directives().is_empty());
+	}
+
+	#[test]
+	fn script_is_identifier() {
+		assert!(is_identifier("record"));
+		assert!(is_identifier("$value"));
+		assert!(is_identifier("_n2"));
+		assert!(!is_identifier(""));
+		assert!(!is_identifier("2n"));
+		assert!(!is_identifier("my-name"));
+		assert!(!is_identifier("return"));
 	}
 }
@@ -474,6 +474,10 @@ impl DefineScopeStatement {
 		opt.needs(Level::Db)?;
 		// Allowed to run?
 		opt.check(Level::Db)?;
+		// Compile any embedded functions
+		if opt.scripting.validate_on_define {
+			self.signup.iter().chain(&self.signin).try_for_each(Value::validate)?;
+		}
 		// Clone transaction
 		let run = txn.clone();
 		// Claim transaction
@@ -771,6 +775,11 @@ impl DefineEventStatement {
 		opt.needs(Level::Db)?;
 		// Allowed to run?
 		opt.check(Level::Db)?;
+		// Compile any embedded functions
+		if opt.scripting.validate_on_define {
+			self.when.validate()?;
+			self.then.iter().try_for_each(Value::validate)?;
+		}
 		// Clone transaction
 		let run = txn.clone();
 		// Claim transaction
@@ -852,6 +861,10 @@ impl DefineFieldStatement {
 		opt.needs(Level::Db)?;
 		// Allowed to run?
 		opt.check(Level::Db)?;
+		// Compile any embedded functions
+		if opt.scripting.validate_on_define {
+			self.value.iter().chain(&self.assert).try_for_each(Value::validate)?;
+		}
 		// Clone transaction
 		let run = txn.clone();
 		// Claim transaction
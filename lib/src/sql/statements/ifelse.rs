@@ -28,6 +28,14 @@ impl IfelseStatement {
 		self.close.as_ref().map_or(false, |v| v.writeable())
 	}
 
+	pub(crate) fn validate(&self) -> Result<(), Error> {
+		for (cond, then) in self.exprs.iter() {
+			cond.validate()?;
+			then.validate()?;
+		}
+		self.close.as_ref().map_or(Ok(()), |v| v.validate())
+	}
+
 	pub(crate) async fn compute(
 		&self,
 		ctx: &Context<'_>,
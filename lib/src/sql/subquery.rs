@@ -53,6 +53,14 @@ impl Subquery {
 		}
 	}
 
+	pub(crate) fn validate(&self) -> Result<(), Error> {
+		match self {
+			Self::Value(v) => v.validate(),
+			Self::Ifelse(v) => v.validate(),
+			_ => Ok(()),
+		}
+	}
+
 	pub(crate) async fn compute(
 		&self,
 		ctx: &Context<'_>,
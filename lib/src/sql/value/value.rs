@@ -1126,6 +1126,23 @@ impl Value {
 		}
 	}
 
+	// Compiles the embedded functions within this value, without running
+	// them, so that a syntax error is found before they are first run.
+	// Only the values within a subquery, and not its statement, are checked.
+	pub(crate) fn validate(&self) -> Result<(), Error> {
+		match self {
+			Value::Array(v) => v.iter().try_for_each(Value::validate),
+			Value::Object(v) => v.values().try_for_each(Value::validate),
+			Value::Function(v) => v.validate(),
+			Value::Subquery(v) => v.validate(),
+			Value::Expression(v) => {
+				v.l.validate()?;
+				v.r.validate()
+			}
+			_ => Ok(()),
+		}
+	}
+
 	#[cfg_attr(feature = "parallel", async_recursion)]
 	#[cfg_attr(not(feature = "parallel"), async_recursion(?Send))]
 	pub(crate) async fn compute(
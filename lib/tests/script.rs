@@ -2,9 +2,26 @@
 
 mod parse;
 use parse::Parse;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use std::time::Instant;
+use surrealdb::sql::Datetime;
+use surrealdb::sql::Idiom;
+use surrealdb::sql::Number;
+use surrealdb::sql::Param;
+use surrealdb::sql::ScriptPermissions;
+use surrealdb::sql::Thing;
 use surrealdb::sql::Value;
+use surrealdb::Bindings;
+use surrealdb::Canceller;
 use surrealdb::Datastore;
+use surrealdb::DeterministicSeed;
 use surrealdb::Error;
+use surrealdb::LogLevel;
+use surrealdb::MemoryModules;
+use surrealdb::ScriptGlobals;
+use surrealdb::Scripting;
 use surrealdb::Session;
 
 #[tokio::test]
@@ -26,6 +43,139 @@ async fn script_function_simple() -> Result<(), Error> {
 	Ok(())
 }
 
+#[tokio::test]
+async fn script_function_arrow() -> Result<(), Error> {
+	let sql = "
+		RETURN function() { v => v * 10 };
+		RETURN function(6.6) { (v => v * 10)(...arguments) };
+		RETURN function(6.6) { return (v => v * 10)(arguments[0]); };
+		RETURN function() { [6.6, 8.4, 7.3].map(v => v * 10) };
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv()
+		.with_ns("test")
+		.with_db("test")
+		.with_scripting(Scripting::default().with_completion(true));
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 4);
+	// A bare arrow function is returned as a function, which is NONE
+	assert_eq!(res.remove(0).result?, Value::None);
+	// An arrow function is applied by calling it within the body
+	assert_eq!(res.remove(0).result?, Value::from(66));
+	assert_eq!(res.remove(0).result?, Value::from(66));
+	assert_eq!(res.remove(0).result?, Value::parse("[66, 84, 73]"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_completion() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			if (true) return 'explicit';
+			'completion';
+		};
+		RETURN function() {
+			const a = [1, 2, 3];
+			a.map(v => v * 2)
+		};
+		RETURN function() {
+			let a = 1;
+			await Promise.resolve(a + 1);
+		};
+		RETURN function() {};
+		RETURN function() {
+			let a = 1;
+		};
+		RETURN function() {
+			undefined;
+		};
+		RETURN function() {
+			const a = 1;
+			`return ${a} and ${`return ${a + 1}`}`
+		};
+		RETURN function() {
+			const re = /return \\/ }/;
+			re.source
+		};
+		RETURN function() {
+			[/}/.test('}'), `}`, '}']
+		};
+		RETURN function() {
+			outer: for (const v of [1, 2]) { if (v) break outer; }
+		};
+		RETURN function() {
+			if (true) 1; else 2;
+		};
+		RETURN function() {
+			globalThis.a = []; a.push(1)
+			a.push(2)
+			a
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv()
+		.with_ns("test")
+		.with_db("test")
+		.with_scripting(Scripting::default().with_completion(true));
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 12);
+	// An explicit return is used, when it is run
+	assert_eq!(res.remove(0).result?, Value::from("explicit"));
+	// Otherwise the value of a trailing expression is returned
+	assert_eq!(res.remove(0).result?, Value::parse("[2, 4, 6]"));
+	assert_eq!(res.remove(0).result?, Value::from(2));
+	// A body with no completion value returns NONE
+	assert_eq!(res.remove(0).result?, Value::None);
+	assert_eq!(res.remove(0).result?, Value::None);
+	assert_eq!(res.remove(0).result?, Value::None);
+	// Template literals and regexes are left as they are
+	assert_eq!(res.remove(0).result?, Value::from("return 1 and return 2"));
+	assert_eq!(res.remove(0).result?, Value::from("return \\/ }"));
+	assert_eq!(res.remove(0).result?, Value::parse("[true, '}', '}']"));
+	// A labelled statement has no completion value
+	assert_eq!(res.remove(0).result?, Value::None);
+	// Nor does an `if`, even without braces
+	assert_eq!(res.remove(0).result?, Value::None);
+	// Statements which end at a line break all run
+	assert_eq!(res.remove(0).result?, Value::parse("[1, 2]"));
+	// Without the completion value, a body which does not return returns NONE
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 12);
+	assert_eq!(res.remove(0).result?, Value::from("explicit"));
+	for _ in 1..12 {
+		assert_eq!(res.remove(0).result?, Value::None);
+	}
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_private_fields() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			class Counter {
+				#count = 0;
+				inc() { this.#count++; return this; }
+				get value() { return this.#count; }
+				static is(v) { try { v.#count; return true; } catch { return false; } }
+			}
+			const c = new Counter().inc().inc();
+			return [c.value, Counter.is(c), Counter.is({}), c['#count']];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::parse("[2, true, false, NONE]"));
+	//
+	Ok(())
+}
+
 #[tokio::test]
 async fn script_function_context() -> Result<(), Error> {
 	let sql = "
@@ -68,6 +218,67 @@ async fn script_function_context() -> Result<(), Error> {
 	Ok(())
 }
 
+#[tokio::test]
+async fn script_function_computed_field() -> Result<(), Error> {
+	let sql = "
+		DEFINE FIELD total ON product VALUE function() {
+			return this.price * 1.2;
+		};
+		DEFINE FIELD summary ON product VALUE function() {
+			const { name, tags, stock: { count } } = this;
+			return { name, tags: tags.length, count, first: tags[0] };
+		};
+		CREATE product:test SET name = 'Chair', price = 10, tags = ['wood', 'oak'], stock = { count: 3 };
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 3);
+	//
+	let tmp = res.remove(0).result;
+	assert!(tmp.is_ok());
+	//
+	let tmp = res.remove(0).result;
+	assert!(tmp.is_ok());
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse(
+		"[
+			{
+				id: product:test,
+				name: 'Chair',
+				price: 10,
+				stock: { count: 3 },
+				summary: { name: 'Chair', tags: 2, count: 3, first: 'wood' },
+				tags: ['wood', 'oak'],
+				total: 12,
+			}
+		]",
+	);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_without_record() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return this === undefined;
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::True;
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
 #[tokio::test]
 async fn script_function_arguments() -> Result<(), Error> {
 	let sql = "
@@ -103,22 +314,122 @@ async fn script_function_arguments() -> Result<(), Error> {
 }
 
 #[tokio::test]
-async fn script_function_types() -> Result<(), Error> {
+async fn script_function_datetime() -> Result<(), Error> {
 	let sql = "
-		CREATE article:test SET
-			created_at = function() {
-				return new Date('1995-12-17T03:24:00Z');
-			},
-			next_signin = function() {
-				return new Duration('1w2d6h');
-			},
-			manager = function() {
-				return new Record('user', 'joanna');
-			},
-			identifier = function() {
-				return new Uuid('03412258-988f-47cd-82db-549902cdaffe');
-			}
-		;
+		LET $date = '2022-07-03T07:18:52.841147Z';
+		LET $past = '1969-12-31T23:59:59.999500Z';
+		RETURN function($date) {
+			const [d] = arguments;
+			return [d instanceof Date, d.getTime(), d.toISOString(), d];
+		};
+		RETURN function($past) {
+			const [d] = arguments;
+			return [d.getTime(), d];
+		};
+		RETURN function() {
+			return [new Date(1656832732841), new Date('invalid')];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 5);
+	//
+	let tmp = res.remove(0).result;
+	assert!(tmp.is_ok());
+	//
+	let tmp = res.remove(0).result;
+	assert!(tmp.is_ok());
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from(true),
+		Value::from(1656832732841.0),
+		Value::from("2022-07-03T07:18:52.841Z"),
+		Value::from(Datetime::from("2022-07-03T07:18:52.841Z")),
+	]);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val =
+		Value::from(vec![Value::from(-1), Value::from(Datetime::from("1969-12-31T23:59:59.999Z"))]);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val =
+		Value::from(vec![Value::from(Datetime::from("2022-07-03T07:18:52.841Z")), Value::None]);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_record_ids() -> Result<(), Error> {
+	let sql = "
+		RETURN function(person:tobie, person:100, person:[1, 'a'], person:{ a: 1 }) {
+			return [...arguments];
+		};
+		RETURN function() {
+			return [
+				new Record('person', 'tobie'),
+				new Record('person', 100),
+				new Record('person', [1, 'a']),
+				new Record('person', { a: 1 }),
+			];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let val = Value::parse("[person:tobie, person:100, person:[1, 'a'], person:{ a: 1 }]");
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_large_integers() -> Result<(), Error> {
+	let sql = "
+		RETURN function(9007199254740993, -9223372036854775807, 9007199254740991, 4294967296) {
+			const [a, b, c, d] = arguments;
+			return [typeof a, a, typeof b, b, typeof c, c, typeof d, d, a + 1n, 2n ** 64n];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from("bigint"),
+		Value::from(9007199254740993i64),
+		Value::from("bigint"),
+		Value::from(-9223372036854775807i64),
+		Value::from("number"),
+		Value::from(9007199254740991.0),
+		Value::from("number"),
+		Value::from(4294967296.0),
+		Value::from(9007199254740994i64),
+		Value::from(Number::from("18446744073709551616")),
+	]);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_bigint_outside_integers() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return [2n ** 63n - 1n, 2n ** 63n, -(2n ** 63n), -(2n ** 63n) - 1n, 10n ** 40n];
+		};
 	";
 	let dbs = Datastore::new("memory").await?;
 	let ses = Session::for_kv().with_ns("test").with_db("test");
@@ -126,37 +437,1623 @@ async fn script_function_types() -> Result<(), Error> {
 	assert_eq!(res.len(), 1);
 	//
 	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from(i64::MAX),
+		Value::from(Number::from("9223372036854775808")),
+		Value::from(i64::MIN),
+		Value::from(Number::from("-9223372036854775809")),
+		Value::from(Number::from("10000000000000000000000000000000000000000")),
+	]);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_binary_data() -> Result<(), Error> {
+	let sql = "
+		RETURN function([0, 1, 127, 128, 255]) {
+			const [blob] = arguments;
+			const large = new Uint8Array(100000).fill(7);
+			return [
+				new Uint8Array(blob),
+				new Uint8Array(blob).buffer,
+				new Uint8Array(blob).subarray(3),
+				new Uint8Array(0),
+				new ArrayBuffer(0),
+				[large.length, large.every(v => v === 7)],
+			];
+		};
+		RETURN array::len(function() { return new Uint8Array(100000).fill(7); });
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(0).result?;
 	let val = Value::parse(
-		"[
-			{
-				id: article:test,
-				created_at: '1995-12-17T03:24:00Z',
-				next_signin: 1w2d6h,
-				manager: user:joanna,
-				identifier: '03412258-988f-47cd-82db-549902cdaffe',
-			}
-		]",
+		"[[0, 1, 127, 128, 255], [0, 1, 127, 128, 255], [128, 255], [], [], [100000, true]]",
 	);
 	assert_eq!(tmp, val);
 	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::from(100000));
+	//
 	Ok(())
 }
 
 #[tokio::test]
-async fn script_function_module_os() -> Result<(), Error> {
+async fn script_function_decimals() -> Result<(), Error> {
 	let sql = "
-		CREATE platform:test SET version = function() {
-			const { release } = await import('os');
-			return release();
+		RETURN function(0.1, 0.2, 0.30000000000000000001) {
+			const [a, b, c] = arguments;
+			return [a, b, c, a instanceof Decimal, `${c}`, a + b, new Decimal('1.10')];
+		};
+		RETURN function() {
+			return new Decimal('invalid');
 		};
 	";
 	let dbs = Datastore::new("memory").await?;
 	let ses = Session::for_kv().with_ns("test").with_db("test");
 	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
-	assert_eq!(res.len(), 1);
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from(Number::from("0.1")),
+		Value::from(Number::from("0.2")),
+		Value::from(Number::from("0.30000000000000000001")),
+		Value::from(true),
+		Value::from("0.30000000000000000001"),
+		Value::from(0.1 + 0.2),
+		Value::from(Number::from("1.10")),
+	]);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::None);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_surql() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			const x = [new Record('person', 'tobie'), new Date(1656832732841)];
+			return JSON.parse(JSON.stringify(x));
+		};
+		RETURN function() {
+			const x = [new Record('person', 'tobie'), new Date(1656832732841)];
+			const y = surql.parse(surql.stringify(x));
+			return [...y, y[0] instanceof Record, y[1] instanceof Date];
+		};
+		RETURN function() {
+			const x = [new Duration('1h'), new Decimal('0.1'), 9007199254740993n, { $surql: 'record' }];
+			return surql.parse(surql.stringify(x));
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 3);
+	//
+	let tmp = res.remove(0).result?;
+	let val =
+		Value::from(vec![Value::from("person:tobie"), Value::from("2022-07-03T07:18:52.841Z")]);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from(Thing::from(("person", "tobie"))),
+		Value::from(Datetime::from("2022-07-03T07:18:52.841Z")),
+		Value::from(true),
+		Value::from(true),
+	]);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from(surrealdb::sql::Duration::from("1h")),
+		Value::from(Number::from("0.1")),
+		Value::from(9007199254740993i64),
+		Value::parse("{ '$surql': 'record' }"),
+	]);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_uuids() -> Result<(), Error> {
+	let sql = "
+		LET $id = rand::uuid();
+		RETURN $id;
+		RETURN function($id) {
+			return arguments[0];
+		};
+		RETURN function('03412258-988f-47cd-82db-549902cdaffe') {
+			const [id] = arguments;
+			return [id instanceof Uuid, id];
+		};
+		RETURN function() {
+			return new Uuid('invalid');
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 5);
 	//
 	let tmp = res.remove(0).result;
 	assert!(tmp.is_ok());
 	//
+	let id = res.remove(0).result?;
+	assert!(matches!(id, Value::Uuid(_)));
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, id);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from(true),
+		Value::parse("'03412258-988f-47cd-82db-549902cdaffe'"),
+	]);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::from("invalid"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_geometries() -> Result<(), Error> {
+	let sql = "
+		LET $poly = function() {
+			return {
+				type: 'Polygon',
+				coordinates: [
+					[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+					[[2, 2], [4, 2], [4, 4], [2, 2]],
+				],
+			};
+		};
+		RETURN $poly;
+		RETURN function($poly, (-0.118092, 51.509865)) {
+			const [poly, point] = arguments;
+			return [poly.type, poly.coordinates.length, poly.coordinates[1][1], point.coordinates[0], poly];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 3);
+	//
+	let tmp = res.remove(0).result;
+	assert!(tmp.is_ok());
+	//
+	let poly = res.remove(0).result?;
+	assert!(matches!(poly, Value::Geometry(_)));
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(vec![
+		Value::from("Polygon"),
+		Value::from(2),
+		Value::from(vec![4, 2]),
+		Value::from(-0.118092),
+		poly,
+	]);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_none_and_null() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return null;
+		};
+		RETURN function() {
+			return undefined;
+		};
+		RETURN function() {
+			return { a: null, b: undefined, c: [null, undefined] };
+		};
+		RETURN function(NULL, NONE, { a: NULL, b: NONE }) {
+			const [a, b, c] = arguments;
+			return [a === null, b === undefined, c.a === null, 'a' in c, 'b' in c];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 4);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::Null);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::None);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("{ a: NULL, c: [NULL, NONE] }");
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("[true, true, true, true, false]");
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_durations() -> Result<(), Error> {
+	let sql = "
+		RETURN function(1h30m, 250ms, 1s500ns) {
+			const [a, b, c] = arguments;
+			return [a.secs, a.nanos, b.secs, b.nanos, c.nanos, `${a}`, `${b}`, a, b, c];
+		};
+		RETURN function() {
+			return [new Duration('1h30m'), new Duration(90, 5), new Duration()];
+		};
+		RETURN function() {
+			return ['invalid', '', '1h30', '1h 30m'].map(v => {
+				try {
+					return new Duration(v);
+				} catch (e) {
+					return `${e.name}: ${e.message}`;
+				}
+			});
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 3);
+	//
+	let tmp = res.remove(0).result?;
+	let val =
+		Value::parse("[5400, 0, 0, 250000000, 500, '1h30m', '250000000ns', 1h30m, 250ms, 1s500ns]");
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("[1h30m, 1m30s5ns, 0ns]");
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse(
+		r#"[
+			"TypeError: Invalid duration: 'invalid'",
+			"TypeError: Invalid duration: ''",
+			"TypeError: Invalid duration: '1h30'",
+			"TypeError: Invalid duration: '1h 30m'",
+		]"#,
+	);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_nested_types() -> Result<(), Error> {
+	let sql = "
+		RETURN function(person:tobie) {
+			const [who] = arguments;
+			return {
+				when: new Date(0),
+				who,
+				nested: {
+					list: [
+						new Date(1000),
+						new Record('person', 1),
+						new Duration('1s'),
+						new Decimal('0.1'),
+						{ deep: new Uuid('03412258-988f-47cd-82db-549902cdaffe') },
+					],
+				},
+			};
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse(
+		"{
+			when: '1970-01-01T00:00:00Z',
+			who: person:tobie,
+			nested: {
+				list: [
+					'1970-01-01T00:00:01Z',
+					person:1,
+					1s,
+					0.1,
+					{ deep: '03412258-988f-47cd-82db-549902cdaffe' },
+				],
+			},
+		}",
+	);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_types() -> Result<(), Error> {
+	let sql = "
+		CREATE article:test SET
+			created_at = function() {
+				return new Date('1995-12-17T03:24:00Z');
+			},
+			next_signin = function() {
+				return new Duration('1w2d6h');
+			},
+			manager = function() {
+				return new Record('user', 'joanna');
+			},
+			identifier = function() {
+				return new Uuid('03412258-988f-47cd-82db-549902cdaffe');
+			}
+		;
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse(
+		"[
+			{
+				id: article:test,
+				created_at: '1995-12-17T03:24:00Z',
+				next_signin: 1w2d6h,
+				manager: user:joanna,
+				identifier: '03412258-988f-47cd-82db-549902cdaffe',
+			}
+		]",
+	);
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_module_os() -> Result<(), Error> {
+	let sql = "
+		CREATE platform:test SET version = function() {
+			const { release } = await import('os');
+			return release();
+		};
+	";
+	let dbs = Datastore::new("memory").await?.with_script_permissions(ScriptPermissions {
+		import: true,
+		..Default::default()
+	});
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(tmp.is_ok());
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_timeout() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			while(true) {}
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let ses = ses.with_scripting(Scripting::default().with_timeout(Duration::from_millis(100)));
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptTimeout { .. })));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_timeout_datastore() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			while(true) {}
+		};
+	";
+	let scripting = Scripting::default().with_timeout(Duration::from_millis(100));
+	let dbs = Datastore::new("memory").await?.with_scripting(scripting);
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptTimeout { .. })));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_memory_limit() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			let a = [];
+			while(true) a.push(a);
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let ses = ses.with_scripting(Scripting::default().with_memory_limit(1_000_000));
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(
+		tmp,
+		Err(Error::ScriptMemoryLimit {
+			limit: 1_000_000
+		})
+	));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_memory_limit_datastore() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return 'a'.repeat(1024).length;
+		};
+		RETURN function() {
+			return 'a'.repeat(8_000_000).length;
+		};
+	";
+	let scripting = Scripting::default().with_memory_limit(4_000_000);
+	let dbs = Datastore::new("memory").await?.with_scripting(scripting);
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(1024);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(
+		tmp,
+		Err(Error::ScriptMemoryLimit {
+			limit: 4_000_000
+		})
+	));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_stack_overflow() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			const f = (n) => f(n + 1);
+			return f(0);
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(
+		tmp,
+		Err(Error::ScriptStackOverflow {
+			limit: 262_144
+		})
+	));
+	//
+	let ses = ses.with_scripting(Scripting::default().with_stack_size(65_536));
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(
+		tmp,
+		Err(Error::ScriptStackOverflow {
+			limit: 65_536
+		})
+	));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_pooled_runtimes() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			globalThis.leaked = 1;
+			Math.leaked = 2;
+			Object.prototype.leaked = 3;
+			return 1;
+		};
+		RETURN function() {
+			return [typeof leaked, typeof Math.leaked, typeof {}.leaked];
+		};
+	";
+	let dbs = Datastore::new("memory").await?.with_script_pool(1);
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(1);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("['undefined', 'undefined', 'undefined']");
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_bindings() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return [record instanceof Record, record, num * 2, arr.length, param];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let bindings = Bindings::new()
+		.bind("record", Thing::from(("person", "test")))?
+		.bind("num", 21)?
+		.bind("arr", vec![1, 2, 3])?
+		.bind("param", Param::from(Idiom::from(String::from("custom"))))?;
+	let ses = Session::for_kv().with_ns("test").with_db("test").with_bindings(bindings);
+	let var = BTreeMap::from([(String::from("custom"), Value::from("value"))]);
+	let res = &mut dbs.execute(&sql, &ses, Some(var), false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("[true, person:test, 42, 3, 'value']");
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_bindings_context() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return [ctx.user.name, ctx.now instanceof Date, ctx.now.getTime(), typeof user];
+		};
+		RETURN function() {
+			return [Object.isFrozen(ctx), Object.isFrozen(ctx.user), Object.isFrozen(arr)];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let values = BTreeMap::from([
+		(String::from("user"), Value::parse("{ name: 'Tobie' }")),
+		(String::from("now"), Value::from(Datetime::from("2022-07-03T07:18:52.841Z"))),
+	]);
+	let run = |frozen| {
+		let bindings = Bindings::new()
+			.bind_context("ctx", values.clone(), frozen)
+			.and_then(|b| b.bind("arr", vec![1, 2, 3]));
+		let dbs = &dbs;
+		async move {
+			let ses = Session::for_kv().with_ns("test").with_db("test").with_bindings(bindings?);
+			let res = dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 2);
+			Ok::<_, Error>(res.into_iter().map(|v| v.result).collect::<Vec<_>>())
+		}
+	};
+	//
+	let mut res = run(true).await?;
+	let val = Value::parse("['Tobie', true, 1656832732841, 'undefined']");
+	assert_eq!(res.remove(0)?, val);
+	assert_eq!(res.remove(0)?, Value::parse("[true, true, false]"));
+	//
+	let mut res = run(false).await?;
+	res.remove(0)?;
+	assert_eq!(res.remove(0)?, Value::parse("[false, false, false]"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_bindings_invalid() -> Result<(), Error> {
+	let res = Bindings::new().bind("not-valid", 1);
+	assert!(matches!(res, Err(Error::InvalidBinding { name }) if name == "not-valid"));
+	let res = Bindings::new().bind("return", 1);
+	assert!(matches!(res, Err(Error::InvalidBinding { .. })));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_async() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			const double = async (v) => v * 2;
+			const a = await double(2);
+			const b = await Promise.all([double(a), Promise.resolve(1)]);
+			return [a, ...b];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("[4, 8, 1]");
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_async_rejected() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			await null;
+			throw new Error('something went wrong');
+		};
+		RETURN function() {
+			return Promise.reject('nothing to see');
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptException { name, message, .. })
+		if name == "Error" && message == "something went wrong"));
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptException { name, message, stack, .. })
+		if name.is_empty() && message == "nothing to see" && stack.is_empty()));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_async_unsettled() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			await new Promise(() => {});
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(
+		matches!(tmp, Err(Error::InvalidScript { message }) if message.contains("never settled"))
+	);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_async_timeout() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			while(true) await null;
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let ses = ses.with_scripting(Scripting::default().with_timeout(Duration::from_millis(100)));
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptTimeout { .. })));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_console() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			for (let i = 0; i < 3; i++) console.log('step', i);
+			console.warn({ low: true });
+			console.error('failed');
+			return 1;
+		};
+		RETURN 2;
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(0);
+	assert_eq!(tmp.result?, Value::from(1));
+	let logs: Vec<_> = tmp.logs.iter().map(|v| (v.level, v.message.as_str())).collect();
+	assert_eq!(
+		logs,
+		vec![
+			(LogLevel::Log, "step 0"),
+			(LogLevel::Log, "step 1"),
+			(LogLevel::Log, "step 2"),
+			(LogLevel::Warn, "{ low: true }"),
+			(LogLevel::Error, "failed"),
+		]
+	);
+	//
+	let tmp = res.remove(0);
+	assert!(tmp.logs.is_empty());
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_console_limit() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			const line = 'a'.repeat(1000);
+			for (let i = 0; i < 1000; i++) console.log(line);
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0);
+	assert!(tmp.result.is_ok());
+	let size: usize = tmp.logs.iter().map(|v| v.message.len() + 1).sum();
+	assert_eq!(tmp.logs.len(), 66);
+	assert_eq!(size, 65_536);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_module_custom() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			const { double } = await import('fn::util');
+			return double(21);
+		};
+		RETURN function() {
+			const { triple } = await import('fn::other');
+			return triple(1);
+		};
+	";
+	let modules = MemoryModules::new()
+		.with_module(
+			"fn::util",
+			"import { two } from 'fn::two'; export const double = (v) => v * two;",
+		)
+		.with_module("fn::two", "export const two = 2;");
+	let dbs = Datastore::new("memory").await?.with_modules(modules).with_script_permissions(
+		ScriptPermissions {
+			import: true,
+			..Default::default()
+		},
+	);
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from(42);
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptException { .. })));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_module_disabled() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			const { double } = await import('fn::util');
+			return double(21);
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptException { .. })));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_deterministic() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return [Math.random(), Math.random()];
+		};
+		RETURN function() {
+			return [Date.now(), new Date().getTime(), new Date(0).getTime(), new Date() instanceof Date];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let run = |seed| {
+		let scripting = Scripting::default().with_deterministic(DeterministicSeed {
+			seed,
+			timestamp: 1_000_000_000_000,
+		});
+		let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+		let dbs = &dbs;
+		async move {
+			let res = &mut dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 2);
+			Ok::<_, Error>((res.remove(0).result?, res.remove(0).result?))
+		}
+	};
+	let one = run(1).await?;
+	let two = run(1).await?;
+	let other = run(2).await?;
+	assert_eq!(one, two);
+	assert_ne!(one.0, other.0);
+	//
+	let val = Value::parse("[1000000000000, 1000000000000, 0, true]");
+	assert_eq!(one.1, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_globals_default() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return [typeof eval, typeof Function, typeof Math.max, typeof JSON];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("['undefined', 'undefined', 'function', 'object']");
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_globals_allow() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return [typeof eval, typeof JSON, typeof Math, Math.max(1, 2), new Date(0).getTime()];
+		};
+	";
+	let globals = ScriptGlobals::allow(["Math", "eval"])?;
+	let dbs = Datastore::new("memory").await?.with_globals(globals).with_script_permissions(
+		ScriptPermissions {
+			globals: ["eval".to_owned()].into(),
+			..Default::default()
+		},
+	);
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("['function', 'undefined', 'object', 2, 0]");
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_globals_deny() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return [typeof eval, typeof JSON, typeof console];
+		};
+	";
+	let globals = ScriptGlobals::deny(["JSON", "console"])?;
+	let dbs = Datastore::new("memory").await?.with_globals(globals).with_script_permissions(
+		ScriptPermissions {
+			globals: ["eval".to_owned()].into(),
+			..Default::default()
+		},
+	);
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("['function', 'undefined', 'undefined']");
+	assert_eq!(tmp, val);
+	//
+	let res = ScriptGlobals::deny(["not-valid"]);
+	assert!(matches!(res, Err(Error::InvalidGlobal { name }) if name == "not-valid"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_exception_location() -> Result<(), Error> {
+	let sql = "RETURN 1;\nRETURN function() { let a = 'x\\\ny';\n\tthrow new Error('failed');\n};";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 2);
+	//
+	let tmp = res.remove(1).result;
+	assert_eq!(sql.find("\tthrow"), Some(46));
+	let err = tmp.unwrap_err();
+	assert!(err.to_string().starts_with(
+		"Problem with embedded script function. An exception occurred at script:3 (on the line at byte 46 of the query): Error: failed"
+	));
+	assert!(matches!(
+		err,
+		Error::ScriptException {
+			line: Some(3),
+			offset: Some(46),
+			..
+		}
+	));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_hashbang() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {#!/usr/bin/env node '{
+			return '#!' + 1;
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::from("#!1");
+	assert_eq!(tmp, val);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_exception_types() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			throw new TypeError('not a number');
+		};
+		RETURN function() {
+			throw 'plain';
+		};
+		RETURN function() {
+			class ValidationError extends Error {
+				constructor(message) {
+					super(message);
+					this.name = 'ValidationError';
+				}
+			}
+			function check(v) {
+				throw new ValidationError('invalid ' + v);
+			}
+			check(1);
+		};
+		RETURN function() {
+			throw { name: 'Custom', message: 'shaped' };
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(&sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 4);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(&tmp, Err(Error::ScriptException { name, message, stack, line: Some(2), .. })
+		if name == "TypeError" && message == "not a number" && stack == "    at main (script:2)\n"));
+	assert!(tmp.unwrap_err().to_string().contains("): TypeError: not a number\n"));
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(&tmp, Err(Error::ScriptException { name, message, stack, .. })
+		if name.is_empty() && message == "plain" && stack.is_empty()));
+	assert!(tmp.unwrap_err().to_string().ends_with("An exception occurred: plain"));
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptException { name, message, stack, .. })
+		if name == "ValidationError" && message == "invalid 1" && stack.contains("at check")));
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptException { name, message, .. })
+		if name == "Custom" && message == "shaped"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_freeze_inputs() -> Result<(), Error> {
+	let sql = "
+		LET $words = ['awesome', 'advanced'];
+		LET $nested = { tags: ['a'] };
+		RETURN function($words) {
+			arguments[0].push('cool');
+			return arguments[0];
+		};
+		RETURN function($nested) {
+			arguments[0].tags[0] = 'b';
+			return arguments[0];
+		};
+		RETURN function($words) {
+			return [...arguments[0], 'cool'];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let run = |freeze| {
+		let scripting = Scripting::default().with_freeze_inputs(freeze);
+		let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+		let dbs = &dbs;
+		async move {
+			let res = dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 5);
+			Ok::<_, Error>(res.into_iter().skip(2).map(|v| v.result).collect::<Vec<_>>())
+		}
+	};
+	//
+	let mut res = run(false).await?;
+	assert_eq!(res.remove(0)?, Value::parse("['awesome', 'advanced', 'cool']"));
+	assert_eq!(res.remove(0)?, Value::parse("{ tags: ['b'] }"));
+	assert_eq!(res.remove(0)?, Value::parse("['awesome', 'advanced', 'cool']"));
+	//
+	let mut res = run(true).await?;
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::ScriptException { name, .. }) if name == "TypeError"));
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::ScriptException { name, .. }) if name == "TypeError"));
+	assert_eq!(res.remove(0)?, Value::parse("['awesome', 'advanced', 'cool']"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_queries() -> Result<(), Error> {
+	let sql = "
+		CREATE person:one SET age = 10;
+		CREATE person:two SET age = 20;
+		RETURN function() {
+			return typeof surrealdb;
+		};
+		RETURN function() {
+			const res = await surrealdb.query('SELECT age FROM person WHERE age > $age', { age: 15 });
+			return res.map(v => v.age);
+		};
+		RETURN function() {
+			try {
+				await surrealdb.query('SELECT * FROM person; SELECT * FROM person');
+			} catch (e) {
+				return e.message;
+			}
+		};
+	";
+	let dbs = Datastore::new("memory").await?.with_script_permissions(ScriptPermissions {
+		query: true,
+		..Default::default()
+	});
+	let run = |queries| {
+		let scripting = Scripting::default().with_queries(queries);
+		let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+		let dbs = &dbs;
+		async move {
+			let res = dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 5);
+			Ok::<_, Error>(res.into_iter().skip(2).map(|v| v.result).collect::<Vec<_>>())
+		}
+	};
+	//
+	let mut res = run(false).await?;
+	assert_eq!(res.remove(0)?, Value::from("undefined"));
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::ScriptException { name, .. }) if name == "ReferenceError"));
+	//
+	let mut res = run(true).await?;
+	assert_eq!(res.remove(0)?, Value::from("object"));
+	assert_eq!(res.remove(0)?, Value::parse("[20]"));
+	assert_eq!(
+		res.remove(0)?,
+		Value::from("Problem with embedded script function. A script can only run a single statement at a time")
+	);
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_record_access() -> Result<(), Error> {
+	let sql = "
+		CREATE person:one SET age = 10;
+		CREATE person:two SET age = 20;
+		CREATE audit:test SET total = function() {
+			if (typeof surrealdb === 'undefined') return 0;
+			const old = await surrealdb.query('SELECT * FROM person WHERE age > 15');
+			const upd = await surrealdb.query('UPDATE person:three, person:two SET age += 1');
+			await surrealdb.query('SELECT age FROM person');
+			return old.length + upd.length;
+		};
+	";
+	let dbs = Datastore::new("memory").await?.with_script_permissions(ScriptPermissions {
+		query: true,
+		..Default::default()
+	});
+	let run = |queries, record| {
+		let scripting = Scripting::default().with_queries(queries).with_record_access(record);
+		let ses = Session::for_kv()
+			.with_ns("test")
+			.with_db(&format!("{}{}", queries, record))
+			.with_scripting(scripting);
+		let dbs = &dbs;
+		async move {
+			let mut res = dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 3);
+			assert!(res.iter().take(2).all(|v| v.records.is_empty()));
+			let res = res.remove(2);
+			Ok::<_, Error>((res.result?, res.records))
+		}
+	};
+	//
+	let (val, records) = run(true, true).await?;
+	assert_eq!(val, Value::parse("[{ id: audit:test, total: 3 }]"));
+	let ids: Vec<_> = records.into_iter().map(|v| v.to_string()).collect();
+	assert_eq!(ids, vec!["person:two", "person:three"]);
+	// Nothing is recorded unless it is specified
+	let (val, records) = run(true, false).await?;
+	assert_eq!(val, Value::parse("[{ id: audit:test, total: 3 }]"));
+	assert!(records.is_empty());
+	// Nothing is recorded when queries can not be run
+	let (val, records) = run(false, true).await?;
+	assert_eq!(val, Value::parse("[{ id: audit:test, total: 0 }]"));
+	assert!(records.is_empty());
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_cancelled() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			while (true) {}
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let scripting = Scripting::default().with_timeout(Duration::from_secs(30));
+	let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+	let canceller = Canceller::default();
+	let cancel = canceller.clone();
+	std::thread::spawn(move || {
+		std::thread::sleep(Duration::from_millis(100));
+		cancel.cancel();
+	});
+	let now = std::time::Instant::now();
+	let res = &mut dbs.execute_cancellable(sql, &ses, None, false, &canceller).await?;
+	assert!(now.elapsed() < Duration::from_secs(5));
+	assert_eq!(res.len(), 1);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(tmp, Err(Error::ScriptCancelled)));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_timing() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			let a = 0;
+			for (let i = 0; i < 100000; i++) a += i;
+			return [a, a * 2];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let run = |timing| {
+		let scripting = Scripting::default().with_timing(timing);
+		let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+		let dbs = &dbs;
+		async move {
+			let mut res = dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 1);
+			let res = res.remove(0);
+			Ok::<_, Error>((res.result?, res.timing))
+		}
+	};
+	//
+	let now = Instant::now();
+	let (val, mut timing) = run(true).await?;
+	let elapsed = now.elapsed();
+	assert_eq!(val, Value::parse("[4999950000, 9999900000]"));
+	assert_eq!(timing.len(), 1);
+	let timing = timing.remove(0);
+	assert!(!timing.cached);
+	assert_eq!(timing.offset, sql.find('{').map(|v| v + 1));
+	// Every phase is recorded
+	assert!(timing.parse > Duration::ZERO);
+	assert!(timing.compile > Duration::ZERO);
+	assert!(timing.bindings > Duration::ZERO);
+	assert!(timing.execute > Duration::ZERO);
+	assert!(timing.marshal > Duration::ZERO);
+	// Each phase is counted once within the total, which is no longer
+	// than the whole query took to run
+	let sum = timing.parse + timing.compile + timing.bindings + timing.execute + timing.marshal;
+	assert!(sum <= timing.total);
+	assert!(timing.total <= elapsed);
+	// The compiled function is used the second time it runs
+	let (_, mut timing) = run(true).await?;
+	assert!(timing.remove(0).cached);
+	// Nothing is recorded unless it is specified
+	let (_, timing) = run(false).await?;
+	assert!(timing.is_empty());
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_coverage() -> Result<(), Error> {
+	let sql = "
+		LET $x = 5;
+		RETURN function($x) {
+			const [x] = arguments;
+			if (x > 1) {
+				return 'big';
+			} else {
+				return 'small';
+			}
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let run = |x: i64, coverage| {
+		let scripting = Scripting::default().with_coverage(coverage);
+		let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+		let sql = sql.replace('5', &x.to_string());
+		let dbs = &dbs;
+		async move {
+			let mut res = dbs.execute(&sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 2);
+			let res = res.remove(1);
+			Ok::<_, Error>((res.result?, res.coverage, sql))
+		}
+	};
+	//
+	let (val, mut coverage, sql) = run(5, true).await?;
+	assert_eq!(val, Value::from("big"));
+	assert_eq!(coverage.len(), 1);
+	let coverage = coverage.remove(0);
+	assert_eq!(coverage.lines, BTreeSet::from([2, 3, 4, 6]));
+	assert_eq!(coverage.executed, BTreeSet::from([2, 3, 4]));
+	assert_eq!(coverage.query_lines(&sql), BTreeSet::from([4, 5, 6]));
+	//
+	let (val, mut coverage, sql) = run(0, true).await?;
+	assert_eq!(val, Value::from("small"));
+	let coverage = coverage.remove(0);
+	assert_eq!(coverage.executed, BTreeSet::from([2, 3, 6]));
+	assert_eq!(coverage.query_lines(&sql), BTreeSet::from([4, 5, 8]));
+	// Nothing is recorded unless it is specified
+	let (val, coverage, _) = run(5, false).await?;
+	assert_eq!(val, Value::from("big"));
+	assert!(coverage.is_empty());
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_seal_globals() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			undeclared = 1;
+			return undeclared;
+		};
+		RETURN function() {
+			globalThis.added = 1;
+			return added;
+		};
+		RETURN function() {
+			let a = 1;
+			var b = 2;
+			function c() { return a + b; }
+			Math.extra = 3;
+			return c() + Math.extra;
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let run = |seal| {
+		let scripting = Scripting::default().with_seal_globals(seal);
+		let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+		let dbs = &dbs;
+		async move {
+			let res = dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 3);
+			Ok::<_, Error>(res.into_iter().map(|v| v.result).collect::<Vec<_>>())
+		}
+	};
+	//
+	let mut res = run(false).await?;
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::ScriptException { name, .. }) if name == "ReferenceError"));
+	assert_eq!(res.remove(0)?, Value::from(1));
+	assert_eq!(res.remove(0)?, Value::from(6));
+	//
+	let mut res = run(true).await?;
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::ScriptException { name, .. }) if name == "ReferenceError"));
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::ScriptException { name, .. }) if name == "TypeError"));
+	assert_eq!(res.remove(0)?, Value::from(6));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_non_finite_numbers() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return NaN;
+		};
+		RETURN function() {
+			return Infinity;
+		};
+		RETURN function() {
+			return -Infinity;
+		};
+		RETURN function() {
+			return { a: [1, 0 / 0] };
+		};
+		RETURN function() {
+			return [1.5, -0.5];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let ses = Session::for_kv().with_ns("test").with_db("test");
+	let res = &mut dbs.execute(sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 5);
+	//
+	for name in ["NaN", "Infinity", "-Infinity", "NaN"] {
+		let tmp = res.remove(0).result.unwrap_err().to_string();
+		assert!(tmp.contains(&format!("{} can not be converted to a number", name)), "{}", tmp);
+	}
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::parse("[1.5, -0.5]"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_result_limit() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			return new Array(999).fill(1);
+		};
+		RETURN function() {
+			return new Array(1000).fill(1);
+		};
+		RETURN function() {
+			return Array.from({ length: 100 }, () => ({ a: [1, 2, 3, 4, 5, 6, 7, 8, 9] }));
+		};
+		RETURN function() {
+			return { get a() { console.log(new Array(2000).fill(1)); return 1; } };
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let scripting = Scripting::default().with_result_limit(1000);
+	let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+	let res = &mut dbs.execute(sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 4);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::from(vec![1; 999]));
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(
+		tmp,
+		Err(Error::ScriptResultTooLarge {
+			limit: 1000
+		})
+	));
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(
+		tmp,
+		Err(Error::ScriptResultTooLarge {
+			limit: 1000
+		})
+	));
+	// Values converted while the result is converted have their own limit
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::parse("{ a: 1 }"));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_generator() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			yield 1;
+			yield 2;
+		};
+		RETURN function() {
+			for (const v of ['a', 'b']) yield { v, d: new Duration('1h') };
+			return 3;
+		};
+		RETURN function() {
+			let i = 0;
+			while (true) yield i++;
+		};
+		RETURN function() {
+			return [...(function*() { yield 1; })()];
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let scripting = Scripting::default().with_result_limit(1000);
+	let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+	let res = &mut dbs.execute(sql, &ses, None, false).await?;
+	assert_eq!(res.len(), 4);
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::from(vec![1, 2]));
+	//
+	let tmp = res.remove(0).result?;
+	let val = Value::parse("[{ v: 'a', d: 1h }, { v: 'b', d: 1h }]");
+	assert_eq!(tmp, val);
+	//
+	let tmp = res.remove(0).result;
+	assert!(matches!(
+		tmp,
+		Err(Error::ScriptResultTooLarge {
+			limit: 1000
+		})
+	));
+	//
+	let tmp = res.remove(0).result?;
+	assert_eq!(tmp, Value::from(vec![1]));
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_instruction_limit() -> Result<(), Error> {
+	let sql = "
+		RETURN function() {
+			let n = 0;
+			for (let i = 0; i < 20000; i++) n++;
+			return n;
+		};
+		RETURN function() {
+			let n = 0;
+			for (let i = 0; i < 150000; i++) n++;
+			return n;
+		};
+		RETURN function() {
+			while (true) {}
+		};
+	";
+	let dbs = Datastore::new("memory").await?;
+	let scripting =
+		Scripting::default().with_timeout(Duration::from_secs(60)).with_instruction_limit(100_000);
+	let ses = Session::for_kv().with_ns("test").with_db("test").with_scripting(scripting);
+	// The same functions stay within, or exceed, the limit every time
+	for _ in 0..3 {
+		let res = &mut dbs.execute(sql, &ses, None, false).await?;
+		assert_eq!(res.len(), 3);
+		//
+		let tmp = res.remove(0).result?;
+		assert_eq!(tmp, Value::from(20000));
+		//
+		let tmp = res.remove(0).result;
+		assert!(matches!(
+			tmp,
+			Err(Error::ScriptInstructionLimit {
+				limit: 100_000
+			})
+		));
+		//
+		let tmp = res.remove(0).result;
+		assert!(matches!(
+			tmp,
+			Err(Error::ScriptInstructionLimit {
+				limit: 100_000
+			})
+		));
+	}
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_namespace_isolation() -> Result<(), Error> {
+	let set = "
+		RETURN function($secret) {
+			globalThis.secret = arguments[0];
+			Object.prototype.leaked = true;
+			Array.prototype.includes = () => true;
+			const { version } = await import('os');
+			version.leaked = true;
+			return secret;
+		};
+	";
+	let get = "
+		RETURN function() {
+			const { version } = await import('os');
+			return [typeof secret, 'leaked' in {}, [].includes(1), 'leaked' in version];
+		};
+	";
+	let dbs = Datastore::new("memory").await?.with_script_permissions(ScriptPermissions {
+		import: true,
+		..Default::default()
+	});
+	let one = Session::for_kv().with_ns("one").with_db("test");
+	let two = Session::for_kv().with_ns("two").with_db("test");
+	// Enough calls are made for every runtime in the pool to be used
+	for _ in 0..20 {
+		let vars = BTreeMap::from([(String::from("secret"), Value::from("one"))]);
+		let res = &mut dbs.execute(set, &one, Some(vars), false).await?;
+		assert_eq!(res.remove(0).result?, Value::from("one"));
+		let res = &mut dbs.execute(get, &two, None, false).await?;
+		assert_eq!(res.remove(0).result?, Value::parse("['undefined', false, false, false]"));
+	}
+	//
+	Ok(())
+}
+
+#[tokio::test]
+async fn script_function_validate_on_define() -> Result<(), Error> {
+	let sql = "
+		DEFINE FIELD total ON person VALUE function() { return 1 + ; };
+		DEFINE EVENT changed ON person WHEN true THEN function() { let = 1; };
+		DEFINE FIELD valid ON person VALUE function() { return $value * 2; };
+		INFO FOR TABLE person;
+	";
+	let dbs = Datastore::new("memory").await?;
+	let run = |validate| {
+		let scripting = Scripting::default().with_validate_on_define(validate);
+		let ses = Session::for_kv()
+			.with_ns("test")
+			.with_db(&validate.to_string())
+			.with_scripting(scripting);
+		let dbs = &dbs;
+		async move {
+			let res = dbs.execute(sql, &ses, None, false).await?;
+			assert_eq!(res.len(), 4);
+			Ok::<_, Error>(res.into_iter().map(|v| v.result).collect::<Vec<_>>())
+		}
+	};
+	// Without validation, any body whose braces balance is defined
+	let mut res = run(false).await?;
+	assert!(res.remove(0).is_ok());
+	assert!(res.remove(0).is_ok());
+	assert!(res.remove(0).is_ok());
+	let tmp = res.remove(0)?.to_string();
+	assert!(tmp.contains("total") && tmp.contains("changed"));
+	// With validation, a syntax error is reported when the body is defined
+	let mut res = run(true).await?;
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::InvalidScript { message }) if message.contains("byte 50")));
+	let tmp = res.remove(0);
+	assert!(matches!(tmp, Err(Error::InvalidScript { .. })));
+	assert!(res.remove(0).is_ok());
+	let tmp = res.remove(0)?.to_string();
+	assert!(!tmp.contains("total") && !tmp.contains("changed") && tmp.contains("valid"));
+	//
 	Ok(())
 }